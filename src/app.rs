@@ -1,30 +1,43 @@
+/// How long to wait for an event before checking timed tasks such as autosave.
+const TICK: std::time::Duration = std::time::Duration::from_secs(1);
+
 /// Main application.
 pub struct App {
     event_info: String,
     file: Option<crate::file::FileApp>,
     layout: crate::widgets::AppLayout,
+    keymap: std::rc::Rc<crate::keymap::Keymap>,
 }
 
 impl App {
-    pub fn empty(term_size: ratatui::layout::Size) -> App {
+    pub fn empty(
+        term_size: ratatui::layout::Size,
+        keymap: std::rc::Rc<crate::keymap::Keymap>,
+    ) -> App {
         App {
             event_info: String::new(),
             file: None,
             layout: crate::widgets::AppLayout::new(term_size, 8, 1),
+            keymap,
         }
     }
 
     pub fn with_file(
         term_size: ratatui::layout::Size,
         path: &std::path::Path,
+        locale: crate::locale::Locale,
+        keep_history: bool,
+        keymap: std::rc::Rc<crate::keymap::Keymap>,
+        vim: bool,
     ) -> std::io::Result<App> {
-        let mut file = crate::file::FileApp::open(path)?;
+        let mut file = crate::file::FileApp::open(path, locale, keep_history, keymap.clone(), vim)?;
         let layout = crate::widgets::AppLayout::new(term_size, 8, 1);
         file.update_content_area(layout.content);
         Ok(App {
             event_info: String::new(),
             file: Some(file),
             layout,
+            keymap,
         })
     }
 
@@ -53,15 +66,24 @@ impl App {
         terminal.draw(|frame| self.render(frame))?;
 
         loop {
-            let event = match crossterm::event::read() {
-                Ok(event) => match event {
+            let event = match crossterm::event::poll(TICK).and_then(|ready| match ready {
+                true => crossterm::event::read().map(Some),
+                false => Ok(None),
+            }) {
+                Ok(None) => None,
+                Ok(Some(event)) => match event {
                     /* Some events need catching at the app level */
-                    crossterm::event::Event::Key(crossterm::event::KeyEvent {
-                        code: crossterm::event::KeyCode::Char('q'),
-                        modifiers: crossterm::event::KeyModifiers::CONTROL,
-                        ..
-                    }) => break Ok(()),
+                    crossterm::event::Event::Key(key)
+                        if self.keymap.action(crate::keymap::Context::Global, &key)
+                            == Some(crate::keymap::Action::Quit) =>
+                    {
+                        break Ok(());
+                    }
                     crossterm::event::Event::FocusGained => {
+                        // other programs may have changed the file while we were away
+                        if let Some(file) = &mut self.file {
+                            file.check_disk(&mut self.event_info);
+                        }
                         redraw_requested = true;
                         None
                     }
@@ -83,12 +105,19 @@ impl App {
             match (&mut self.file, event) {
                 (Some(file), Some(event)) => {
                     use crate::event::EventHandler;
-                    redraw_requested |= file.handle_event(event, &mut self.event_info)
+                    redraw_requested |= file.handle_event(event, &mut self.event_info);
+                    if file.quit_requested() {
+                        break Ok(());
+                    }
                 }
                 _ => { /* either the event have been consumed, either we have no file to redirect */
                 }
             }
 
+            if let Some(file) = &mut self.file {
+                redraw_requested |= file.autosave(&mut self.event_info);
+            }
+
             if redraw_requested {
                 terminal.draw(|frame| self.render(frame))?;
                 redraw_requested = false;
@@ -1,13 +1,22 @@
 pub enum Args {
     File(std::path::PathBuf),
+    /// Locale used to detect and display dates and booleans, as `--locale=fr-FR`.
+    Locale(String),
+    /// Keep the undo history next to the file when saving, to undo after a restart.
+    KeepHistory,
 }
 
 pub fn parse_args() -> Vec<Args> {
     let mut result = Vec::new();
-    let args = std::env::args();
+    // first argument is the program path
+    let args = std::env::args().skip(1);
 
     for arg in args.into_iter() {
-        result.push(Args::File(arg.into()));
+        match arg.strip_prefix("--locale=") {
+            Some(locale) => result.push(Args::Locale(locale.to_string())),
+            None if arg == "--keep-history" => result.push(Args::KeepHistory),
+            None => result.push(Args::File(arg.into())),
+        }
     }
 
     result
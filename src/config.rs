@@ -0,0 +1,194 @@
+/// Settings of the config file, `$XDG_CONFIG_HOME/rex/config.toml` or `~/.config/rex/config.toml`,
+/// written in a subset of TOML: sections, and keys set to booleans, strings or lists of strings.
+///
+/// ```toml
+/// [editor]
+/// vim = true
+///
+/// [keys]
+/// undo = "ctrl+u"
+/// redo = ["ctrl+shift+u", "ctrl+y"]
+/// # unbound, Alt+H being used by the terminal
+/// history = []
+/// ```
+#[derive(Default)]
+pub struct Config {
+    pub keymap: crate::keymap::Keymap,
+    /// Vim-style modal navigation, set by `vim = true` in the `[editor]` section.
+    pub vim: bool,
+}
+
+impl Config {
+    pub fn path() -> Option<std::path::PathBuf> {
+        let config_home = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(config_home) if !config_home.is_empty() => std::path::PathBuf::from(config_home),
+            _ => std::path::PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+        Some(config_home.join("rex").join("config.toml"))
+    }
+
+    /// Read the config file, the defaults being used without one.
+    pub fn load() -> Result<Config, String> {
+        let Some(path) = Config::path() else {
+            return Ok(Config::default());
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(text) => Config::parse(&text).map_err(|e| format!("{}: {e}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(format!("{}: {e}", path.display())),
+        }
+    }
+
+    fn parse(text: &str) -> Result<Config, String> {
+        let mut bindings = Vec::new();
+        let mut vim = false;
+        for entry in parse_entries(text)? {
+            let line = entry.line;
+            match (entry.section.as_str(), entry.key.as_str(), entry.value) {
+                ("editor", "vim", Value::Bool(value)) => vim = value,
+                ("editor", "vim", _) => return Err(format!("line {line}: vim is true or false")),
+                ("keys", _, Value::Bool(_)) => {
+                    return Err(format!("line {line}: keys are strings or lists of strings"));
+                }
+                ("keys", _, Value::Text(key)) => bindings.push((entry.key, vec![key])),
+                ("keys", _, Value::List(keys)) => bindings.push((entry.key, keys)),
+                ("editor", key, _) => {
+                    return Err(format!("line {line}: unknown setting \"{key}\""));
+                }
+                ("", key, _) => return Err(format!("line {line}: unknown setting \"{key}\"")),
+                (section, _, _) => return Err(format!("line {line}: unknown section [{section}]")),
+            }
+        }
+        Ok(Config {
+            keymap: crate::keymap::Keymap::with_bindings(&bindings)?,
+            vim,
+        })
+    }
+}
+
+enum Value {
+    Bool(bool),
+    Text(String),
+    List(Vec<String>),
+}
+
+/// A key set in a section, and the line it is on.
+struct Entry {
+    section: String,
+    key: String,
+    value: Value,
+    line: usize,
+}
+
+fn parse_entries(text: &str) -> Result<Vec<Entry>, String> {
+    let mut entries = Vec::new();
+    let mut section = String::new();
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[') {
+            let (name, rest) = header
+                .split_once(']')
+                .ok_or_else(|| format!("line {number}: missing ] after the section name"))?;
+            expect_end(rest).map_err(|e| format!("line {number}: {e}"))?;
+            section = name.trim().to_string();
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {number}: expected key = value"))?;
+        let key = key.trim();
+        let key = match key.strip_prefix('"').and_then(|key| key.strip_suffix('"')) {
+            Some(quoted) => quoted.to_string(),
+            None if !key.is_empty()
+                && key
+                    .chars()
+                    .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_') =>
+            {
+                key.to_string()
+            }
+            None => return Err(format!("line {number}: invalid key \"{key}\"")),
+        };
+        let value = parse_value(value.trim()).map_err(|e| format!("line {number}: {e}"))?;
+        entries.push(Entry {
+            section: section.clone(),
+            key,
+            value,
+            line: number,
+        });
+    }
+    Ok(entries)
+}
+
+fn parse_value(text: &str) -> Result<Value, String> {
+    match text.strip_prefix('[') {
+        Some(mut rest) => {
+            let mut items = Vec::new();
+            loop {
+                rest = rest.trim_start();
+                if let Some(after) = rest.strip_prefix(']') {
+                    expect_end(after)?;
+                    return Ok(Value::List(items));
+                }
+                let (item, after) = parse_string(rest)?;
+                items.push(item);
+                rest = after.trim_start();
+                match (rest.strip_prefix(','), rest.starts_with(']')) {
+                    (Some(after), _) => rest = after,
+                    (None, true) => {}
+                    (None, false) => return Err("expected , or ] in the list".to_string()),
+                }
+            }
+        }
+        None if text.starts_with("true") || text.starts_with("false") => {
+            let (value, rest) = match text.strip_prefix("true") {
+                Some(rest) => (true, rest),
+                None => (false, &text["false".len()..]),
+            };
+            expect_end(rest)?;
+            Ok(Value::Bool(value))
+        }
+        None => {
+            let (text, rest) = parse_string(text)?;
+            expect_end(rest)?;
+            Ok(Value::Text(text))
+        }
+    }
+}
+
+/// Read a quoted string at the start of the text: basic with escapes in `"`, literal in `'`.
+/// Returns it with the rest of the text.
+fn parse_string(text: &str) -> Result<(String, &str), String> {
+    let mut chars = text.char_indices();
+    let quote = match chars.next() {
+        Some((_, quote @ ('"' | '\''))) => quote,
+        _ => return Err(format!("expected a quoted string at \"{text}\"")),
+    };
+    let mut string = String::new();
+    while let Some((index, ch)) = chars.next() {
+        match ch {
+            ch if ch == quote => return Ok((string, &text[index + 1..])),
+            '\\' if quote == '"' => match chars.next() {
+                Some((_, '"')) => string.push('"'),
+                Some((_, '\\')) => string.push('\\'),
+                Some((_, 'n')) => string.push('\n'),
+                Some((_, 't')) => string.push('\t'),
+                _ => return Err(format!("invalid escape in {text}")),
+            },
+            ch => string.push(ch),
+        }
+    }
+    Err(format!("missing closing {quote} in {text}"))
+}
+
+/// Check that only a comment follows a value.
+fn expect_end(rest: &str) -> Result<(), String> {
+    let rest = rest.trim_start();
+    match rest.is_empty() || rest.starts_with('#') {
+        true => Ok(()),
+        false => Err(format!("unexpected \"{rest}\"")),
+    }
+}
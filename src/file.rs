@@ -1,11 +1,28 @@
 use std::collections::BTreeMap;
 
+use crate::keymap;
+
 mod cell;
 mod change_history;
+mod clipboard;
 mod command;
+mod csv;
+mod disk;
+mod export;
+mod fill;
+pub mod format;
+mod formula;
 mod input_buffer;
+mod merges;
+mod names;
+mod navigation;
+mod notes;
+mod recovery;
+mod selection;
 mod state;
+mod validation;
 mod viewport;
+mod vim;
 
 const TOP_LEFT: ratatui::style::Color = ratatui::style::Color::Gray;
 const AXIS_STYLE: [ratatui::style::Color; 2] = [
@@ -17,36 +34,130 @@ const CELL_STYLE: [ratatui::style::Color; 2] = [
     ratatui::style::Color::Indexed(234),
 ];
 const SELECTED: ratatui::style::Color = ratatui::style::Color::Indexed(62);
+/// Active cell of a selection of many cells, the one it started from.
+const ACTIVE: ratatui::style::Color = ratatui::style::Color::Indexed(99);
+/// Cells cut and waiting to be moved, or where a dragged selection will land.
+const MOVING: ratatui::style::Color = ratatui::style::Color::Indexed(58);
+/// How often the content is written to the swap file, when it changed.
+const AUTOSAVE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
 
 /// App state for an opened file.
 pub struct FileApp {
     path: std::path::PathBuf,
+    locale: crate::locale::Locale,
     saved: bool,
     viewport: viewport::FileViewport,
     state: state::State,
     content: BTreeMap<cell::CellIndex, cell::Cell>,
+    formats: BTreeMap<cell::CellIndex, format::CellFormat>,
+    /// Conditional formatting rules, by priority: later rules override earlier ones.
+    rules: Vec<format::conditional::Rule>,
+    /// Data validation rules, every one applying to the values written in its cells.
+    validations: Vec<validation::Validation>,
+    /// Notes attached to cells, kept in a sidecar file.
+    notes: BTreeMap<cell::CellIndex, String>,
+    /// Ranges merged into a single cell, shown and edited as their top left cell.
+    merges: Vec<cell::CellRect>,
+    /// Names standing for cells or constants in formulas.
+    names: Vec<names::Name>,
+    /// Last copied or cut cells.
+    clipboard: Option<clipboard::Clipboard>,
     changes: change_history::ChangeHistory,
+    /// Whether the undo history is saved next to the file, and read back when opening it.
+    keep_history: bool,
+    /// Unsaved changes found when opening the file, until they are recovered or discarded.
+    /// Autosave is off meanwhile, not to overwrite them.
+    recovery: Option<recovery::Recovered>,
+    /// State of the history last written to the swap file, and when.
+    autosaved: (usize, std::time::Instant),
+    /// The file as last read or written, to notice changes made by other processes.
+    disk: disk::DiskState,
+    keymap: std::rc::Rc<keymap::Keymap>,
+    /// Vim-style modal layer, when enabled from the config file.
+    vim: Option<vim::Vim>,
+    /// Whether the file asked to leave the application, with `:q`.
+    quit_requested: bool,
 }
 
 impl FileApp {
-    pub fn open(path: &std::path::Path) -> std::io::Result<FileApp> {
-        let content = std::fs::read_to_string(path)?;
-        let content = Self::parse_csv(content.as_str())?;
-        Ok(FileApp {
+    pub fn open(
+        path: &std::path::Path,
+        locale: crate::locale::Locale,
+        keep_history: bool,
+        keymap: std::rc::Rc<keymap::Keymap>,
+        vim: bool,
+    ) -> std::io::Result<FileApp> {
+        let text = std::fs::read_to_string(path)?;
+        let content = Self::parse_csv(text.as_str(), &locale)?;
+        // a history saved with another content of the file can't be applied to it
+        let changes = std::fs::read_to_string(hidden_path(path, "history"))
+            .ok()
+            .filter(|_| keep_history)
+            .and_then(|history| change_history::ChangeHistory::from_text(&history, &text).ok())
+            .unwrap_or_else(change_history::ChangeHistory::new);
+        let autosaved = (changes.current(), std::time::Instant::now());
+        let disk = disk::DiskState::new(path, &text, content.clone());
+        let mut app = FileApp {
             path: path.to_owned(),
+            locale,
             saved: true,
             viewport: viewport::FileViewport::new(ratatui::layout::Rect::ZERO),
             state: state::State::Idle,
             content,
-            changes: change_history::ChangeHistory::new(),
-        })
+            formats: BTreeMap::new(),
+            rules: format::conditional::read(path),
+            validations: validation::read(path),
+            notes: notes::read(path),
+            merges: merges::read(path),
+            names: names::read(path),
+            clipboard: None,
+            changes,
+            keep_history,
+            recovery: None,
+            autosaved,
+            disk,
+            keymap,
+            vim: vim.then(vim::Vim::default),
+            quit_requested: false,
+        };
+
+        app.recovery = recovery::find(path, &app.sheet_changes()).filter(|recovered| {
+            recovered.content != app.content
+                || recovered.notes != app.notes
+                || recovered.formats != app.formats
+                || recovered.rules != app.rules
+                || recovered.validations != app.validations
+                || recovered.merges != app.merges
+                || recovered.names != app.names
+        });
+        match &app.recovery {
+            Some(recovered) => {
+                let age = recovered.time.elapsed().unwrap_or_default().as_secs();
+                app.state = state::State::Prompt(state::PromptState::new(
+                    state::PromptKind::Recover {
+                        cells: cell::CellRect::new(0, 0, 1, 1),
+                        summary: format!(
+                            "Recover unsaved changes ({}, {})? y/n: ",
+                            state::elapsed(age),
+                            disk::summary(&app.content, &recovered.content)
+                        ),
+                    },
+                    "",
+                ));
+            }
+            None => recovery::remove(path),
+        }
+        Ok(app)
     }
 
-    fn parse_csv(content: &str) -> std::io::Result<BTreeMap<cell::CellIndex, cell::Cell>> {
+    fn parse_csv(
+        content: &str,
+        locale: &crate::locale::Locale,
+    ) -> std::io::Result<BTreeMap<cell::CellIndex, cell::Cell>> {
         let mut result = BTreeMap::new();
 
-        for (y, line) in content.lines().enumerate() {
-            for (x, cell_content) in line.split(';').enumerate() {
+        for (y, row) in csv::rows(content, ';').iter().enumerate() {
+            for (x, cell_content) in row.iter().enumerate() {
                 if !cell_content.is_empty() {
                     let cell_x = match u64::try_from(x) {
                         Ok(x) => x,
@@ -57,7 +168,7 @@ impl FileApp {
                         Err(_) => continue,
                     };
                     let index = cell::CellIndex::new(cell_x, cell_y);
-                    result.insert(index, cell::Cell::parse(cell_content));
+                    result.insert(index, cell::Cell::parse(cell_content, locale));
                 }
             }
         }
@@ -65,7 +176,211 @@ impl FileApp {
         Ok(result)
     }
 
-    pub fn save(&self) -> std::io::Result<usize> {
+    pub fn save(&mut self) -> std::io::Result<usize> {
+        self.save_to(self.path.clone())
+    }
+
+    /// Write the sheet as CSV to the given path, which becomes the edited file once written.
+    fn save_to(&mut self, path: std::path::PathBuf) -> std::io::Result<usize> {
+        let content = self.to_csv();
+        let bytes_count = content.len();
+        std::fs::write(&path, &content)?;
+        self.write_hidden_files(&path)?;
+        if self.keep_history {
+            std::fs::write(
+                hidden_path(&path, "history"),
+                self.changes.to_text(&content),
+            )?;
+        }
+        if path != self.path {
+            // the unsaved changes went to the new file, their recovery with them
+            recovery::remove(&self.path);
+            self.path = path;
+        }
+        if self.recovery.is_none() {
+            recovery::remove(&self.path);
+        }
+        self.changes.mark_saved();
+        self.saved = true;
+        self.autosaved = (self.changes.current(), std::time::Instant::now());
+        self.disk = disk::DiskState::new(
+            &self.path,
+            &content,
+            Self::parse_csv(&content, &self.locale)?,
+        );
+
+        Ok(bytes_count)
+    }
+
+    /// Write what the CSV file can't hold next to it: notes, names, rules, validations and merges.
+    fn write_hidden_files(&self, path: &std::path::Path) -> std::io::Result<()> {
+        notes::write(path, &self.notes)?;
+        names::write(path, &self.names)?;
+        format::conditional::write(path, &self.rules)?;
+        validation::write(path, &self.validations)?;
+        merges::write(path, &self.merges)
+    }
+
+    /// Write the whole sheet to another file, in the format of its extension. Saved as CSV, it
+    /// becomes the edited file, while the other formats are copies that can't be opened back.
+    /// Returns the written bytes count, and whether the edited file changed.
+    pub fn save_as(&mut self, path: std::path::PathBuf) -> std::io::Result<(usize, bool)> {
+        match export::ExportFormat::from_path(&path).map_err(std::io::Error::other)? {
+            export::ExportFormat::Csv => Ok((self.save_to(path)?, true)),
+            _ => Ok((self.write_copy(&path)?, false)),
+        }
+    }
+
+    /// Write a copy of the whole sheet to another file, in the format of its extension, and keep
+    /// editing this one. A CSV copy gets its hidden files as well, so it opens as this sheet.
+    /// Returns the written bytes count.
+    pub fn write_copy(&self, path: &std::path::Path) -> std::io::Result<usize> {
+        let export_format = export::ExportFormat::from_path(path).map_err(std::io::Error::other)?;
+        if export_format == export::ExportFormat::Csv {
+            let content = self.to_csv();
+            std::fs::write(path, &content)?;
+            self.write_hidden_files(path)?;
+            return Ok(content.len());
+        }
+        let text = export::export(
+            export_format,
+            &self.content,
+            &self.formats,
+            &self.names,
+            export::used_cells(&self.content),
+            &self.locale,
+            false,
+        );
+        std::fs::write(path, &text)?;
+        Ok(text.len())
+    }
+
+    /// Write the values of the given cells to a new file, in the format of its extension.
+    /// Returns the written bytes count.
+    pub fn export_cells(
+        &self,
+        cells: cell::CellRect,
+        path: &std::path::Path,
+    ) -> std::io::Result<usize> {
+        let export_format = export::ExportFormat::from_path(path).map_err(std::io::Error::other)?;
+        let text = export::export(
+            export_format,
+            &self.content,
+            &self.formats,
+            &self.names,
+            cells,
+            &self.locale,
+            true,
+        );
+        std::fs::write(path, &text)?;
+        Ok(text.len())
+    }
+
+    /// Content of the file as it is now on disk.
+    fn read_disk(&self) -> std::io::Result<disk::DiskState> {
+        let text = std::fs::read_to_string(&self.path)?;
+        let content = Self::parse_csv(&text, &self.locale)?;
+        Ok(disk::DiskState::new(&self.path, &text, content))
+    }
+
+    /// Ask what to do when another process changed the file since we last read or wrote it.
+    /// Returns whether it changed, the question being asked only when no other one is.
+    pub fn check_disk(&mut self, info: &mut String) -> bool {
+        if !matches!(self.state, state::State::Idle) || !self.disk.changed(&self.path) {
+            return false;
+        }
+        let theirs = match self.read_disk() {
+            Ok(theirs) => theirs,
+            Err(e) => {
+                *info = format!("Failed to read {:?}: {e}", self.path);
+                return true;
+            }
+        };
+        // only touched, or rewritten with the same content
+        if theirs.content == self.disk.content {
+            self.disk = theirs;
+            return false;
+        }
+        self.state = state::State::Prompt(state::PromptState::new(
+            state::PromptKind::ExternalChange {
+                cells: self
+                    .viewport
+                    .selected_cells()
+                    .unwrap_or(cell::CellRect::new(0, 0, 1, 1)),
+                summary: format!(
+                    "File changed on disk ({}): r(eload), k(eep mine), m(erge)? ",
+                    disk::summary(&self.disk.content, &theirs.content)
+                ),
+            },
+            "",
+        ));
+        *info = format!("{:?} was changed by another program", self.path);
+        true
+    }
+
+    /// Write the content to the swap file if it changed since the last autosave, and it is time.
+    /// Returns whether the info changed, to be redrawn.
+    pub fn autosave(&mut self, info: &mut String) -> bool {
+        let (state, time) = self.autosaved;
+        if self.recovery.is_some()
+            || state == self.changes.current()
+            || time.elapsed() < AUTOSAVE_INTERVAL
+        {
+            return false;
+        }
+        self.autosaved = (self.changes.current(), std::time::Instant::now());
+        match recovery::write_swap(&self.path, &self.sheet_changes()) {
+            Ok(()) => false,
+            Err(e) => {
+                *info = format!("Failed to autosave: {e}");
+                true
+            }
+        }
+    }
+
+    /// Changes building the whole sheet from an empty one, as autosaved.
+    fn sheet_changes(&self) -> Vec<change_history::Change> {
+        use change_history::Change;
+
+        let mut changes = Vec::new();
+        for (index, cell) in &self.content {
+            changes.push(Change::cell(*index, None, Some(cell.clone())));
+        }
+        for (index, format) in &self.formats {
+            changes.push(Change::Format {
+                index: *index,
+                previous_value: None,
+                new_value: Some(format.clone()),
+            });
+        }
+        for (index, note) in &self.notes {
+            changes.push(Change::Note {
+                index: *index,
+                previous_value: None,
+                new_value: Some(note.clone()),
+            });
+        }
+        changes.push(Change::Rules {
+            previous_value: Vec::new(),
+            new_value: self.rules.clone(),
+        });
+        changes.push(Change::Validations {
+            previous_value: Vec::new(),
+            new_value: self.validations.clone(),
+        });
+        changes.push(Change::Merges {
+            previous_value: Vec::new(),
+            new_value: self.merges.clone(),
+        });
+        changes.push(Change::Names {
+            previous_value: Vec::new(),
+            new_value: self.names.clone(),
+        });
+        changes
+    }
+
+    /// Content as CSV, as saved to the file.
+    fn to_csv(&self) -> String {
         let mut content = String::new();
 
         let mut last_index = cell::CellIndex::new(0, 0);
@@ -77,14 +392,13 @@ impl FileApp {
             for _empty_col in last_index.x..index.x {
                 content.push(';');
             }
-            cell.save(&mut content);
+            match self.disk.field(*index, cell) {
+                Some(field) => content.push_str(&csv::quote(field, ';')),
+                None => cell.save(&mut content),
+            }
             last_index = *index;
         }
-
-        let bytes_count = content.bytes().len();
-        std::fs::write(&self.path, &content)?;
-
-        Ok(bytes_count)
+        content
     }
 
     pub fn update_content_area(&mut self, content_area: ratatui::layout::Rect) {
@@ -106,55 +420,161 @@ impl FileApp {
         let top_left_block = ratatui::widgets::Block::new().bg(TOP_LEFT);
         frame.render_widget(top_left_block, top_left_area);
 
-        let start_cell_x = self.viewport.top_left.x;
-        let end_cell_x = start_cell_x + u64::from(area.width / self.viewport.cell_size.width);
         let x_axis_rect = Rect::new(
             area.x.saturating_add(self.viewport.cell_size.width),
             area.y,
             area.width.saturating_sub(self.viewport.cell_size.width),
             self.viewport.cell_size.height,
         );
-        self.render_x_axis(start_cell_x..end_cell_x, frame, x_axis_rect);
+        self.render_x_axis(self.viewport.visible_columns(), frame, x_axis_rect);
 
-        let start_cell_y = self.viewport.top_left.y;
-        let end_cell_y = start_cell_y + u64::from(area.height.saturating_sub(1));
         let y_axis_rect = Rect::new(
             area.x,
             area.y.saturating_add(self.viewport.cell_size.height),
             self.viewport.cell_size.width,
             area.height.saturating_sub(self.viewport.cell_size.height),
         );
-        self.render_y_axis(start_cell_y..end_cell_y, frame, y_axis_rect);
-
-        for cell_y in start_cell_y..end_cell_y {
-            for cell_x in start_cell_x..end_cell_x {
-                let cells = cell::CellRect::new(cell_x, cell_y, 1, 1);
-                let cell_area = self.viewport.cells_pos_to_screen_pos(cells);
+        self.render_y_axis(self.viewport.visible_rows(), frame, y_axis_rect);
 
+        let evaluator = formula::Evaluator::new(&self.content, &self.names);
+        // references of an edited formula are shown over their cells
+        let references = match &self.state {
+            state::State::Editing(editor) => editor.references(),
+            _ => Vec::new(),
+        };
+        let rule_stats = self
+            .rules
+            .iter()
+            .map(|rule| rule.stats(&evaluator))
+            .collect::<Vec<_>>();
+        // merged cells are drawn once over their whole area, even when their top left cell is
+        // scrolled away
+        for cell_y in self.viewport.visible_rows() {
+            for cell_x in self.viewport.visible_columns() {
                 let cell_index = cell::CellIndex::new(cell_x, cell_y);
-                let bg_style = if self.viewport.is_selected(cell_index) {
-                    SELECTED
-                } else {
-                    CELL_STYLE[cell_index.alternate_color_index()]
-                };
-                frame.render_widget(ratatui::widgets::Block::new().bg(bg_style), cell_area);
-
-                let cell = self.content.get(&cell_index);
-                if let Some(cell) = cell {
-                    cell.render(frame, cell_area);
+                if self.merge_at(cell_index).is_none() {
+                    let cells = cell::CellRect::new(cell_x, cell_y, 1, 1);
+                    self.render_cell(cells, &evaluator, &rule_stats, &references, frame);
                 }
             }
         }
+        for merge in &self.merges {
+            self.render_cell(*merge, &evaluator, &rule_stats, &references, frame);
+        }
+
+        // the note of a selected cell shows next to it while browsing
+        if let state::State::Idle = self.state
+            && let Some(cells) = self.viewport.selected_cells()
+            && (cells.count() == 1 || self.merges.contains(&cells))
+            && let Some(note) = self.notes.get(&cell::CellIndex::new(cells.x, cells.y))
+        {
+            notes::render_popup(
+                note,
+                self.viewport.cells_pos_to_screen_pos(cells),
+                self.viewport.grid_area(),
+                frame,
+            );
+        }
 
         match &self.state {
             state::State::Idle => {}
             state::State::Editing(editor) => editor.render(&self.viewport, frame),
+            state::State::Prompt(prompt) => prompt.render(&self.viewport, frame),
+            state::State::Rules(rules) => rules.render(&self.viewport, frame),
+            state::State::Validations(validations) => validations.render(&self.viewport, frame),
+            state::State::Names(names) => names.render(&self.viewport, frame),
+            state::State::History(history) => history.render(&self.viewport, frame),
+        }
+    }
+
+    /// Render a cell, or a merged one from its top left cell, over the area of its cells.
+    fn render_cell(
+        &self,
+        cells: cell::CellRect,
+        evaluator: &formula::Evaluator,
+        rule_stats: &[format::conditional::RuleStats],
+        references: &[(cell::CellRect, ratatui::style::Color)],
+        frame: &mut ratatui::Frame,
+    ) {
+        use ratatui::style::Stylize;
+
+        let cell_area = self.viewport.cells_pos_to_screen_pos(cells);
+        if cell_area.is_empty() {
+            return;
+        }
+        let cell_index = cell::CellIndex::new(cells.x, cells.y);
+        let value = evaluator.cell_value(cell_index);
+        let mut format = self.formats.get(&cell_index).cloned();
+        let mut data_bar = None;
+        for (rule, stats) in self.rules.iter().zip(rule_stats) {
+            match rule.effect(cell_index, &value, stats, evaluator) {
+                Some(format::conditional::Effect::Style(style)) => {
+                    style.apply(format.get_or_insert_default())
+                }
+                Some(format::conditional::Effect::DataBar { ratio, color }) => {
+                    data_bar = Some((ratio, color))
+                }
+                None => {}
+            }
+        }
+        let format = format.as_ref();
+        let moving = self
+            .clipboard
+            .iter()
+            .filter(|clipboard| clipboard.cut)
+            .map(|clipboard| &clipboard.source)
+            .chain(self.viewport.drag_target().as_ref())
+            .any(|cells| cells.contains(cell_index));
+        let reference = references
+            .iter()
+            .find(|(cells, _)| cells.contains(cell_index));
+        let bg_style = if let Some((_, color)) = reference {
+            *color
+        } else if self.viewport.is_active(cell_index)
+            && self.viewport.selected_cells() != Some(cells)
+        {
+            ACTIVE
+        } else if self.viewport.is_selected(cell_index) {
+            SELECTED
+        } else if moving {
+            MOVING
+        } else {
+            format
+                .and_then(|format| format.background)
+                .unwrap_or(CELL_STYLE[cell_index.alternate_color_index()])
+        };
+        frame.render_widget(ratatui::widgets::Block::new().bg(bg_style), cell_area);
+
+        let below_top_border = self
+            .formats
+            .get(&cell::CellIndex::new(
+                cells.x,
+                cells.y.saturating_add(cells.height),
+            ))
+            .is_some_and(|below| below.borders.contains(ratatui::widgets::Borders::TOP));
+        let borders = format.map_or(ratatui::widgets::Borders::NONE, |format| format.borders);
+        let text_area = format::render_borders(
+            borders,
+            borders.contains(ratatui::widgets::Borders::BOTTOM) || below_top_border,
+            frame,
+            cell_area,
+        );
+
+        let cell = self.content.get(&cell_index);
+        if let Some(cell) = cell {
+            cell.render(&value, format, &self.locale, frame, text_area);
+        }
+        if let Some((ratio, color)) = data_bar {
+            format::conditional::render_data_bar(ratio, color, frame, text_area);
+        }
+        if self.notes.contains_key(&cell_index) {
+            notes::render_indicator(frame, cell_area);
         }
     }
 
     fn render_x_axis(
         &self,
-        cells: std::ops::Range<u64>,
+        cells: impl Iterator<Item = u64>,
         frame: &mut ratatui::Frame,
         axis_area: ratatui::layout::Rect,
     ) {
@@ -171,7 +591,7 @@ impl FileApp {
                 self.viewport.cell_size.width,
                 self.viewport.cell_size.height,
             ));
-            let bg_style = if self.viewport.is_selected_x(u64::from(cell_index)) {
+            let bg_style = if self.viewport.is_selected_x(cell_index) {
                 SELECTED
             } else {
                 AXIS_STYLE[usize::try_from(cell_index % 2).unwrap()]
@@ -187,7 +607,7 @@ impl FileApp {
 
     fn render_y_axis(
         &self,
-        cells: std::ops::Range<u64>,
+        cells: impl Iterator<Item = u64>,
         frame: &mut ratatui::Frame,
         axis_area: ratatui::layout::Rect,
     ) {
@@ -204,7 +624,7 @@ impl FileApp {
                 self.viewport.cell_size.width,
                 self.viewport.cell_size.height,
             ));
-            let bg_style = if self.viewport.is_selected_y(u64::from(cell_index)) {
+            let bg_style = if self.viewport.is_selected_y(cell_index) {
                 SELECTED
             } else {
                 AXIS_STYLE[usize::try_from(cell_index % 2).unwrap()]
@@ -219,6 +639,436 @@ impl FileApp {
     }
 }
 
+impl FileApp {
+    /// Command for an Alt formatting shortcut on the selection.
+    /// Toggles are based on the format of the top left selected cell.
+    fn format_shortcut(
+        &mut self,
+        action: keymap::Action,
+        cells: cell::CellRect,
+        info: &mut String,
+    ) -> command::Command {
+        use format::FormatChange;
+        use keymap::Action;
+        use ratatui::layout::Alignment;
+
+        let current = self
+            .formats
+            .get(&cell::CellIndex::new(cells.x, cells.y))
+            .cloned()
+            .unwrap_or_default();
+        let toggle_alignment = |alignment| {
+            FormatChange::Alignment((current.alignment != Some(alignment)).then_some(alignment))
+        };
+        let (kind, initial_value) = match action {
+            Action::Bold => return format_cells(cells, FormatChange::Bold(!current.bold)),
+            Action::Italic => return format_cells(cells, FormatChange::Italic(!current.italic)),
+            Action::Underline => {
+                return format_cells(cells, FormatChange::Underline(!current.underline));
+            }
+            Action::AlignLeft => return format_cells(cells, toggle_alignment(Alignment::Left)),
+            Action::AlignCenter => {
+                return format_cells(cells, toggle_alignment(Alignment::Center));
+            }
+            Action::AlignRight => return format_cells(cells, toggle_alignment(Alignment::Right)),
+            Action::TextColor => (
+                state::PromptKind::Foreground { cells },
+                current.foreground.map(|color| color.to_string()),
+            ),
+            Action::FillColor => (
+                state::PromptKind::Background { cells },
+                current.background.map(|color| color.to_string()),
+            ),
+            Action::ConditionalFormatting => {
+                self.state = state::State::Rules(state::RulesState::new(cells, self.rules.clone()));
+                *info = "Conditional formatting rules".to_string();
+                return command::Command::RedrawRequest;
+            }
+            Action::Borders => (
+                state::PromptKind::Borders { cells },
+                Some(format::borders_to_string(current.borders)),
+            ),
+            _ => (
+                state::PromptKind::NumberFormat { cells },
+                current
+                    .number
+                    .map(|number_format| number_format.code().to_string()),
+            ),
+        };
+        self.state = state::State::Prompt(state::PromptState::new(
+            kind,
+            &initial_value.unwrap_or_default(),
+        ));
+        *info = format!("Format of {cells}");
+        command::Command::RedrawRequest
+    }
+
+    /// Write the file, unless another program changed it since we last read or wrote it, asking
+    /// what to do then. Returns whether the file was saved.
+    fn save_checked(&mut self, info: &mut String) -> bool {
+        if self.check_disk(info) {
+            return false;
+        }
+        match self.save() {
+            Ok(bytes) => {
+                *info = format!("Saved {bytes} bytes to {:?}", self.path);
+                true
+            }
+            Err(e) => {
+                *info = format!("Failed to save to {:?}: {e}", self.path);
+                false
+            }
+        }
+    }
+
+    /// Whether the application should be left, as asked with `:q`.
+    pub fn quit_requested(&self) -> bool {
+        self.quit_requested
+    }
+
+    /// Merged cell holding the given one.
+    fn merge_at(&self, index: cell::CellIndex) -> Option<cell::CellRect> {
+        self.merges
+            .iter()
+            .find(|merge| merge.contains(index))
+            .copied()
+    }
+
+    /// Open the editor on the cells, starting with the given text. A merged cell is edited
+    /// through its top left cell.
+    fn start_editing(
+        &mut self,
+        cells: cell::CellRect,
+        text: &str,
+        info: &mut String,
+    ) -> command::Command {
+        let top_left = cell::CellIndex::new(cells.x, cells.y);
+        let cells = match self.merges.contains(&cells) {
+            true => cell::CellRect::new(cells.x, cells.y, 1, 1),
+            false => cells,
+        };
+        let choices = self
+            .validations
+            .iter()
+            .filter(|validation| validation.cells.contains(top_left))
+            .find_map(|validation| validation.choices())
+            .map(<[String]>::to_vec)
+            .unwrap_or_default();
+        self.state = state::State::Editing(state::EditingState::new(
+            cells,
+            text,
+            self.locale,
+            self.keymap.clone(),
+            choices,
+        ));
+        *info = format!("Editing {cells}");
+        command::Command::RedrawRequest
+    }
+
+    /// Handle an event while no editor or panel is open.
+    fn handle_idle_event(
+        &mut self,
+        event: crossterm::event::Event,
+        info: &mut String,
+    ) -> Option<command::Command> {
+        use crate::event::EventHandler;
+        use crossterm::event::Event;
+        use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseEventKind};
+
+        let mouse_pos = |mouse: &crossterm::event::MouseEvent| {
+            ratatui::layout::Position::new(mouse.column, mouse.row)
+        };
+        // vim mode takes the typed characters, and Esc to leave a pending command
+        if let (Some(vim), Event::Key(key)) = (&mut self.vim, &event)
+            && key.kind == KeyEventKind::Press
+        {
+            match key.code {
+                KeyCode::Char(ch)
+                    if !key
+                        .modifiers
+                        .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+                {
+                    return self.handle_vim_key(ch, info);
+                }
+                KeyCode::Esc if vim.reset() => {
+                    info.clear();
+                    return Some(command::Command::RedrawRequest);
+                }
+                _ => {}
+            }
+        }
+
+        let action = match &event {
+            Event::Key(key) => self.keymap.action(keymap::Context::Sheet, key),
+            _ => None,
+        };
+        match (action, event, self.viewport.selected_cells()) {
+            (Some(action), _, _) => {
+                if let Some(vim) = &mut self.vim {
+                    vim.reset();
+                }
+                self.handle_action(action, info)
+            }
+            (None, Event::Paste(paste_buffer), Some(cells)) => Some(command::Command::PasteCells {
+                cells,
+                paste_buffer,
+            }),
+            // when cells are selected and we press any writing chars, enter editing
+            (
+                None,
+                Event::Key(KeyEvent {
+                    kind: KeyEventKind::Press,
+                    code: KeyCode::Char(opening_char),
+                    modifiers,
+                    ..
+                }),
+                Some(cells),
+            ) if !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => {
+                let mut buffer = [0u8; 4];
+                let opening_chars = opening_char.encode_utf8(&mut buffer);
+                Some(self.start_editing(cells, opening_chars, info))
+            }
+            // clicking the axis headers selects whole rows or columns
+            (None, Event::Mouse(mouse), _)
+                if matches!(mouse.kind, MouseEventKind::Down(_))
+                    && self
+                        .viewport
+                        .screen_pos_to_header(mouse_pos(&mouse))
+                        .is_some() =>
+            {
+                let (axis, line) = self.viewport.screen_pos_to_header(mouse_pos(&mouse))?;
+                self.select_line(axis, line, mouse.modifiers, info)
+            }
+            // Lastly, we can redirect the event to the viewport control
+            (None, other, _) => self.viewport.handle_event(other, info),
+        }
+    }
+
+    /// Do what the key bound to the action asks for, in the sheet.
+    fn handle_action(
+        &mut self,
+        action: keymap::Action,
+        info: &mut String,
+    ) -> Option<command::Command> {
+        use keymap::Action;
+
+        let selection = self.viewport.selected_cells();
+        // the other actions only use the current range, those needing a single one refuse more
+        if let Some(ranges) = self
+            .viewport
+            .selection
+            .as_ref()
+            .filter(|selection| !selection.others.is_empty())
+            && matches!(
+                action,
+                Action::Copy
+                    | Action::Cut
+                    | Action::Paste
+                    | Action::PasteSpecial
+                    | Action::FillDown
+                    | Action::FillRight
+                    | Action::FillSeriesDown
+                    | Action::FillSeriesRight
+                    | Action::MergeCells
+                    | Action::ExportSelection
+                    | Action::InsertRows
+                    | Action::DeleteRows
+                    | Action::InsertColumns
+                    | Action::DeleteColumns
+            )
+        {
+            *info = format!("{} needs a single range, not {ranges}", action.name());
+            return Some(command::Command::RedrawRequest);
+        }
+        match action {
+            Action::Save => Some(command::Command::Save),
+            Action::SaveAs => {
+                self.state = state::State::Prompt(state::PromptState::new(
+                    state::PromptKind::SaveAs {
+                        cells: selection.unwrap_or(cell::CellRect::new(0, 0, 1, 1)),
+                    },
+                    &self.path.to_string_lossy(),
+                ));
+                *info = "Save as .csv, or export the sheet as .tsv, .md or .json".to_string();
+                Some(command::Command::RedrawRequest)
+            }
+            Action::ExportSelection => {
+                let cells = selection?;
+                let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+                let path = self.path.with_file_name(format!("{stem}-selection.csv"));
+                self.state = state::State::Prompt(state::PromptState::new(
+                    state::PromptKind::ExportCells { cells },
+                    &path.to_string_lossy(),
+                ));
+                *info = format!("Export the values of {cells} as .csv, .tsv, .md or .json");
+                Some(command::Command::RedrawRequest)
+            }
+            Action::Copy => Some(command::Command::CopyCells { cells: selection? }),
+            Action::Cut => Some(command::Command::CutCells { cells: selection? }),
+            Action::Paste => Some(command::Command::PasteClipboard {
+                cells: selection?,
+                options: clipboard::PasteOptions::default(),
+            }),
+            Action::PasteSpecial => {
+                self.state = state::State::Prompt(state::PromptState::new(
+                    state::PromptKind::PasteSpecial { cells: selection? },
+                    "",
+                ));
+                *info = "Paste (v)alues (f)ormulas f(o)rmats (t)ranspose skip (b)lanks + - * /"
+                    .to_string();
+                Some(command::Command::RedrawRequest)
+            }
+            // fill copying the first cells, or continuing their series
+            Action::FillDown
+            | Action::FillRight
+            | Action::FillSeriesDown
+            | Action::FillSeriesRight => Some(command::Command::FillCells {
+                cells: selection?,
+                direction: match action {
+                    Action::FillDown | Action::FillSeriesDown => fill::FillDirection::Down,
+                    _ => fill::FillDirection::Right,
+                },
+                series: matches!(action, Action::FillSeriesDown | Action::FillSeriesRight),
+            }),
+            Action::Undo => Some(command::Command::Undo),
+            Action::Redo => Some(command::Command::Redo),
+            Action::History => {
+                self.state = state::State::History(state::HistoryState::new(
+                    selection?,
+                    self.changes.entries(),
+                    self.changes.current(),
+                ));
+                *info = "Undo history".to_string();
+                Some(command::Command::RedrawRequest)
+            }
+            Action::InsertRows
+            | Action::DeleteRows
+            | Action::InsertColumns
+            | Action::DeleteColumns => {
+                let cells = selection?;
+                let (axis, at, count) = match action {
+                    Action::InsertRows | Action::DeleteRows => {
+                        (cell::Axis::Rows, cells.y, cells.height)
+                    }
+                    _ => (cell::Axis::Columns, cells.x, cells.width),
+                };
+                Some(command::Command::ShiftLines {
+                    shift: cell::LineShift {
+                        axis,
+                        at,
+                        count,
+                        inserted: matches!(action, Action::InsertRows | Action::InsertColumns),
+                    },
+                })
+            }
+            Action::ClearFormat => Some(command::Command::FormatCells {
+                cells: selection?,
+                change: format::FormatChange::Clear,
+            }),
+            Action::Bold
+            | Action::Italic
+            | Action::Underline
+            | Action::AlignLeft
+            | Action::AlignCenter
+            | Action::AlignRight
+            | Action::TextColor
+            | Action::FillColor
+            | Action::Borders
+            | Action::NumberFormat
+            | Action::ConditionalFormatting => Some(self.format_shortcut(action, selection?, info)),
+            Action::DataValidation => {
+                self.state = state::State::Validations(state::RulesState::new(
+                    selection?,
+                    self.validations.clone(),
+                ));
+                *info = "Data validation rules".to_string();
+                Some(command::Command::RedrawRequest)
+            }
+            Action::MergeCells => Some(command::Command::MergeCells { cells: selection? }),
+            Action::NameManager => {
+                self.state = state::State::Names(state::RulesState::new(
+                    selection.unwrap_or(cell::CellRect::new(0, 0, 1, 1)),
+                    self.names.clone(),
+                ));
+                *info = "Names of cells and constants".to_string();
+                Some(command::Command::RedrawRequest)
+            }
+            Action::GoToCells => {
+                self.state = state::State::Prompt(state::PromptState::new(
+                    state::PromptKind::GoTo {
+                        cells: selection.unwrap_or(cell::CellRect::new(0, 0, 1, 1)),
+                    },
+                    "",
+                ));
+                *info = "Go to cells, as A1:B2, or to a name".to_string();
+                Some(command::Command::RedrawRequest)
+            }
+            Action::EditNote => {
+                let cells = selection?;
+                let note = self.notes.get(&cell::CellIndex::new(cells.x, cells.y));
+                self.state = state::State::Prompt(state::PromptState::new(
+                    state::PromptKind::Note { cells },
+                    note.map_or("", String::as_str),
+                ));
+                *info = "Note of the cell, empty to delete it".to_string();
+                Some(command::Command::RedrawRequest)
+            }
+            Action::Edit if selection?.count() == 1 || self.merges.contains(&selection?) => {
+                let cells = selection?;
+                let content = self
+                    .content
+                    .get(&cell::CellIndex::new(cells.x, cells.y))
+                    .map(|cell| cell.to_string())
+                    .unwrap_or(String::with_capacity(0));
+                Some(self.start_editing(cells, &content, info))
+            }
+            // clearing and formatting act on every range of the selection
+            Action::ClearCells => Some(command::Command::DeleteCells {
+                cells: selection?,
+                next_selection: command::SelectionDirection::Stay,
+            }),
+            // the viewport moves the selection
+            Action::Edit
+            | Action::Next
+            | Action::Deselect
+            | Action::Move(_)
+            | Action::Extend(_)
+            | Action::FreezePanes => self.viewport.handle_action(action, &self.merges, info),
+            Action::GoTo(target) => self.go_to(target, false, info),
+            Action::ExtendTo(target) => self.go_to(target, true, info),
+            // handled by the app, or by the editor
+            Action::Quit
+            | Action::Confirm
+            | Action::ConfirmNext
+            | Action::Cancel
+            | Action::Newline => None,
+        }
+    }
+}
+
+/// Where what a CSV file can't hold is kept, as its notes or its undo history: a hidden file next
+/// to it, named after it with the given extension.
+fn hidden_path(path: &std::path::Path, extension: &str) -> std::path::PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{name}.{extension}"))
+}
+
+/// Write the hidden file of the given extension next to the file, removing it when empty.
+fn write_hidden(path: &std::path::Path, extension: &str, text: &str) -> std::io::Result<()> {
+    let hidden = hidden_path(path, extension);
+    match text.is_empty() {
+        true => match std::fs::remove_file(hidden) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        },
+        false => std::fs::write(hidden, text),
+    }
+}
+
+fn format_cells(cells: cell::CellRect, change: format::FormatChange) -> command::Command {
+    command::Command::FormatCells { cells, change }
+}
+
 impl crate::event::EventHandler for FileApp {
     /// If we request the redraw or not after an event
     type EventResponse = bool;
@@ -227,119 +1077,81 @@ impl crate::event::EventHandler for FileApp {
         event: crossterm::event::Event,
         info: &mut String,
     ) -> Self::EventResponse {
-        use crossterm::event::Event;
-        use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
-
         let mut redraw_requested = false;
 
         let command_result = match &mut self.state {
-            state::State::Idle => match (event, self.viewport.selection) {
-                (
-                    Event::Key(KeyEvent {
-                        kind: KeyEventKind::Press,
-                        code: KeyCode::Char('s'),
-                        modifiers: KeyModifiers::CONTROL,
-                        ..
-                    }),
-                    _,
-                ) => {
-                    match self.save() {
-                        Ok(bytes) => *info = format!("Saved {bytes} bytes to {:?}", self.path),
-                        Err(e) => *info = format!("Failed to save to {:?}: {e}", self.path),
+            state::State::Idle => self.handle_idle_event(event, info),
+            state::State::Editing(editor) => match event {
+                crossterm::event::Event::Mouse(mouse) => editor
+                    .handle_mouse(mouse, &self.viewport)
+                    .then_some(command::Command::RedrawRequest),
+                event => match editor.handle_event(event, info) {
+                    Some(response) => {
+                        let rejected = match &response.command {
+                            command::Command::WriteCells { cells, content, .. } => self
+                                .validate(
+                                    &cells
+                                        .indices()
+                                        .map(|index| (index, Some(content.clone())))
+                                        .collect(),
+                                )
+                                .err(),
+                            _ => None,
+                        };
+                        match rejected {
+                            // rejected values stay in the editor, to be corrected
+                            Some(reason) => {
+                                *info = format!("Rejected: {reason}");
+                                Some(command::Command::RedrawRequest)
+                            }
+                            None => {
+                                if response.exit {
+                                    self.state = state::State::Idle;
+                                }
+                                Some(response.command)
+                            }
+                        }
                     }
-                    Some(command::Command::RedrawRequest)
+                    None => None,
+                },
+            },
+            state::State::Prompt(prompt) => match prompt.handle_event(event, info) {
+                Some(response) => {
+                    if response.exit {
+                        self.state = state::State::Idle;
+                    }
+                    Some(response.command)
                 }
-                (
-                    Event::Key(KeyEvent {
-                        kind: KeyEventKind::Press,
-                        code: KeyCode::Char('c'),
-                        modifiers: KeyModifiers::CONTROL,
-                        ..
-                    }),
-                    Some(cells),
-                ) => Some(command::Command::CopyCells { cells }),
-                (Event::Paste(paste_buffer), Some(cells)) => Some(command::Command::PasteCells {
-                    cells,
-                    paste_buffer,
-                }),
-                (
-                    Event::Key(KeyEvent {
-                        kind: KeyEventKind::Press,
-                        code: KeyCode::Char('z'),
-                        modifiers: KeyModifiers::CONTROL,
-                        ..
-                    }),
-                    _,
-                ) => Some(command::Command::Undo),
-                (
-                    Event::Key(KeyEvent {
-                        kind: KeyEventKind::Press,
-                        code: KeyCode::Char('y'),
-                        modifiers: KeyModifiers::CONTROL,
-                        ..
-                    }),
-                    _,
-                ) => Some(command::Command::Redo),
-                // when cells are selected and we press any writing chars, enter editing
-                (
-                    Event::Key(KeyEvent {
-                        kind: KeyEventKind::Press,
-                        code: KeyCode::Char(opening_char),
-                        modifiers,
-                        ..
-                    }),
-                    Some(cells),
-                ) => {
-                    if !modifiers.contains(KeyModifiers::CONTROL) {
-                        let mut buffer = [0u8; 4];
-                        let opening_chars = opening_char.encode_utf8(&mut buffer);
-                        self.state =
-                            state::State::Editing(state::EditingState::new(cells, opening_chars));
-                        *info = format!("Editing {cells}");
-                        Some(command::Command::RedrawRequest)
-                    } else {
-                        None
+                None => None,
+            },
+            state::State::Rules(rules) => match rules.handle_event(event, info) {
+                Some(response) => {
+                    if response.exit {
+                        self.state = state::State::Idle;
                     }
+                    Some(response.command)
                 }
-                (
-                    Event::Key(KeyEvent {
-                        kind: KeyEventKind::Press,
-                        code: KeyCode::Enter,
-                        ..
-                    }),
-                    Some(cells),
-                ) => {
-                    if cells.count() == 1 {
-                        let content = self
-                            .content
-                            .get(&cell::CellIndex::new(cells.x, cells.y))
-                            .map(|cell| cell.to_string())
-                            .unwrap_or(String::with_capacity(0));
-                        self.state =
-                            state::State::Editing(state::EditingState::new(cells, &content));
-                        *info = format!("Editing {cells}");
-                        Some(command::Command::RedrawRequest)
-                    } else {
-                        None
+                None => None,
+            },
+            state::State::Validations(validations) => match validations.handle_event(event, info) {
+                Some(response) => {
+                    if response.exit {
+                        self.state = state::State::Idle;
                     }
+                    Some(response.command)
                 }
-                // cells selected and suppr / backspace, delete selected cells
-                (
-                    Event::Key(KeyEvent {
-                        kind: KeyEventKind::Press,
-                        code: KeyCode::Delete | KeyCode::Backspace,
-                        ..
-                    }),
-                    Some(cells),
-                ) => Some(command::Command::DeleteCells {
-                    cells,
-                    next_selection: command::SelectionDirection::Stay,
-                }),
-
-                // Lastly, we can redirect the event to the viewport control
-                (other, _) => self.viewport.handle_event(other, info),
+                None => None,
+            },
+            state::State::Names(names) => match names.handle_event(event, info) {
+                Some(response) => {
+                    if response.exit {
+                        self.state = state::State::Idle;
+                    }
+                    Some(response.command)
+                }
+                None => None,
             },
-            state::State::Editing(editor) => match editor.handle_event(event, info) {
+            state::State::History(history) => match history.handle_event(event, info) {
                 Some(response) => {
                     if response.exit {
                         self.state = state::State::Idle;
@@ -354,6 +1166,8 @@ impl crate::event::EventHandler for FileApp {
             self.execute_command(cmd, info);
             redraw_requested = true;
         }
+        // however a merged cell got selected, its whole area is
+        self.viewport.fit_merges(&self.merges);
 
         redraw_requested
     }
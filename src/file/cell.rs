@@ -1,63 +1,141 @@
 const TEXT_COLOR: ratatui::style::Color = ratatui::style::Color::White;
 const NUM_COLOR: ratatui::style::Color = ratatui::style::Color::Indexed(230);
+const BOOL_COLOR: ratatui::style::Color = ratatui::style::Color::Indexed(183);
+const DATE_COLOR: ratatui::style::Color = ratatui::style::Color::Indexed(151);
 const FORMULA_COLOR: ratatui::style::Color = ratatui::style::Color::Indexed(159);
+const ERROR_COLOR: ratatui::style::Color = ratatui::style::Color::Indexed(203);
 
 /// Single cell in a spreadsheet!
-#[derive(Debug, Clone)]
+/// Dates, date times and durations are counted in days, dates and date times from 1899-12-30.
+#[derive(Debug, Clone, PartialEq)]
 pub enum Cell {
     Text(String),
-    Num(f32),
-    Formula(()),
+    Num(f64),
+    Bool(bool),
+    Date(i64),
+    DateTime(f64),
+    Duration(f64),
+    Formula(crate::file::formula::Formula),
 }
 
 impl Cell {
-    pub fn parse(content: &str) -> Cell {
-        if content.chars().next() == Some('=') {
-            return Cell::Formula(());
+    /// Parse user input into a cell, detecting the value type.
+    /// Dates and booleans are detected according to the given locale.
+    pub fn parse(content: &str, locale: &crate::locale::Locale) -> Cell {
+        if let Some(formula) = content.strip_prefix('=') {
+            return Cell::Formula(crate::file::formula::Formula::parse(formula));
         }
 
-        if let Ok(val) = content.parse::<f32>() {
+        if let Ok(val) = content.parse::<f64>() {
             return Cell::Num(val);
         }
 
+        if let Some(value) = locale.parse_bool(content) {
+            return Cell::Bool(value);
+        }
+
+        if let Some(serial) = locale.parse_date(content) {
+            return Cell::Date(serial);
+        }
+
+        if let Some(serial) = locale.parse_datetime(content) {
+            return Cell::DateTime(serial);
+        }
+
+        if let Some(days) = locale.parse_time(content) {
+            return Cell::Duration(days);
+        }
+
         Cell::Text(content.to_string())
     }
 
-    pub fn save(&self, content: &mut String) {
-        match self {
-            Cell::Text(text) => content.push_str(text.as_str()),
-            Cell::Num(val) => content.push_str(&format!("{val}")),
-            Cell::Formula(_) => content.push_str("=?"),
+    /// Constant cell holding an evaluated value, errors being kept as text.
+    pub fn from_value(value: &crate::file::formula::Value) -> Option<Cell> {
+        use crate::file::formula::Value;
+
+        match value {
+            Value::Empty => None,
+            Value::Number(number) => Some(Cell::Num(*number)),
+            Value::Text(text) => Some(Cell::Text(text.clone())),
+            Value::Bool(value) => Some(Cell::Bool(*value)),
+            Value::Date(serial) => Some(Cell::Date(*serial)),
+            Value::DateTime(serial) => Some(Cell::DateTime(*serial)),
+            Value::Duration(days) => Some(Cell::Duration(*days)),
+            Value::Error(error) => Some(Cell::Text(error.to_string())),
         }
     }
 
-    pub fn to_string(&self) -> String {
+    pub fn save(&self, content: &mut String) {
+        content.push_str(&crate::file::csv::quote(&self.to_string(), ';'));
+    }
+
+    /// Value of the cell, formulas are evaluated as errors: use an evaluator for them.
+    pub fn value(&self) -> crate::file::formula::Value {
+        use crate::file::formula::{FormulaError, Value};
+
         match self {
-            Cell::Text(text) => text.clone(),
-            Cell::Num(num) => format!("{num}"),
-            Cell::Formula(_) => format!("=?"),
+            Cell::Text(text) => Value::Text(text.clone()),
+            Cell::Num(num) => Value::Number(*num),
+            Cell::Bool(value) => Value::Bool(*value),
+            Cell::Date(serial) => Value::Date(*serial),
+            Cell::DateTime(serial) => Value::DateTime(*serial),
+            Cell::Duration(days) => Value::Duration(*days),
+            Cell::Formula(_) => Value::Error(FormulaError::NotAvailable),
         }
     }
 
-    pub fn render(&self, frame: &mut ratatui::Frame, cell_area: ratatui::layout::Rect) {
-        use ratatui::style::Stylize;
+    /// Render the cell, given its evaluated value.
+    pub fn render(
+        &self,
+        value: &crate::file::formula::Value,
+        format: Option<&crate::file::format::CellFormat>,
+        locale: &crate::locale::Locale,
+        frame: &mut ratatui::Frame,
+        cell_area: ratatui::layout::Rect,
+    ) {
+        use crate::file::formula::Value;
 
-        let paragraph = match self {
-            Cell::Text(text) => ratatui::widgets::Paragraph::new(text.as_str())
-                .left_aligned()
-                .fg(TEXT_COLOR),
-            Cell::Num(num) => ratatui::widgets::Paragraph::new(format!("{num}"))
-                .right_aligned()
-                .fg(NUM_COLOR),
-            Cell::Formula(_) => ratatui::widgets::Paragraph::new("formula")
-                .left_aligned()
-                .fg(FORMULA_COLOR),
+        let number_format = format.and_then(|format| format.number.as_ref());
+        let color = match (self, value) {
+            (_, Value::Error(_)) => ERROR_COLOR,
+            (Cell::Formula(_), _) => FORMULA_COLOR,
+            (Cell::Text(_), _) => TEXT_COLOR,
+            (Cell::Num(_), _) => NUM_COLOR,
+            (Cell::Bool(_), _) => BOOL_COLOR,
+            (Cell::Date(_) | Cell::DateTime(_) | Cell::Duration(_), _) => DATE_COLOR,
+        };
+        let (style, alignment) = match format {
+            Some(format) => (
+                format.text_style(color),
+                format.alignment.unwrap_or(value.alignment()),
+            ),
+            None => (ratatui::style::Style::new().fg(color), value.alignment()),
         };
+        let paragraph = ratatui::widgets::Paragraph::new(value.display(number_format, locale))
+            .alignment(alignment)
+            .style(style);
 
         frame.render_widget(paragraph, cell_area);
     }
 }
 
+impl std::fmt::Display for Cell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // typed values are written in ISO format, so they can be read back with any locale.
+        // The file keeps those it was read with in their own form, see `DiskState::field`
+        let iso = crate::locale::Locale::default();
+        match self {
+            Cell::Text(text) => write!(f, "{text}"),
+            Cell::Num(num) => write!(f, "{num}"),
+            Cell::Bool(value) => write!(f, "{}", iso.format_bool(*value)),
+            Cell::Date(serial) => write!(f, "{}", iso.format_date(*serial)),
+            Cell::DateTime(serial) => write!(f, "{}", iso.format_datetime(*serial)),
+            Cell::Duration(days) => write!(f, "{}", crate::locale::format_time(*days)),
+            Cell::Formula(formula) => write!(f, "{formula}"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct CellIndex {
     pub x: u64,
@@ -69,6 +147,24 @@ impl CellIndex {
         CellIndex { x, y }
     }
 
+    /// Parse a cell name such as `B12`, case insensitive.
+    pub fn parse(name: &str) -> Option<CellIndex> {
+        let letters = name
+            .chars()
+            .take_while(|ch| ch.is_ascii_alphabetic())
+            .count();
+        let (column, row) = name.split_at(letters);
+        Some(CellIndex::new(parse_column(column)?, parse_row(row)?))
+    }
+
+    /// Index moved by an offset, None when it would go out of the sheet.
+    pub fn offset(&self, dx: i64, dy: i64) -> Option<CellIndex> {
+        Some(CellIndex::new(
+            self.x.checked_add_signed(dx)?,
+            self.y.checked_add_signed(dy)?,
+        ))
+    }
+
     pub fn alternate_color_index(&self) -> usize {
         usize::try_from((self.x + self.y) % 2).unwrap()
     }
@@ -84,11 +180,7 @@ impl std::fmt::Display for CellIndex {
 
 impl PartialOrd for CellIndex {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(match self.y.cmp(&other.y) {
-            std::cmp::Ordering::Less => std::cmp::Ordering::Less,
-            std::cmp::Ordering::Greater => std::cmp::Ordering::Greater,
-            std::cmp::Ordering::Equal => self.x.cmp(&other.x),
-        })
+        Some(self.cmp(other))
     }
 }
 impl Ord for CellIndex {
@@ -101,7 +193,7 @@ impl Ord for CellIndex {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct CellRect {
     pub x: u64,
     pub y: u64,
@@ -129,6 +221,53 @@ impl CellRect {
     pub fn count(&self) -> u64 {
         self.width.saturating_mul(self.height)
     }
+
+    /// Whether the rectangles have cells in common.
+    pub fn intersects(&self, other: CellRect) -> bool {
+        self.x < other.x + other.width
+            && other.x < self.x + self.width
+            && self.y < other.y + other.height
+            && other.y < self.y + self.height
+    }
+
+    /// Rectangle moved by an offset, None when it would go out of the sheet.
+    pub fn offset(&self, dx: i64, dy: i64) -> Option<CellRect> {
+        let top_left = CellIndex::new(self.x, self.y).offset(dx, dy)?;
+        Some(CellRect::new(
+            top_left.x,
+            top_left.y,
+            self.width,
+            self.height,
+        ))
+    }
+
+    /// All the cell indices of the rectangle, row by row.
+    pub fn indices(&self) -> impl Iterator<Item = CellIndex> + use<> {
+        let CellRect {
+            x,
+            y,
+            width,
+            height,
+        } = *self;
+        (y..y.saturating_add(height)).flat_map(move |cell_y| {
+            (x..x.saturating_add(width)).map(move |cell_x| CellIndex::new(cell_x, cell_y))
+        })
+    }
+
+    /// Parse a cell or a range of cells, such as `B2` or `B2:D10`, as displayed.
+    pub fn parse(name: &str) -> Option<CellRect> {
+        let (start, end) = name
+            .trim()
+            .split_once(':')
+            .unwrap_or((name.trim(), name.trim()));
+        let (start, end) = (CellIndex::parse(start)?, CellIndex::parse(end)?);
+        Some(CellRect::new(
+            start.x.min(end.x),
+            start.y.min(end.y),
+            start.x.abs_diff(end.x) + 1,
+            start.y.abs_diff(end.y) + 1,
+        ))
+    }
 }
 
 impl std::fmt::Display for CellRect {
@@ -162,6 +301,117 @@ impl From<ratatui::layout::Rect> for CellRect {
     }
 }
 
+/// Whether whole rows or whole columns are inserted or deleted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    Rows,
+    Columns,
+}
+
+/// Insertion or deletion of `count` rows or columns at `at`, moving the following ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineShift {
+    pub axis: Axis,
+    pub at: u64,
+    pub count: u64,
+    pub inserted: bool,
+}
+
+impl LineShift {
+    /// The shift that reverts this one.
+    pub fn rev(self) -> LineShift {
+        LineShift {
+            inserted: !self.inserted,
+            ..self
+        }
+    }
+
+    /// Position of a row or column after the shift, None when it is deleted.
+    pub fn line(&self, line: u64) -> Option<u64> {
+        if line < self.at {
+            Some(line)
+        } else if self.inserted {
+            line.checked_add(self.count)
+        } else if line - self.at < self.count {
+            None
+        } else {
+            Some(line - self.count)
+        }
+    }
+
+    /// Position of the start or the end of a range after the shift.
+    /// A deleted bound is replaced by the closest kept line inside the range, if any.
+    pub fn bound(&self, line: u64, is_end: bool) -> Option<u64> {
+        match (self.line(line), is_end) {
+            (Some(line), _) => Some(line),
+            (None, false) => Some(self.at),
+            (None, true) => self.at.checked_sub(1),
+        }
+    }
+
+    pub fn index(&self, index: CellIndex) -> Option<CellIndex> {
+        match self.axis {
+            Axis::Rows => Some(CellIndex::new(index.x, self.line(index.y)?)),
+            Axis::Columns => Some(CellIndex::new(self.line(index.x)?, index.y)),
+        }
+    }
+
+    /// Rectangle after the shift, growing or shrinking when lines are inserted or deleted inside
+    /// it. None when all its cells are deleted.
+    pub fn rect(&self, rect: CellRect) -> Option<CellRect> {
+        let (start, length) = match self.axis {
+            Axis::Rows => (rect.y, rect.height),
+            Axis::Columns => (rect.x, rect.width),
+        };
+        let end = start + length.saturating_sub(1);
+        let (start, end) = (self.bound(start, false)?, self.bound(end, true)?);
+        if start > end {
+            return None;
+        }
+        Some(match self.axis {
+            Axis::Rows => CellRect::new(rect.x, start, rect.width, end - start + 1),
+            Axis::Columns => CellRect::new(start, rect.y, end - start + 1, rect.height),
+        })
+    }
+
+    /// Move the keys of a map of cells. Values in deleted lines are dropped.
+    pub fn apply<T>(&self, map: &mut std::collections::BTreeMap<CellIndex, T>) {
+        let moved = match self.axis {
+            // cells are sorted by rows, so the moved ones are all at the end
+            Axis::Rows => map.split_off(&CellIndex::new(0, self.at)),
+            Axis::Columns => {
+                let keys = map
+                    .keys()
+                    .filter(|index| index.x >= self.at)
+                    .copied()
+                    .collect::<Vec<_>>();
+                keys.iter()
+                    .filter_map(|index| map.remove_entry(index))
+                    .collect()
+            }
+        };
+        map.extend(
+            moved
+                .into_iter()
+                .filter_map(|(index, value)| Some((self.index(index)?, value))),
+        );
+    }
+}
+
+impl std::fmt::Display for LineShift {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let last = self.at + self.count.saturating_sub(1);
+        let (name, first, last) = match self.axis {
+            Axis::Rows => ("row", format_row(self.at), format_row(last)),
+            Axis::Columns => ("column", format_column(self.at), format_column(last)),
+        };
+        match self.count {
+            1 => write!(f, "{name} {first}"),
+            _ => write!(f, "{name}s {first}:{last}"),
+        }
+    }
+}
+
 pub fn format_column(col: u64) -> String {
     let mut index = col;
     let mut column = String::new();
@@ -181,3 +431,27 @@ pub fn format_column(col: u64) -> String {
 pub fn format_row(row: u64) -> String {
     format!("{}", row + 1)
 }
+
+/// Parse a column name such as `A` or `AB`, case insensitive.
+pub fn parse_column(column: &str) -> Option<u64> {
+    if column.is_empty() {
+        return None;
+    }
+    let mut index = 0u64;
+    for ch in column.chars() {
+        if !ch.is_ascii_alphabetic() {
+            return None;
+        }
+        let digit = u64::from(ch.to_ascii_uppercase() as u8 - b'A' + 1);
+        index = index.checked_mul(26)?.checked_add(digit)?;
+    }
+    Some(index - 1)
+}
+
+/// Parse a row number as displayed, starting at 1.
+pub fn parse_row(row: &str) -> Option<u64> {
+    if !row.chars().all(|ch| ch.is_ascii_digit()) {
+        return None;
+    }
+    row.parse::<u64>().ok()?.checked_sub(1)
+}
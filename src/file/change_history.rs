@@ -1,46 +1,324 @@
 use crate::file::cell;
+use crate::file::format;
+use crate::file::names;
+use crate::file::validation;
 
-/// History of all the changes for undo / redo
-/// Stored as a tree changes, to explore what happened maybe ?
+/// Header of a persisted history, followed by a hash of the file it was saved with.
+const HISTORY_HEADER: &str = "rex history 1";
+
+/// History of all the changes for undo / redo, stored as a tree: changes made after an undo
+/// start a new branch, and the undone changes stay reachable from the history panel.
 pub struct ChangeHistory {
-    last_changes: Vec<Changes>,
-    last_undos: Vec<Changes>,
+    /// Every state the file went through, the first one being the opened file.
+    nodes: Vec<HistoryNode>,
+    /// Node of the current state.
+    current: usize,
+    /// Node of the state last saved to the file, or opened from it, if the file still has it.
+    saved: Option<usize>,
+}
+
+struct HistoryNode {
+    parent: Option<usize>,
+    /// Changes from the parent state to this one.
+    changes: Changes,
+    /// When the changes were made, in seconds since the unix epoch.
+    time: u64,
+    children: Vec<usize>,
+    /// Child followed by redo: the last one made or visited.
+    redo_child: Option<usize>,
+}
+
+/// Line of the history panel.
+pub struct HistoryEntry {
+    pub node: usize,
+    /// Branch depth: branches left behind are indented below the state they started from.
+    pub depth: usize,
+    pub time: u64,
+    pub description: String,
 }
 
 impl ChangeHistory {
     pub fn new() -> ChangeHistory {
         ChangeHistory {
-            last_changes: Vec::new(),
-            last_undos: Vec::new(),
+            nodes: vec![HistoryNode::new(None, Changes::Empty, now())],
+            current: 0,
+            saved: Some(0),
         }
     }
 
+    /// Record changes made from the current state. Empty changes are not worth an undo step.
     pub fn push(&mut self, changes: Changes) {
-        self.last_changes.push(changes);
+        if let Changes::Empty = changes {
+            return;
+        }
+        self.add_node(self.current, changes, now());
     }
 
-    pub fn last_changes(&self) -> Option<&Changes> {
-        self.last_changes.last()
+    /// Undo the last changes, returning the changes to apply to revert them.
+    pub fn undo(&mut self) -> Option<Changes> {
+        let node = &self.nodes[self.current];
+        let parent = node.parent?;
+        let changes = node.changes.clone().rev();
+        self.current = parent;
+        Some(changes)
     }
 
-    pub fn undo(&mut self) {
-        if let Some(last_change) = self.last_changes.pop() {
-            self.last_undos.push(last_change.rev());
+    /// Redo the last undone changes, returning the changes to apply again.
+    pub fn redo(&mut self) -> Option<Changes> {
+        let child = self.nodes[self.current].redo_child?;
+        self.current = child;
+        Some(self.nodes[child].changes.clone())
+    }
+
+    /// Go to any state of the tree, returning the changes to apply in order to get there:
+    /// undo up to the branch it is on, then redo down to it. Redo then follows this branch.
+    pub fn jump(&mut self, node: usize) -> Vec<Changes> {
+        if node >= self.nodes.len() {
+            return Vec::new();
+        }
+        let changes = self.path(self.current, node);
+        for child in self.ancestors(node) {
+            if let Some(parent) = self.nodes[child].parent {
+                self.nodes[parent].redo_child = Some(child);
+            }
         }
+        self.current = node;
+        changes
     }
 
-    pub fn last_undos(&self) -> Option<&Changes> {
-        self.last_undos.last()
+    /// Changes to apply in order to go from a state to another, through their common state.
+    pub fn path(&self, from: usize, to: usize) -> Vec<Changes> {
+        let up = self.ancestors(from);
+        let down = self.ancestors(to);
+        let common = up.iter().find(|ancestor| down.contains(ancestor)).copied();
+
+        let mut changes = up
+            .iter()
+            .take_while(|ancestor| Some(**ancestor) != common)
+            .map(|ancestor| self.nodes[*ancestor].changes.clone().rev())
+            .collect::<Vec<_>>();
+        let down = down
+            .into_iter()
+            .take_while(|ancestor| Some(*ancestor) != common)
+            .collect::<Vec<_>>();
+        changes.extend(
+            down.into_iter()
+                .rev()
+                .map(|child| self.nodes[child].changes.clone()),
+        );
+        changes
     }
 
-    pub fn redo(&mut self) {
-        if let Some(last_undo) = self.last_undos.pop() {
-            self.last_changes.push(last_undo.rev());
+    /// Number of changes undo can revert, back to the opened file.
+    pub fn undo_count(&self) -> usize {
+        self.ancestors(self.current).len() - 1
+    }
+
+    /// Node of the current state.
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Number of states in the tree, growing with every recorded change.
+    pub fn state_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Record the selection before and after the changes that led to the current state, so
+    /// undo and redo can restore them.
+    pub fn record_selection(
+        &mut self,
+        previous_value: Option<cell::CellRect>,
+        new_value: Option<cell::CellRect>,
+    ) {
+        let node = &mut self.nodes[self.current];
+        if node.parent.is_none() {
+            return;
         }
+        let mut changes = std::mem::replace(&mut node.changes, Changes::Empty)
+            .as_slice()
+            .to_vec();
+        changes.push(Change::Selection {
+            previous_value,
+            new_value,
+        });
+        node.changes = Changes::from_vec(changes);
     }
+
+    /// Remember the current state as the one written in the file.
+    pub fn mark_saved(&mut self) {
+        self.saved = Some(self.current);
+    }
+
+    /// Forget the saved state, when the file was overwritten by another program.
+    pub fn mark_unsaved(&mut self) {
+        self.saved = None;
+    }
+
+    /// Whether the current state is the one written in the file.
+    pub fn is_saved(&self) -> bool {
+        self.saved == Some(self.current)
+    }
+
+    /// Every state, in the order of the history panel: from the oldest one, each branch left
+    /// behind coming, indented, before the branch that was kept.
+    pub fn entries(&self) -> Vec<HistoryEntry> {
+        let mut entries = Vec::with_capacity(self.nodes.len());
+        let mut stack = vec![(0, 0)];
+        while let Some((node, depth)) = stack.pop() {
+            let history_node = &self.nodes[node];
+            entries.push(HistoryEntry {
+                node,
+                depth,
+                time: history_node.time,
+                description: match history_node.parent {
+                    None => "opened file".to_string(),
+                    Some(_) => history_node.changes.to_string(),
+                },
+            });
+            if let Some((last, older)) = history_node.children.split_last() {
+                stack.push((*last, depth));
+                stack.extend(older.iter().rev().map(|child| (*child, depth + 1)));
+            }
+        }
+        entries
+    }
+
+    /// Write the history as text, to be read back with the content of the file being saved.
+    pub fn to_text(&self, content: &str) -> String {
+        let mut lines = vec![format!("{HISTORY_HEADER}\t{:016x}", hash(content))];
+        for node in &self.nodes {
+            lines.push(match node.parent {
+                None => format!("root\t{}", node.time),
+                Some(parent) => format!("node\t{parent}\t{}", node.time),
+            });
+            lines.extend(node.changes.as_slice().iter().map(Change::to_line));
+        }
+        lines.push(format!("current\t{}", self.current));
+        lines.join("\n") + "\n"
+    }
+
+    /// Read a history written by `to_text`, checking the file still has the saved content.
+    pub fn from_text(text: &str, content: &str) -> Result<ChangeHistory, String> {
+        let mut lines = text.lines();
+        match lines.next().and_then(|header| header.split_once('\t')) {
+            Some((HISTORY_HEADER, saved_hash))
+                if u64::from_str_radix(saved_hash, 16) == Ok(hash(content)) => {}
+            Some((HISTORY_HEADER, _)) => {
+                return Err("The file changed since its history was saved".to_string());
+            }
+            _ => return Err("Not a history file".to_string()),
+        }
+
+        let mut history = ChangeHistory {
+            nodes: Vec::new(),
+            current: 0,
+            saved: Some(0),
+        };
+        // node being read, with its changes so far
+        let mut node: Option<(Option<usize>, u64, Vec<Change>)> = None;
+        for line in lines {
+            let fields = line.split('\t').collect::<Vec<_>>();
+            let started = match fields.as_slice() {
+                ["root", time] => Some((None, time)),
+                ["node", parent, time] => Some((Some(parse_field(parent)?), time)),
+                ["current", current] => {
+                    history.current = parse_field(current)?;
+                    None
+                }
+                _ => match &mut node {
+                    Some((_, _, changes)) => {
+                        changes.push(Change::from_fields(&fields)?);
+                        None
+                    }
+                    None => return Err(format!("Unexpected history line \"{line}\"")),
+                },
+            };
+            if let Some((parent, time)) = started {
+                if let Some(finished) = node.take() {
+                    history.add_read_node(finished)?;
+                }
+                node = Some((parent, parse_field(time)?, Vec::new()));
+            }
+        }
+        if let Some(finished) = node.take() {
+            history.add_read_node(finished)?;
+        }
+        // the history is saved along with the file
+        history.saved = Some(history.current);
+        match history.current < history.nodes.len() {
+            true => Ok(history),
+            false => Err("Invalid current state in history".to_string()),
+        }
+    }
+
+    fn add_node(&mut self, parent: usize, changes: Changes, time: u64) {
+        let node = self.nodes.len();
+        self.nodes
+            .push(HistoryNode::new(Some(parent), changes, time));
+        self.nodes[parent].children.push(node);
+        self.nodes[parent].redo_child = Some(node);
+        self.current = node;
+    }
+
+    fn add_read_node(
+        &mut self,
+        (parent, time, changes): (Option<usize>, u64, Vec<Change>),
+    ) -> Result<(), String> {
+        match parent {
+            None if self.nodes.is_empty() => {
+                self.nodes
+                    .push(HistoryNode::new(None, Changes::Empty, time));
+            }
+            Some(parent) if parent < self.nodes.len() => {
+                self.add_node(parent, Changes::from_vec(changes), time)
+            }
+            _ => return Err("Invalid state parent in history".to_string()),
+        }
+        Ok(())
+    }
+
+    /// The node and all its ancestors, up to the opened file.
+    fn ancestors(&self, node: usize) -> Vec<usize> {
+        std::iter::successors(Some(node), |node| self.nodes[*node].parent).collect()
+    }
+}
+
+impl HistoryNode {
+    fn new(parent: Option<usize>, changes: Changes, time: u64) -> HistoryNode {
+        HistoryNode {
+            parent,
+            changes,
+            time,
+            children: Vec::new(),
+            redo_child: None,
+        }
+    }
+}
+
+/// Current time, in seconds since the unix epoch.
+pub fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+/// FNV-1a hash, stable across runs, to recognize the content a history was saved with.
+fn hash(content: &str) -> u64 {
+    content.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn parse_field<T: std::str::FromStr>(field: &str) -> Result<T, String> {
+    field
+        .parse()
+        .map_err(|_| format!("Invalid value \"{field}\" in history"))
 }
 
 /// Changes made by a single action
+#[derive(Debug, Clone, PartialEq)]
 pub enum Changes {
     Empty,
     Atomic(Change),
@@ -48,13 +326,33 @@ pub enum Changes {
 }
 
 impl Changes {
+    /// Group a list of changes, picking the smallest representation.
+    pub fn from_vec(mut changes: Vec<Change>) -> Changes {
+        match changes.len() {
+            0 => Changes::Empty,
+            1 => Changes::Atomic(changes.pop().unwrap()),
+            _ => Changes::Group(changes),
+        }
+    }
+
+    /// All the atomic changes, in the order they were made.
+    pub fn as_slice(&self) -> &[Change] {
+        match self {
+            Changes::Empty => &[],
+            Changes::Atomic(change) => std::slice::from_ref(change),
+            Changes::Group(changes) => changes.as_slice(),
+        }
+    }
+
     fn rev(self) -> Changes {
         match self {
             Changes::Empty => Changes::Empty,
             Changes::Atomic(change) => Changes::Atomic(change.rev()),
+            // reverted in the opposite order, as some changes move the cells of the others
             Changes::Group(changes) => Changes::Group(
                 changes
                     .into_iter()
+                    .rev()
                     .map(|change| change.rev())
                     .collect::<Vec<_>>(),
             ),
@@ -63,29 +361,757 @@ impl Changes {
 }
 
 /// Single atomic change
-pub struct Change {
-    pub index: cell::CellIndex,
-    pub previous_value: Option<cell::Cell>,
-    pub new_value: Option<cell::Cell>,
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// The content of a cell changed.
+    Cell {
+        index: cell::CellIndex,
+        previous_value: Option<cell::Cell>,
+        new_value: Option<cell::Cell>,
+    },
+    /// The format of a cell changed.
+    Format {
+        index: cell::CellIndex,
+        previous_value: Option<format::CellFormat>,
+        new_value: Option<format::CellFormat>,
+    },
+    /// The note of a cell changed.
+    Note {
+        index: cell::CellIndex,
+        previous_value: Option<String>,
+        new_value: Option<String>,
+    },
+    /// Rows or columns were inserted or deleted, moving the cells, formats and notes after them.
+    Lines(cell::LineShift),
+    /// The conditional formatting rules changed.
+    Rules {
+        previous_value: Vec<format::conditional::Rule>,
+        new_value: Vec<format::conditional::Rule>,
+    },
+    /// The data validation rules changed.
+    Validations {
+        previous_value: Vec<validation::Validation>,
+        new_value: Vec<validation::Validation>,
+    },
+    /// The merged cells changed.
+    Merges {
+        previous_value: Vec<cell::CellRect>,
+        new_value: Vec<cell::CellRect>,
+    },
+    /// The names of cells and constants changed.
+    Names {
+        previous_value: Vec<names::Name>,
+        new_value: Vec<names::Name>,
+    },
+    /// The selection around the other changes, restored with them.
+    Selection {
+        previous_value: Option<cell::CellRect>,
+        new_value: Option<cell::CellRect>,
+    },
 }
 
 impl Change {
-    pub fn new(
+    pub fn cell(
         index: cell::CellIndex,
         previous_value: Option<cell::Cell>,
         new_value: Option<cell::Cell>,
     ) -> Change {
-        Change {
+        Change::Cell {
+            index,
+            previous_value,
+            new_value,
+        }
+    }
+
+    pub fn format(
+        index: cell::CellIndex,
+        previous_value: Option<format::CellFormat>,
+        new_value: Option<format::CellFormat>,
+    ) -> Change {
+        Change::Format {
             index,
             previous_value,
             new_value,
         }
     }
+
     fn rev(self) -> Change {
-        Change {
-            index: self.index,
-            previous_value: self.new_value,
-            new_value: self.previous_value,
+        match self {
+            Change::Cell {
+                index,
+                previous_value,
+                new_value,
+            } => Change::Cell {
+                index,
+                previous_value: new_value,
+                new_value: previous_value,
+            },
+            Change::Format {
+                index,
+                previous_value,
+                new_value,
+            } => Change::Format {
+                index,
+                previous_value: new_value,
+                new_value: previous_value,
+            },
+            Change::Note {
+                index,
+                previous_value,
+                new_value,
+            } => Change::Note {
+                index,
+                previous_value: new_value,
+                new_value: previous_value,
+            },
+            Change::Lines(shift) => Change::Lines(shift.rev()),
+            Change::Rules {
+                previous_value,
+                new_value,
+            } => Change::Rules {
+                previous_value: new_value,
+                new_value: previous_value,
+            },
+            Change::Validations {
+                previous_value,
+                new_value,
+            } => Change::Validations {
+                previous_value: new_value,
+                new_value: previous_value,
+            },
+            Change::Merges {
+                previous_value,
+                new_value,
+            } => Change::Merges {
+                previous_value: new_value,
+                new_value: previous_value,
+            },
+            Change::Names {
+                previous_value,
+                new_value,
+            } => Change::Names {
+                previous_value: new_value,
+                new_value: previous_value,
+            },
+            Change::Selection {
+                previous_value,
+                new_value,
+            } => Change::Selection {
+                previous_value: new_value,
+                new_value: previous_value,
+            },
+        }
+    }
+}
+
+/// Everything of a sheet the changes are made on, borrowed from the app or from a recovery.
+pub struct Sheet<'a> {
+    pub content: &'a mut std::collections::BTreeMap<cell::CellIndex, cell::Cell>,
+    pub formats: &'a mut std::collections::BTreeMap<cell::CellIndex, format::CellFormat>,
+    pub rules: &'a mut Vec<format::conditional::Rule>,
+    pub validations: &'a mut Vec<validation::Validation>,
+    pub notes: &'a mut std::collections::BTreeMap<cell::CellIndex, String>,
+    pub merges: &'a mut Vec<cell::CellRect>,
+    pub names: &'a mut Vec<names::Name>,
+}
+
+impl Change {
+    /// Apply the new value of the change to the sheet. Selections are left to the viewport.
+    pub fn apply(&self, sheet: &mut Sheet) {
+        let Sheet {
+            content,
+            formats,
+            rules,
+            validations,
+            notes,
+            merges,
+            names,
+        } = sheet;
+        match self {
+            Change::Cell {
+                index, new_value, ..
+            } => {
+                match new_value {
+                    Some(value) => content.insert(*index, value.clone()),
+                    None => content.remove(index),
+                };
+            }
+            Change::Format {
+                index, new_value, ..
+            } => {
+                match new_value {
+                    Some(format) => formats.insert(*index, format.clone()),
+                    None => formats.remove(index),
+                };
+            }
+            Change::Note {
+                index, new_value, ..
+            } => {
+                match new_value {
+                    Some(note) => notes.insert(*index, note.clone()),
+                    None => notes.remove(index),
+                };
+            }
+            Change::Lines(shift) => {
+                shift.apply(content);
+                shift.apply(formats);
+                shift.apply(notes);
+            }
+            Change::Rules { new_value, .. } => **rules = new_value.clone(),
+            Change::Validations { new_value, .. } => **validations = new_value.clone(),
+            Change::Merges { new_value, .. } => **merges = new_value.clone(),
+            Change::Names { new_value, .. } => **names = new_value.clone(),
+            Change::Selection { .. } => {}
+        }
+    }
+
+    /// Write the change as a tab separated line of the persisted history.
+    pub fn to_line(&self) -> String {
+        match self {
+            Change::Cell {
+                index,
+                previous_value,
+                new_value,
+            } => format!(
+                "cell\t{index}\t{}\t{}",
+                write_cell(previous_value),
+                write_cell(new_value)
+            ),
+            Change::Format {
+                index,
+                previous_value,
+                new_value,
+            } => format!(
+                "format\t{index}\t{}\t{}",
+                write_format(previous_value),
+                write_format(new_value)
+            ),
+            Change::Note {
+                index,
+                previous_value,
+                new_value,
+            } => format!(
+                "note\t{index}\t{}\t{}",
+                write_note(previous_value),
+                write_note(new_value)
+            ),
+            Change::Lines(shift) => format!(
+                "lines\t{}\t{}\t{}\t{}",
+                match shift.inserted {
+                    true => "insert",
+                    false => "delete",
+                },
+                match shift.axis {
+                    cell::Axis::Rows => "rows",
+                    cell::Axis::Columns => "columns",
+                },
+                shift.at,
+                shift.count
+            ),
+            Change::Rules {
+                previous_value,
+                new_value,
+            } => std::iter::once(format!("rules\t{}", previous_value.len()))
+                .chain(
+                    previous_value
+                        .iter()
+                        .chain(new_value)
+                        .map(|rule| escape(&rule.to_string())),
+                )
+                .collect::<Vec<_>>()
+                .join("\t"),
+            Change::Validations {
+                previous_value,
+                new_value,
+            } => std::iter::once(format!("validations\t{}", previous_value.len()))
+                .chain(
+                    previous_value
+                        .iter()
+                        .chain(new_value)
+                        .map(|validation| escape(&validation.to_string())),
+                )
+                .collect::<Vec<_>>()
+                .join("\t"),
+            Change::Merges {
+                previous_value,
+                new_value,
+            } => std::iter::once(format!("merges\t{}", previous_value.len()))
+                .chain(
+                    previous_value
+                        .iter()
+                        .chain(new_value)
+                        .map(|merge| merge.to_string()),
+                )
+                .collect::<Vec<_>>()
+                .join("\t"),
+            Change::Names {
+                previous_value,
+                new_value,
+            } => std::iter::once(format!("names\t{}", previous_value.len()))
+                .chain(
+                    previous_value
+                        .iter()
+                        .chain(new_value)
+                        .map(|name| escape(&name.to_string())),
+                )
+                .collect::<Vec<_>>()
+                .join("\t"),
+            Change::Selection {
+                previous_value,
+                new_value,
+            } => format!(
+                "selection\t{}\t{}",
+                write_selection(previous_value),
+                write_selection(new_value)
+            ),
+        }
+    }
+
+    /// Read a change from the fields of a line written by `to_line`.
+    pub fn from_fields(fields: &[&str]) -> Result<Change, String> {
+        let parse_index = |name: &str| {
+            cell::CellIndex::parse(name)
+                .ok_or_else(|| format!("Invalid cell \"{name}\" in history"))
+        };
+        match fields {
+            ["cell", index, previous, new] => Ok(Change::cell(
+                parse_index(index)?,
+                read_cell(previous),
+                read_cell(new),
+            )),
+            ["format", index, previous, new] => Ok(Change::format(
+                parse_index(index)?,
+                read_format(previous)?,
+                read_format(new)?,
+            )),
+            ["note", index, previous, new] => Ok(Change::Note {
+                index: parse_index(index)?,
+                previous_value: read_note(previous),
+                new_value: read_note(new),
+            }),
+            ["lines", operation, axis, at, count] => Ok(Change::Lines(cell::LineShift {
+                axis: match *axis {
+                    "rows" => cell::Axis::Rows,
+                    _ => cell::Axis::Columns,
+                },
+                at: parse_field(at)?,
+                count: parse_field(count)?,
+                inserted: *operation == "insert",
+            })),
+            ["rules", previous_count, rules @ ..] => {
+                let rules = rules
+                    .iter()
+                    .map(|rule| format::conditional::Rule::parse(&unescape(rule)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let previous_count = parse_field::<usize>(previous_count)?.min(rules.len());
+                let (previous_value, new_value) = rules.split_at(previous_count);
+                Ok(Change::Rules {
+                    previous_value: previous_value.to_vec(),
+                    new_value: new_value.to_vec(),
+                })
+            }
+            ["validations", previous_count, validations @ ..] => {
+                let validations = validations
+                    .iter()
+                    .map(|validation| validation::Validation::parse(&unescape(validation)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let previous_count = parse_field::<usize>(previous_count)?.min(validations.len());
+                let (previous_value, new_value) = validations.split_at(previous_count);
+                Ok(Change::Validations {
+                    previous_value: previous_value.to_vec(),
+                    new_value: new_value.to_vec(),
+                })
+            }
+            ["merges", previous_count, merges @ ..] => {
+                let merges = merges
+                    .iter()
+                    .map(|merge| {
+                        cell::CellRect::parse(merge)
+                            .ok_or_else(|| format!("Invalid cells \"{merge}\" in history"))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                let previous_count = parse_field::<usize>(previous_count)?.min(merges.len());
+                let (previous_value, new_value) = merges.split_at(previous_count);
+                Ok(Change::Merges {
+                    previous_value: previous_value.to_vec(),
+                    new_value: new_value.to_vec(),
+                })
+            }
+            ["names", previous_count, names @ ..] => {
+                let names = names
+                    .iter()
+                    .map(|name| names::Name::parse(&unescape(name)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let previous_count = parse_field::<usize>(previous_count)?.min(names.len());
+                let (previous_value, new_value) = names.split_at(previous_count);
+                Ok(Change::Names {
+                    previous_value: previous_value.to_vec(),
+                    new_value: new_value.to_vec(),
+                })
+            }
+            ["selection", previous, new] => Ok(Change::Selection {
+                previous_value: cell::CellRect::parse(previous),
+                new_value: cell::CellRect::parse(new),
+            }),
+            _ => Err(format!(
+                "Invalid change \"{}\" in history",
+                fields.join(" ")
+            )),
+        }
+    }
+}
+
+/// Short description of the changes, for the history panel.
+impl std::fmt::Display for Changes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let changes = self.as_slice();
+        for change in changes {
+            match change {
+                Change::Lines(shift) if shift.inserted => return write!(f, "inserted {shift}"),
+                Change::Lines(shift) => return write!(f, "deleted {shift}"),
+                Change::Rules { .. } => return write!(f, "conditional formatting rules"),
+                Change::Validations { .. } => return write!(f, "data validation rules"),
+                // names only following moved cells are left to the description of the cells
+                Change::Names {
+                    previous_value,
+                    new_value,
+                } if changes.len() == 1
+                    || !previous_value
+                        .iter()
+                        .map(|name| &name.name)
+                        .eq(new_value.iter().map(|name| &name.name)) =>
+                {
+                    return write!(f, "names");
+                }
+                // merges moved along with their cells keep their count
+                Change::Merges {
+                    previous_value,
+                    new_value,
+                } if new_value.len() > previous_value.len() => return write!(f, "merged cells"),
+                Change::Merges {
+                    previous_value,
+                    new_value,
+                } if new_value.len() < previous_value.len() => {
+                    return write!(f, "unmerged cells");
+                }
+                _ => {}
+            }
+        }
+        let cells = changes
+            .iter()
+            .filter_map(|change| match change {
+                Change::Cell {
+                    index, new_value, ..
+                } => Some((*index, new_value)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let formats = changes.iter().filter_map(|change| match change {
+            Change::Format { index, .. } => Some(*index),
+            _ => None,
+        });
+        let notes = changes.iter().filter_map(|change| match change {
+            Change::Note { index, .. } => Some(*index),
+            _ => None,
+        });
+        match cells.as_slice() {
+            [] => match (bounds(formats), bounds(notes)) {
+                (Some(cells), _) => write!(f, "format of {cells}"),
+                (None, Some(cells)) => write!(f, "note of {cells}"),
+                (None, None) => write!(f, "no changes"),
+            },
+            [(index, Some(content))] => write!(f, "{index} = {content}"),
+            [(index, None)] => write!(f, "cleared {index}"),
+            _ => write!(
+                f,
+                "{} cells in {}",
+                cells.len(),
+                bounds(cells.iter().map(|(index, _)| *index))
+                    .map(|cells| cells.to_string())
+                    .unwrap_or_default()
+            ),
         }
     }
 }
+
+/// Smallest rectangle holding all the cells.
+fn bounds(indices: impl Iterator<Item = cell::CellIndex>) -> Option<cell::CellRect> {
+    let (min, max) = indices.fold(None, |bounds, index| match bounds {
+        None => Some((index, index)),
+        Some((min, max)) => Some((
+            cell::CellIndex::new(index.x.min(min.x), index.y.min(min.y)),
+            cell::CellIndex::new(index.x.max(max.x), index.y.max(max.y)),
+        )),
+    })?;
+    Some(cell::CellRect::new(
+        min.x,
+        min.y,
+        max.x - min.x + 1,
+        max.y - min.y + 1,
+    ))
+}
+
+/// Cells are written as typed, in ISO format, texts being marked by a leading quote so
+/// that a text such as `12` is not read back as a number.
+fn write_cell(content: &Option<cell::Cell>) -> String {
+    match content {
+        None => String::new(),
+        Some(cell::Cell::Text(text)) => escape(&format!("'{text}")),
+        Some(content) => escape(&content.to_string()),
+    }
+}
+
+fn read_cell(field: &str) -> Option<cell::Cell> {
+    let content = unescape(field);
+    match content.strip_prefix('\'') {
+        _ if content.is_empty() => None,
+        Some(text) => Some(cell::Cell::Text(text.to_string())),
+        None => Some(cell::Cell::parse(
+            &content,
+            &crate::locale::Locale::default(),
+        )),
+    }
+}
+
+fn write_selection(selection: &Option<cell::CellRect>) -> String {
+    selection
+        .map(|selection| selection.to_string())
+        .unwrap_or_default()
+}
+
+fn write_format(format: &Option<format::CellFormat>) -> String {
+    format
+        .as_ref()
+        .map(|format| escape(&format.to_string()))
+        .unwrap_or_default()
+}
+
+fn read_format(field: &str) -> Result<Option<format::CellFormat>, String> {
+    match field {
+        "" => Ok(None),
+        field => format::CellFormat::parse(&unescape(field)).map(Some),
+    }
+}
+
+fn write_note(note: &Option<String>) -> String {
+    note.as_deref().map(escape).unwrap_or_default()
+}
+
+fn read_note(field: &str) -> Option<String> {
+    (!field.is_empty()).then(|| unescape(field))
+}
+
+/// Escape the tabs and line breaks separating the history fields.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn unescape(field: &str) -> String {
+    let mut text = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => match chars.next() {
+                Some('t') => text.push('\t'),
+                Some('n') => text.push('\n'),
+                Some('r') => text.push('\r'),
+                Some(other) => text.push(other),
+                None => text.push('\\'),
+            },
+            ch => text.push(ch),
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Change of A1 from `previous` to `new`, numbers standing for cell values.
+    fn write(previous: Option<f64>, new: f64) -> Changes {
+        Changes::Atomic(Change::cell(
+            cell::CellIndex::new(0, 0),
+            previous.map(cell::Cell::Num),
+            Some(cell::Cell::Num(new)),
+        ))
+    }
+
+    /// Value of A1 given by the changes, to follow the state of the file.
+    fn value(changes: &Changes) -> Option<f64> {
+        match changes.as_slice() {
+            [
+                Change::Cell {
+                    new_value: Some(cell::Cell::Num(value)),
+                    ..
+                },
+            ] => Some(*value),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn undo_and_redo_follow_the_changes() {
+        let mut history = ChangeHistory::new();
+        history.push(write(None, 1.0));
+        history.push(write(Some(1.0), 2.0));
+
+        assert_eq!(history.undo().as_ref().and_then(value), Some(1.0));
+        assert_eq!(history.undo().as_ref().and_then(value), None);
+        assert!(history.undo().is_none());
+        assert_eq!(history.redo().as_ref().and_then(value), Some(1.0));
+        assert_eq!(history.redo().as_ref().and_then(value), Some(2.0));
+        assert!(history.redo().is_none());
+    }
+
+    #[test]
+    fn empty_changes_are_not_recorded() {
+        let mut history = ChangeHistory::new();
+        history.push(Changes::Empty);
+        assert!(history.undo().is_none());
+        assert_eq!(history.entries().len(), 1);
+    }
+
+    #[test]
+    fn changes_after_an_undo_start_a_branch() {
+        let mut history = ChangeHistory::new();
+        history.push(write(None, 1.0));
+        history.push(write(Some(1.0), 2.0));
+        history.undo();
+        history.push(write(Some(1.0), 3.0));
+
+        // the undone change is not redone over the new one
+        assert!(history.redo().is_none());
+        assert_eq!(history.undo().as_ref().and_then(value), Some(1.0));
+        assert_eq!(history.redo().as_ref().and_then(value), Some(3.0));
+
+        let entries = history.entries();
+        let depths = entries
+            .iter()
+            .map(|entry| (entry.node, entry.depth))
+            .collect::<Vec<_>>();
+        assert_eq!(depths, vec![(0, 0), (1, 0), (2, 1), (3, 0)]);
+    }
+
+    #[test]
+    fn jump_goes_through_the_common_state() {
+        let mut history = ChangeHistory::new();
+        history.push(write(None, 1.0));
+        history.push(write(Some(1.0), 2.0));
+        history.undo();
+        history.push(write(Some(1.0), 3.0));
+
+        // from the 3 branch to the 2 branch
+        let steps = history.jump(2);
+        let values = steps.iter().map(value).collect::<Vec<_>>();
+        assert_eq!(values, vec![Some(1.0), Some(2.0)]);
+        assert_eq!(history.current(), 2);
+
+        // redo now follows the visited branch
+        history.undo();
+        assert_eq!(history.redo().as_ref().and_then(value), Some(2.0));
+
+        // back to the opened file
+        let values = history.jump(0).iter().map(value).collect::<Vec<_>>();
+        assert_eq!(values, vec![Some(1.0), None]);
+        assert!(history.jump(42).is_empty());
+        assert_eq!(history.current(), 0);
+    }
+
+    #[test]
+    fn saved_state_is_found_again_by_undo() {
+        let mut history = ChangeHistory::new();
+        assert!(history.is_saved());
+        history.push(write(None, 1.0));
+        assert!(!history.is_saved());
+        history.mark_saved();
+        history.push(write(Some(1.0), 2.0));
+        assert!(!history.is_saved());
+        history.undo();
+        assert!(history.is_saved());
+        history.undo();
+        assert!(!history.is_saved());
+        history.jump(1);
+        assert!(history.is_saved());
+    }
+
+    #[test]
+    fn selection_is_restored_with_the_changes() {
+        let before = cell::CellRect::new(0, 0, 1, 1);
+        let after = cell::CellRect::new(0, 1, 1, 1);
+        let selection = |changes: &Changes| {
+            changes.as_slice().iter().find_map(|change| match change {
+                Change::Selection { new_value, .. } => Some(*new_value),
+                _ => None,
+            })
+        };
+
+        let mut history = ChangeHistory::new();
+        history.record_selection(Some(before), Some(after));
+        assert_eq!(history.entries()[0].description, "opened file");
+        history.push(write(None, 1.0));
+        history.record_selection(Some(before), Some(after));
+
+        let undo = history.undo().unwrap();
+        assert_eq!(undo.as_slice().len(), 2);
+        assert_eq!(selection(&undo), Some(Some(before)));
+        assert_eq!(selection(&history.redo().unwrap()), Some(Some(after)));
+        assert_eq!(history.entries()[1].description, "A1 = 1");
+    }
+
+    #[test]
+    fn history_is_read_back_from_text() {
+        let mut history = ChangeHistory::new();
+        history.push(Changes::Group(vec![
+            Change::cell(
+                cell::CellIndex::new(1, 2),
+                Some(cell::Cell::Text("12".to_string())),
+                Some(cell::Cell::Text("tab\there\nand \\ line".to_string())),
+            ),
+            Change::cell(
+                cell::CellIndex::new(0, 0),
+                None,
+                Some(cell::Cell::parse("=SUM(A2:A4)", &Default::default())),
+            ),
+            Change::format(
+                cell::CellIndex::new(3, 0),
+                None,
+                Some(format::CellFormat {
+                    bold: true,
+                    foreground: Some(ratatui::style::Color::Indexed(58)),
+                    alignment: Some(ratatui::layout::Alignment::Right),
+                    borders: ratatui::widgets::Borders::LEFT | ratatui::widgets::Borders::TOP,
+                    number: format::NumberFormat::parse("#,##0.00 \"EUR\"").ok(),
+                    ..Default::default()
+                }),
+            ),
+        ]));
+        history.record_selection(None, Some(cell::CellRect::new(1, 2, 3, 4)));
+        history.push(Changes::Atomic(Change::Lines(cell::LineShift {
+            axis: cell::Axis::Columns,
+            at: 2,
+            count: 3,
+            inserted: true,
+        })));
+        history.undo();
+        history.push(Changes::Atomic(Change::Rules {
+            previous_value: Vec::new(),
+            new_value: vec![format::conditional::Rule::parse("A1:A9 > 5 bg=Red bold").unwrap()],
+        }));
+
+        let text = history.to_text("a;b\n");
+        let read = ChangeHistory::from_text(&text, "a;b\n").unwrap();
+        assert_eq!(read.to_text("a;b\n"), text);
+        assert_eq!(read.current(), history.current());
+        for (read, node) in read.nodes.iter().zip(&history.nodes) {
+            assert_eq!(read.parent, node.parent);
+            assert_eq!(read.changes, node.changes);
+        }
+
+        assert!(ChangeHistory::from_text(&text, "a;c\n").is_err());
+        assert!(ChangeHistory::from_text("something else", "a;b\n").is_err());
+    }
+}
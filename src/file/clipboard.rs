@@ -0,0 +1,154 @@
+use crate::file::{cell, format, formula};
+
+/// Cells copied inside the application, keeping their types, formulas and formats.
+/// The same cells are also sent as text to the system clipboard, for other applications.
+pub struct Clipboard {
+    /// Where the cells were copied from, to shift relative references when pasting.
+    pub source: cell::CellRect,
+    /// Content and format of every copied cell, row by row.
+    pub cells: Vec<(Option<cell::Cell>, Option<format::CellFormat>)>,
+    /// Text sent to the system clipboard, to recognize it when the terminal pastes it back.
+    pub text: String,
+    /// Whether the cells were cut, so the next paste moves them instead.
+    pub cut: bool,
+}
+
+impl Clipboard {
+    /// Whether a text pasted by the terminal is the one we copied.
+    pub fn matches(&self, paste_buffer: &str) -> bool {
+        // terminals may send pasted line breaks as carriage returns
+        let normalize = |text: &str| text.replace("\r\n", "\n").replace('\r', "\n");
+        normalize(&self.text) == normalize(paste_buffer)
+    }
+
+    /// Size of the pasted block, columns and rows swapped when transposed.
+    pub fn pasted_size(&self, transpose: bool) -> (u64, u64) {
+        match transpose {
+            false => (self.source.width, self.source.height),
+            true => (self.source.height, self.source.width),
+        }
+    }
+
+    /// Copied cells as they land when pasted with their top left cell at `to`.
+    /// Relative references are shifted by the move of their own cell, and transposed along, as in
+    /// Excel. The ones that would leave the sheet become `#REF!`.
+    pub fn pasted_at(&self, to: cell::CellIndex, transpose: bool) -> Vec<PastedCell> {
+        self.source
+            .indices()
+            .zip(&self.cells)
+            .map(|(source, (content, format))| {
+                let (offset_x, offset_y) = (source.x - self.source.x, source.y - self.source.y);
+                let index = match transpose {
+                    false => cell::CellIndex::new(
+                        to.x.saturating_add(offset_x),
+                        to.y.saturating_add(offset_y),
+                    ),
+                    true => cell::CellIndex::new(
+                        to.x.saturating_add(offset_y),
+                        to.y.saturating_add(offset_x),
+                    ),
+                };
+                let dx = index.x as i64 - source.x as i64;
+                let dy = index.y as i64 - source.y as i64;
+                let content = content.as_ref().map(|content| match content {
+                    cell::Cell::Formula(formula::Formula::Expr(expr)) => {
+                        cell::Cell::Formula(formula::Formula::Expr(expr.map_references(
+                            &|reference, _| match transpose {
+                                false => reference.offset(dx, dy),
+                                true => reference.transposed(source, index),
+                            },
+                        )))
+                    }
+                    other => other.clone(),
+                });
+                PastedCell {
+                    source,
+                    index,
+                    content,
+                    format: format.clone(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// A copied cell, where it is pasted.
+pub struct PastedCell {
+    pub source: cell::CellIndex,
+    pub index: cell::CellIndex,
+    pub content: Option<cell::Cell>,
+    pub format: Option<format::CellFormat>,
+}
+
+/// Which parts of the copied cells are pasted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PastedParts {
+    #[default]
+    All,
+    /// Content, with formulas replaced by their values.
+    Values,
+    /// Content as written, without formats.
+    Formulas,
+    Formats,
+}
+
+/// How to paste the copied cells, chosen with paste special.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PasteOptions {
+    pub parts: PastedParts,
+    /// Swap rows and columns.
+    pub transpose: bool,
+    /// Keep the existing cells where the copied ones are empty.
+    pub skip_blanks: bool,
+    /// Combine the copied values into the existing ones instead of replacing them.
+    pub operation: Option<formula::BinaryOperator>,
+}
+
+impl PasteOptions {
+    /// Read the options from letters: `v` values, `f` formulas, `o` formats, `t` transpose,
+    /// `b` skip blanks, and one of `+ - * /` to combine with the existing numbers.
+    pub fn parse(answer: &str) -> Result<PasteOptions, String> {
+        let mut options = PasteOptions::default();
+        for ch in answer.chars().filter(|ch| !ch.is_whitespace()) {
+            match ch.to_ascii_lowercase() {
+                'a' => options.parts = PastedParts::All,
+                'v' => options.parts = PastedParts::Values,
+                'f' => options.parts = PastedParts::Formulas,
+                'o' => options.parts = PastedParts::Formats,
+                't' => options.transpose = true,
+                'b' => options.skip_blanks = true,
+                '+' => options.operation = Some(formula::BinaryOperator::Add),
+                '-' => options.operation = Some(formula::BinaryOperator::Sub),
+                '*' => options.operation = Some(formula::BinaryOperator::Mul),
+                '/' => options.operation = Some(formula::BinaryOperator::Div),
+                other => return Err(format!("Unknown paste option \"{other}\"")),
+            }
+        }
+        Ok(options)
+    }
+}
+
+impl std::fmt::Display for PasteOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut options = vec![match self.parts {
+            PastedParts::All => "all",
+            PastedParts::Values => "values",
+            PastedParts::Formulas => "formulas",
+            PastedParts::Formats => "formats",
+        }];
+        if self.transpose {
+            options.push("transposed");
+        }
+        if self.skip_blanks {
+            options.push("skipping blanks");
+        }
+        match self.operation {
+            Some(formula::BinaryOperator::Add) => options.push("added"),
+            Some(formula::BinaryOperator::Sub) => options.push("subtracted"),
+            Some(formula::BinaryOperator::Mul) => options.push("multiplied"),
+            Some(formula::BinaryOperator::Div) => options.push("divided"),
+            _ => {}
+        }
+        write!(f, "{}", options.join(", "))
+    }
+}
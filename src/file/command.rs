@@ -1,4 +1,5 @@
 /// When moving (or validating, editing, etc..) cells, where do we go
+#[derive(Clone, Copy)]
 pub enum SelectionDirection {
     /// Don't move at all
     Stay,
@@ -8,6 +9,7 @@ pub enum SelectionDirection {
     Return,
 }
 
+#[derive(Clone)]
 pub enum Command {
     RedrawRequest,
     WriteCells {
@@ -22,16 +24,181 @@ pub enum Command {
     CopyCells {
         cells: crate::file::cell::CellRect,
     },
+    /// Copy the cells and mark them to be moved by the next paste.
+    CutCells {
+        cells: crate::file::cell::CellRect,
+    },
     PasteCells {
         cells: crate::file::cell::CellRect,
         paste_buffer: String,
     },
+    /// Paste the cells of the internal clipboard, with their top left cell on the given cells.
+    PasteClipboard {
+        cells: crate::file::cell::CellRect,
+        options: crate::file::clipboard::PasteOptions,
+    },
+    /// Move a block of cells, with their formats, so its top left cell lands on `to`.
+    MoveCells {
+        from: crate::file::cell::CellRect,
+        to: crate::file::cell::CellIndex,
+    },
+    FormatCells {
+        cells: crate::file::cell::CellRect,
+        change: crate::file::format::FormatChange,
+    },
+    /// Fill the cells from the first ones of each column or row, copying them or, for a
+    /// `series`, continuing the sequence they start.
+    FillCells {
+        cells: crate::file::cell::CellRect,
+        direction: crate::file::fill::FillDirection,
+        series: bool,
+    },
+    /// Insert or delete whole rows or columns.
+    ShiftLines {
+        shift: crate::file::cell::LineShift,
+    },
+    SetRules {
+        rules: Vec<crate::file::format::conditional::Rule>,
+    },
+    SetValidations {
+        validations: Vec<crate::file::validation::Validation>,
+    },
+    /// Merge the cells into one, keeping the content of the top left cell, or split the merged
+    /// cells they overlap.
+    MergeCells {
+        cells: crate::file::cell::CellRect,
+    },
+    /// Replace the names of cells and constants. Names renamed in place are renamed in the
+    /// formulas using them.
+    SetNames {
+        names: Vec<crate::file::names::Name>,
+    },
+    /// Add, edit or delete the note of a cell.
+    SetNote {
+        index: crate::file::cell::CellIndex,
+        note: Option<String>,
+    },
     Undo,
     Redo,
+    /// Apply the unsaved changes found when opening the file, as a single undoable change, or
+    /// discard them.
+    Recover {
+        accept: bool,
+    },
+    /// Write the file, unless another program changed it.
+    Save,
+    /// Leave the application, refusing to lose unsaved changes unless `force`d.
+    Quit {
+        /// Save the file first, not leaving if it fails.
+        save: bool,
+        force: bool,
+    },
+    /// Select the cells, scrolling to them.
+    Select {
+        cells: crate::file::cell::CellRect,
+    },
+    /// Select the cells written as `A1:B2`, or those of a name.
+    GoToCells {
+        target: String,
+    },
+    /// Select the next cell whose content or value contains the text, ignoring case.
+    Search {
+        text: String,
+        backwards: bool,
+    },
+    /// Write the sheet to another file, asking first when it exists unless `overwrite`.
+    SaveAs {
+        path: std::path::PathBuf,
+        overwrite: bool,
+    },
+    /// Write a copy of the sheet to another file, keeping editing this one, asking first when it
+    /// exists unless `overwrite`.
+    WriteCopy {
+        path: std::path::PathBuf,
+        overwrite: bool,
+    },
+    /// Write the values of the cells to a new file, asking first when it exists unless
+    /// `overwrite`.
+    ExportCells {
+        cells: crate::file::cell::CellRect,
+        path: std::path::PathBuf,
+        overwrite: bool,
+    },
+    /// Resolve a change of the file made by another program.
+    ResolveExternalChange {
+        resolution: crate::file::disk::Resolution,
+    },
+    /// Go to a state of the undo tree, undoing and redoing the changes on the way.
+    JumpHistory {
+        node: usize,
+    },
+}
+
+impl Command {
+    /// Whether the command changes cells in a way that can be repeated on other ones.
+    pub fn is_repeatable(&self) -> bool {
+        matches!(
+            self,
+            Command::WriteCells { .. }
+                | Command::DeleteCells { .. }
+                | Command::PasteCells { .. }
+                | Command::PasteClipboard { .. }
+                | Command::FormatCells { .. }
+                | Command::FillCells { .. }
+                | Command::ShiftLines { .. }
+        )
+    }
+
+    /// The same change, made on the given cells instead.
+    pub fn retargeted(&self, cells: crate::file::cell::CellRect) -> Command {
+        let mut command = self.clone();
+        match &mut command {
+            Command::WriteCells { cells: target, .. }
+            | Command::DeleteCells { cells: target, .. }
+            | Command::PasteCells { cells: target, .. }
+            | Command::PasteClipboard { cells: target, .. }
+            | Command::FormatCells { cells: target, .. }
+            | Command::FillCells { cells: target, .. } => *target = cells,
+            Command::ShiftLines { shift } => {
+                shift.at = match shift.axis {
+                    crate::file::cell::Axis::Rows => cells.y,
+                    crate::file::cell::Axis::Columns => cells.x,
+                }
+            }
+            _ => {}
+        }
+        command
+    }
 }
 
 impl super::FileApp {
     pub fn execute_command(&mut self, cmd: Command, info: &mut String) {
+        let selection = self.viewport.selected_cells();
+        let (state, state_count) = (self.changes.current(), self.changes.state_count());
+        // vim mode repeats the last change with `.`
+        let repeatable = (self.vim.is_some() && cmd.is_repeatable()).then(|| cmd.clone());
+        self.run_command(cmd, info);
+        if self.changes.current() != state
+            && let (Some(vim), Some(change)) = (&mut self.vim, repeatable)
+        {
+            vim.last_change = Some(change);
+        }
+        // new changes bring back the selection they were made on when undone
+        if self.changes.state_count() > state_count {
+            self.changes
+                .record_selection(selection, self.viewport.selected_cells());
+        }
+        self.saved = self.changes.is_saved();
+        // journal what changed since the autosave, in case of a crash before the next one
+        if self.changes.current() != state && self.recovery.is_none() {
+            let steps = self.changes.path(state, self.changes.current());
+            if let Err(e) = super::recovery::append_journal(&self.path, &steps) {
+                *info = format!("Failed to write the journal: {e}");
+            }
+        }
+    }
+
+    fn run_command(&mut self, cmd: Command, info: &mut String) {
         use super::cell;
         use super::change_history;
 
@@ -41,11 +208,22 @@ impl super::FileApp {
                 content,
                 next_selection,
             } => {
+                let written = cells
+                    .indices()
+                    .map(|index| (index, Some(content.clone())))
+                    .collect();
+                let warning = match self.validate(&written) {
+                    Ok(warning) => warning,
+                    Err(reason) => {
+                        *info = format!("Rejected: {reason}");
+                        return;
+                    }
+                };
                 let mut changes = Vec::new();
                 for cell_x in cells.x..cells.x + cells.width {
                     for cell_y in cells.y..cells.y + cells.height {
                         let key = cell::CellIndex::new(cell_x, cell_y);
-                        changes.push(change_history::Change::new(
+                        changes.push(change_history::Change::cell(
                             key,
                             self.content.get(&key).cloned(),
                             Some(content.clone()),
@@ -54,105 +232,133 @@ impl super::FileApp {
                     }
                 }
 
-                let changes = match changes.len() {
-                    0 => change_history::Changes::Empty,
-                    1 => change_history::Changes::Atomic(changes.pop().unwrap()),
-                    _ => change_history::Changes::Group(changes),
-                };
-                self.changes.push(changes);
+                self.changes
+                    .push(change_history::Changes::from_vec(changes));
 
-                self.viewport.selection = match next_selection {
-                    SelectionDirection::Stay => Some(cells),
-                    SelectionDirection::Next => Some(cell::CellRect::new(
-                        cells.x.saturating_add(cells.width),
+                // the next cell after a merged one is past its whole area
+                let stop = match cells.count() {
+                    1 => self
+                        .merge_at(cell::CellIndex::new(cells.x, cells.y))
+                        .unwrap_or(cells),
+                    _ => cells,
+                };
+                self.viewport.select(match next_selection {
+                    SelectionDirection::Stay => cells,
+                    SelectionDirection::Next => cell::CellRect::new(
+                        stop.x.saturating_add(stop.width),
                         cells.y,
                         cells.width,
                         cells.height,
-                    )),
-                    SelectionDirection::Return => Some(cell::CellRect::new(
+                    ),
+                    SelectionDirection::Return => cell::CellRect::new(
                         cells.x,
-                        cells.y.saturating_add(cells.height),
+                        stop.y.saturating_add(stop.height),
                         cells.width,
                         cells.height,
-                    )),
-                };
-                self.saved = false;
+                    ),
+                });
                 *info = format!("Wrote {cells} ({} cells)", cells.count());
+                if let Some(warning) = warning {
+                    *info = format!("{info}, but {warning}");
+                }
             }
             Command::DeleteCells {
                 cells,
                 next_selection,
             } => {
+                let ranges = self.ranges_of(cells);
                 let mut changes = Vec::new();
-                for cell_x in cells.x..cells.x + cells.width {
-                    for cell_y in cells.y..cells.y + cells.height {
-                        let key = cell::CellIndex::new(cell_x, cell_y);
-                        match self.content.get(&key) {
-                            Some(prev) => changes.push(change_history::Change::new(
-                                key,
-                                Some(prev.clone()),
-                                None,
-                            )),
-                            None => { /* no changes, from empty to empty */ }
-                        };
-                        self.content.remove(&key);
-                    }
+                for key in ranges.iter().flat_map(|range| range.indices()) {
+                    match self.content.get(&key) {
+                        Some(prev) => changes.push(change_history::Change::cell(
+                            key,
+                            Some(prev.clone()),
+                            None,
+                        )),
+                        None => { /* no changes, from empty to empty */ }
+                    };
+                    self.content.remove(&key);
                 }
 
-                let changes = match changes.len() {
-                    0 => change_history::Changes::Empty,
-                    1 => change_history::Changes::Atomic(changes.pop().unwrap()),
-                    _ => change_history::Changes::Group(changes),
-                };
-                self.changes.push(changes);
+                self.changes
+                    .push(change_history::Changes::from_vec(changes));
 
-                self.viewport.selection = match next_selection {
-                    SelectionDirection::Stay => Some(cells),
-                    SelectionDirection::Next => Some(cell::CellRect::new(
+                if ranges.len() > 1 {
+                    let count = ranges.iter().map(|range| range.count()).sum::<u64>();
+                    *info = format!("Deleted {} ({count} cells)", cells_list(&ranges));
+                    return;
+                }
+                self.viewport.select(match next_selection {
+                    SelectionDirection::Stay => cells,
+                    SelectionDirection::Next => cell::CellRect::new(
                         cells.x.saturating_add(cells.width),
                         cells.y,
                         cells.width,
                         cells.height,
-                    )),
-                    SelectionDirection::Return => Some(cell::CellRect::new(
+                    ),
+                    SelectionDirection::Return => cell::CellRect::new(
                         cells.x,
                         cells.y.saturating_add(cells.height),
                         cells.width,
                         cells.height,
-                    )),
-                };
-                self.saved = false;
+                    ),
+                });
                 *info = format!("Deleted {cells} ({} cells)", cells.count());
             }
-            Command::CopyCells { cells } => {
-                let data = (cells.y..cells.y + cells.height)
-                    .map(|cell_y| {
-                        (cells.x..cells.x + cells.width)
-                            .map(|cell_x| {
-                                match self.content.get(&cell::CellIndex::new(cell_x, cell_y)) {
-                                    Some(cell) => cell.to_string(),
-                                    None => String::with_capacity(0),
-                                }
-                            })
-                            .collect::<Vec<_>>()
-                            .join("\t")
-                    })
-                    .collect::<Vec<_>>()
-                    .join("\n");
-                let bytes = data.as_bytes().len();
-                match crossterm::execute!(
-                    std::io::stdout(),
-                    crossterm::clipboard::CopyToClipboard::to_clipboard_from(data)
-                ) {
-                    Ok(_) => *info = format!("Copied {bytes} bytes to clipboard"),
-                    Err(e) => *info = format!("Failed to copy to clipboard: {e}"),
-                }
+            Command::CopyCells { cells } => self.copy_to_clipboard(cells, false, info),
+            Command::CutCells { cells } => {
+                self.copy_to_clipboard(cells, true, info);
+                *info = format!("Cut {cells}, paste to move them");
             }
+            // the terminal pastes back our own copy: use the cells kept with their types
+            Command::PasteCells {
+                cells,
+                ref paste_buffer,
+            } if self
+                .clipboard
+                .as_ref()
+                .is_some_and(|clipboard| clipboard.matches(paste_buffer)) =>
+            {
+                self.run_command(
+                    Command::PasteClipboard {
+                        cells,
+                        options: super::clipboard::PasteOptions::default(),
+                    },
+                    info,
+                )
+            }
+            Command::PasteClipboard { cells, options } => match self.clipboard.take() {
+                None => *info = "Nothing to paste".to_string(),
+                Some(clipboard) if clipboard.cut && options != Default::default() => {
+                    *info = "Cut cells can only be moved, paste them without options".to_string();
+                    self.clipboard = Some(clipboard);
+                }
+                Some(clipboard) if clipboard.cut => self.run_command(
+                    Command::MoveCells {
+                        from: clipboard.source,
+                        to: cell::CellIndex::new(cells.x, cells.y),
+                    },
+                    info,
+                ),
+                Some(clipboard) => {
+                    match self.paste_clipboard(&clipboard, cells, options) {
+                        Ok((pasted, warning)) => {
+                            self.viewport.select(pasted);
+                            *info = format!("Pasted {} to {pasted} ({options})", clipboard.source);
+                            if let Some(warning) = warning {
+                                *info = format!("{info}, but {warning}");
+                            }
+                        }
+                        Err(reason) => *info = format!("Rejected: {reason}"),
+                    }
+                    self.clipboard = Some(clipboard);
+                }
+            },
             Command::PasteCells {
                 cells,
                 paste_buffer,
             } => {
-                let mut changes = Vec::new();
+                let mut written = std::collections::BTreeMap::new();
                 for (offset_y, row) in paste_buffer.lines().enumerate() {
                     for (offset_x, content) in row.split('\t').enumerate() {
                         let key = cell::CellIndex::new(
@@ -163,78 +369,1013 @@ impl super::FileApp {
                                 .y
                                 .saturating_add(u64::try_from(offset_y).unwrap_or(u64::MAX)),
                         );
-                        let cell = cell::Cell::parse(content);
-                        changes.push(change_history::Change::new(
-                            key,
-                            self.content.get(&key).cloned(),
-                            Some(cell.clone()),
-                        ));
+                        written.insert(key, Some(cell::Cell::parse(content, &self.locale)));
+                    }
+                }
+                let warning = match self.validate(&written) {
+                    Ok(warning) => warning,
+                    Err(reason) => {
+                        *info = format!("Rejected: {reason}");
+                        return;
+                    }
+                };
+                let mut changes = Vec::new();
+                for (key, cell) in written {
+                    changes.push(change_history::Change::cell(
+                        key,
+                        self.content.get(&key).cloned(),
+                        cell.clone(),
+                    ));
+                    if let Some(cell) = cell {
                         self.content.insert(key, cell);
                     }
                 }
-                let changes = match changes.len() {
-                    0 => change_history::Changes::Empty,
-                    1 => change_history::Changes::Atomic(changes.pop().unwrap()),
-                    _ => change_history::Changes::Group(changes),
+                self.changes
+                    .push(change_history::Changes::from_vec(changes));
+                *info = format!("Pasted {} bytes", paste_buffer.len());
+                if let Some(warning) = warning {
+                    *info = format!("{info}, but {warning}");
+                }
+            }
+            Command::MoveCells { from, to } => {
+                let dx = to.x as i64 - from.x as i64;
+                let dy = to.y as i64 - from.y as i64;
+                let Some(destination) = from.offset(dx, dy) else {
+                    return;
                 };
-                self.changes.push(changes);
-                *info = format!("Pasted {} bytes", paste_buffer.bytes().len());
-            }
-            Command::Undo => {
-                let mut updated_cell_count = 0;
-                match self.changes.last_changes() {
-                    None => *info = format!("No changes to undo!"),
-                    Some(change_history::Changes::Empty) => {
-                        *info = format!("Undo changes with no impacts")
-                    }
-                    Some(change_history::Changes::Atomic(change)) => {
-                        match &change.previous_value {
-                            Some(value) => self.content.insert(change.index, value.clone()),
-                            None => self.content.remove(&change.index),
+                let follow = |reference: &super::formula::Reference, role| {
+                    reference.moved(from, dx, dy, role)
+                };
+
+                // new values by index, to record a single change per cell
+                let mut new_cells = std::collections::BTreeMap::new();
+                for (index, content) in &self.content {
+                    if let cell::Cell::Formula(super::formula::Formula::Expr(expr)) = content {
+                        let moved = expr.map_references(&follow);
+                        if moved != *expr {
+                            new_cells.insert(
+                                *index,
+                                Some(cell::Cell::Formula(super::formula::Formula::Expr(moved))),
+                            );
+                        }
+                    }
+                }
+                let moved_cells = from
+                    .indices()
+                    .map(|index| {
+                        let content = match new_cells.get(&index) {
+                            Some(content) => content.clone(),
+                            None => self.content.get(&index).cloned(),
                         };
-                        updated_cell_count += 1;
+                        (
+                            index,
+                            content,
+                            self.formats.get(&index).cloned(),
+                            self.notes.get(&index).cloned(),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                let mut new_formats = std::collections::BTreeMap::new();
+                let mut new_notes = std::collections::BTreeMap::new();
+                for index in from.indices() {
+                    new_cells.insert(index, None);
+                    new_formats.insert(index, None);
+                    new_notes.insert(index, None);
+                }
+                for (index, content, format, note) in moved_cells {
+                    if let Some(index) = index.offset(dx, dy) {
+                        new_cells.insert(index, content);
+                        new_formats.insert(index, format);
+                        new_notes.insert(index, note);
                     }
-                    Some(change_history::Changes::Group(changes)) => {
-                        for change in changes {
-                            match &change.previous_value {
-                                Some(value) => self.content.insert(change.index, value.clone()),
-                                None => self.content.remove(&change.index),
+                }
+
+                let mut changes = self.note_changes(new_notes);
+                let rules = self
+                    .rules
+                    .iter()
+                    .map(|rule| rule.moved(from, dx, dy))
+                    .collect::<Vec<_>>();
+                if rules != self.rules {
+                    changes.push(change_history::Change::Rules {
+                        previous_value: self.rules.clone(),
+                        new_value: rules,
+                    });
+                }
+                let validations = self
+                    .validations
+                    .iter()
+                    .map(|validation| validation.moved(from, dx, dy))
+                    .collect::<Vec<_>>();
+                // moved values are checked against the validations as they will be after the move
+                let warning = match self.validate_against(&new_cells, &validations) {
+                    Ok(warning) => warning,
+                    Err(reason) => {
+                        *info = format!("Rejected: {reason}");
+                        return;
+                    }
+                };
+                if validations != self.validations {
+                    changes.push(change_history::Change::Validations {
+                        previous_value: self.validations.clone(),
+                        new_value: validations,
+                    });
+                }
+                // merges inside the block follow it, those it lands on are split
+                let merges = self
+                    .merges
+                    .iter()
+                    .filter_map(|merge| {
+                        let inside = from.contains(cell::CellIndex::new(merge.x, merge.y))
+                            && from.contains(cell::CellIndex::new(
+                                merge.x + merge.width - 1,
+                                merge.y + merge.height - 1,
+                            ));
+                        match inside {
+                            true => merge.offset(dx, dy),
+                            false => (!merge.intersects(destination)).then_some(*merge),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                if merges != self.merges {
+                    changes.push(change_history::Change::Merges {
+                        previous_value: self.merges.clone(),
+                        new_value: merges,
+                    });
+                }
+                let names = self
+                    .names
+                    .iter()
+                    .map(|name| name.moved(from, dx, dy))
+                    .collect::<Vec<_>>();
+                if names != self.names {
+                    changes.push(change_history::Change::Names {
+                        previous_value: self.names.clone(),
+                        new_value: names,
+                    });
+                }
+                self.replace_cells(new_cells, new_formats, changes);
+                self.viewport.select(destination);
+                *info = format!("Moved {from} to {destination}");
+                if let Some(warning) = warning {
+                    *info = format!("{info}, but {warning}");
+                }
+            }
+            Command::FormatCells { cells, change } => {
+                let ranges = self.ranges_of(cells);
+                let mut changes = Vec::new();
+                for range in &ranges {
+                    for key in range.indices() {
+                        let previous = self.formats.get(&key).cloned();
+                        let mut format = previous.clone().unwrap_or_default();
+                        change.apply(&mut format, key, *range);
+                        let format = (!format.is_default()).then_some(format);
+                        if previous != format {
+                            changes.push(change_history::Change::format(
+                                key,
+                                previous,
+                                format.clone(),
+                            ));
+                            match format {
+                                Some(format) => self.formats.insert(key, format),
+                                None => self.formats.remove(&key),
                             };
-                            updated_cell_count += 1;
                         }
                     }
                 }
-                self.changes.undo();
-                *info = format!("Undo: updated {updated_cell_count} cells");
+                self.changes
+                    .push(change_history::Changes::from_vec(changes));
+                *info = format!("Formatted {}: {change}", cells_list(&ranges));
             }
-            Command::Redo => {
-                let mut updated_cell_count = 0;
-                match self.changes.last_undos() {
-                    None => *info = format!("No changes to redo!"),
-                    Some(change_history::Changes::Empty) => {
-                        *info = format!("Redo changes with no impacts")
+            Command::FillCells {
+                cells,
+                direction,
+                series,
+            } => {
+                let cells = direction.extended(cells);
+                let (count, warning) = match self.fill_cells(cells, direction, series) {
+                    Ok(filled) => filled,
+                    Err(reason) => {
+                        *info = format!("Rejected: {reason}");
+                        return;
                     }
-                    Some(change_history::Changes::Atomic(change)) => {
-                        match &change.previous_value {
-                            Some(value) => self.content.insert(change.index, value.clone()),
-                            None => self.content.remove(&change.index),
+                };
+                *info = match series {
+                    true => format!("Filled a series of {count} cells {direction} in {cells}"),
+                    false => format!("Filled {count} cells {direction} in {cells}"),
+                };
+                if let Some(warning) = warning {
+                    *info = format!("{info}, but {warning}");
+                }
+            }
+            Command::ShiftLines { shift } => {
+                use super::formula::Formula;
+
+                // deleted cells and rewritten formulas are recorded at their current position,
+                // before the move, so they can be restored once undoing moved the cells back
+                let mut changes = Vec::new();
+                for (index, cell) in &self.content {
+                    match (shift.index(*index), cell) {
+                        (None, _) => changes.push(change_history::Change::cell(
+                            *index,
+                            Some(cell.clone()),
+                            None,
+                        )),
+                        (Some(_), cell::Cell::Formula(Formula::Expr(expr))) => {
+                            let shifted = expr
+                                .map_references(&|reference, role| reference.shifted(&shift, role));
+                            if shifted != *expr {
+                                changes.push(change_history::Change::cell(
+                                    *index,
+                                    Some(cell.clone()),
+                                    Some(cell::Cell::Formula(Formula::Expr(shifted))),
+                                ));
+                            }
+                        }
+                        (Some(_), _) => {}
+                    }
+                }
+                for (index, format) in &self.formats {
+                    if shift.index(*index).is_none() {
+                        changes.push(change_history::Change::format(
+                            *index,
+                            Some(format.clone()),
+                            None,
+                        ));
+                    }
+                }
+                for (index, note) in &self.notes {
+                    if shift.index(*index).is_none() {
+                        changes.push(change_history::Change::Note {
+                            index: *index,
+                            previous_value: Some(note.clone()),
+                            new_value: None,
+                        });
+                    }
+                }
+                let rules = self
+                    .rules
+                    .iter()
+                    .filter_map(|rule| rule.shifted(&shift))
+                    .collect::<Vec<_>>();
+                if rules != self.rules {
+                    changes.push(change_history::Change::Rules {
+                        previous_value: self.rules.clone(),
+                        new_value: rules,
+                    });
+                }
+                let validations = self
+                    .validations
+                    .iter()
+                    .filter_map(|validation| validation.shifted(&shift))
+                    .collect::<Vec<_>>();
+                if validations != self.validations {
+                    changes.push(change_history::Change::Validations {
+                        previous_value: self.validations.clone(),
+                        new_value: validations,
+                    });
+                }
+                changes.push(change_history::Change::Lines(shift));
+                // merges left with a single cell are no longer merged
+                let merges = self
+                    .merges
+                    .iter()
+                    .filter_map(|merge| shift.rect(*merge))
+                    .filter(|merge| merge.count() > 1)
+                    .collect::<Vec<_>>();
+                if merges != self.merges {
+                    changes.push(change_history::Change::Merges {
+                        previous_value: self.merges.clone(),
+                        new_value: merges,
+                    });
+                }
+                // names of deleted cells are kept, as `#REF!`, for the formulas using them
+                let names = self
+                    .names
+                    .iter()
+                    .map(|name| name.shifted(&shift))
+                    .collect::<Vec<_>>();
+                if names != self.names {
+                    changes.push(change_history::Change::Names {
+                        previous_value: self.names.clone(),
+                        new_value: names,
+                    });
+                }
+
+                let changes = change_history::Changes::from_vec(changes);
+                self.apply_changes(&changes);
+                self.changes.push(changes);
+                *info = match shift.inserted {
+                    true => format!("Inserted {shift}"),
+                    false => format!("Deleted {shift}"),
+                };
+            }
+            Command::SetRules { rules } => {
+                let previous_value = std::mem::replace(&mut self.rules, rules.clone());
+                self.changes.push(change_history::Changes::Atomic(
+                    change_history::Change::Rules {
+                        previous_value,
+                        new_value: rules,
+                    },
+                ));
+                *info = format!(
+                    "Updated conditional formatting ({} rules)",
+                    self.rules.len()
+                );
+            }
+            Command::SetValidations { validations } => {
+                let previous_value = std::mem::replace(&mut self.validations, validations.clone());
+                self.changes.push(change_history::Changes::Atomic(
+                    change_history::Change::Validations {
+                        previous_value,
+                        new_value: validations,
+                    },
+                ));
+                *info = format!("Updated data validation ({} rules)", self.validations.len());
+            }
+            Command::MergeCells { cells } => {
+                let (split, kept): (Vec<_>, Vec<_>) = self
+                    .merges
+                    .iter()
+                    .partition(|merge| merge.intersects(cells));
+                let (new_cells, merges) = match split.as_slice() {
+                    [] if cells.count() < 2 => {
+                        *info = "Select the cells to merge".to_string();
+                        return;
+                    }
+                    // like other spreadsheets, only the top left content is kept
+                    [] => {
+                        let new_cells = cells
+                            .indices()
+                            .skip(1)
+                            .filter(|index| self.content.contains_key(index))
+                            .map(|index| (index, None))
+                            .collect::<std::collections::BTreeMap<_, _>>();
+                        *info = match new_cells.len() {
+                            0 => format!("Merged {cells}"),
+                            cleared => format!("Merged {cells}, clearing {cleared} other cells"),
                         };
-                        updated_cell_count += 1;
+                        let mut merges = kept;
+                        merges.push(cells);
+                        (new_cells, merges)
                     }
-                    Some(change_history::Changes::Group(changes)) => {
-                        for change in changes {
-                            match &change.previous_value {
-                                Some(value) => self.content.insert(change.index, value.clone()),
-                                None => self.content.remove(&change.index),
-                            };
-                            updated_cell_count += 1;
+                    split => {
+                        *info = format!(
+                            "Unmerged {}",
+                            split
+                                .iter()
+                                .map(|merge| merge.to_string())
+                                .collect::<Vec<_>>()
+                                .join(" ")
+                        );
+                        (Default::default(), kept)
+                    }
+                };
+                let changes = vec![change_history::Change::Merges {
+                    previous_value: self.merges.clone(),
+                    new_value: merges,
+                }];
+                self.replace_cells(new_cells, Default::default(), changes);
+            }
+            Command::SetNames { names } => {
+                use super::formula::Formula;
+
+                // a name replaced by another one at the same place was renamed
+                let renames = match names.len() == self.names.len() {
+                    true => self
+                        .names
+                        .iter()
+                        .zip(&names)
+                        .filter(|(old, new)| {
+                            !old.name.eq_ignore_ascii_case(&new.name)
+                                && super::names::find(&names, &old.name).is_none()
+                        })
+                        .map(|(old, new)| (old.name.clone(), new.name.clone()))
+                        .collect::<Vec<_>>(),
+                    false => Vec::new(),
+                };
+                let mut new_cells = std::collections::BTreeMap::new();
+                for (index, content) in &self.content {
+                    if let cell::Cell::Formula(Formula::Expr(expr)) = content {
+                        let renamed = renames
+                            .iter()
+                            .fold(expr.clone(), |expr, (from, to)| expr.renamed(from, to));
+                        if renamed != *expr {
+                            new_cells
+                                .insert(*index, Some(cell::Cell::Formula(Formula::Expr(renamed))));
+                        }
+                    }
+                }
+                *info = match renames.as_slice() {
+                    [(from, to)] => {
+                        format!("Renamed {from} to {to} in {} formulas", new_cells.len())
+                    }
+                    _ => format!("Updated names ({} names)", names.len()),
+                };
+                let changes = vec![change_history::Change::Names {
+                    previous_value: self.names.clone(),
+                    new_value: names,
+                }];
+                self.replace_cells(new_cells, Default::default(), changes);
+            }
+            Command::SetNote { index, note } => {
+                let previous_value = self.notes.get(&index).cloned();
+                *info = match (&previous_value, &note) {
+                    (None, None) => return,
+                    (None, Some(_)) => format!("Added a note to {index}"),
+                    (Some(_), Some(_)) => format!("Edited the note of {index}"),
+                    (Some(_), None) => format!("Deleted the note of {index}"),
+                };
+                let change = change_history::Change::Note {
+                    index,
+                    previous_value,
+                    new_value: note,
+                };
+                let changes = change_history::Changes::Atomic(change);
+                self.apply_changes(&changes);
+                self.changes.push(changes);
+            }
+            Command::Undo => match self.changes.undo() {
+                None => *info = "No changes to undo!".to_string(),
+                Some(changes) => {
+                    let updated_cell_count = self.apply_changes(&changes);
+                    *info = format!("Undo: updated {updated_cell_count} cells");
+                }
+            },
+            Command::Redo => match self.changes.redo() {
+                None => *info = "No changes to redo!".to_string(),
+                Some(changes) => {
+                    let updated_cell_count = self.apply_changes(&changes);
+                    *info = format!("Redo: updated {updated_cell_count} cells");
+                }
+            },
+            Command::Recover { accept } => match (self.recovery.take(), accept) {
+                (Some(recovered), true) => {
+                    // the recovered changes are journaled again, from the file itself
+                    super::recovery::remove(&self.path);
+                    let new_cells = replacing(&self.content, &recovered.content);
+                    let new_formats = replacing(&self.formats, &recovered.formats);
+                    let mut changes = self.note_changes(replacing(&self.notes, &recovered.notes));
+                    if recovered.rules != self.rules {
+                        changes.push(change_history::Change::Rules {
+                            previous_value: self.rules.clone(),
+                            new_value: recovered.rules,
+                        });
+                    }
+                    if recovered.validations != self.validations {
+                        changes.push(change_history::Change::Validations {
+                            previous_value: self.validations.clone(),
+                            new_value: recovered.validations,
+                        });
+                    }
+                    if recovered.merges != self.merges {
+                        changes.push(change_history::Change::Merges {
+                            previous_value: self.merges.clone(),
+                            new_value: recovered.merges,
+                        });
+                    }
+                    if recovered.names != self.names {
+                        changes.push(change_history::Change::Names {
+                            previous_value: self.names.clone(),
+                            new_value: recovered.names,
+                        });
+                    }
+                    self.replace_cells(new_cells, new_formats, changes);
+                    *info = "Recovered the unsaved changes, Ctrl+S to save them".to_string();
+                }
+                (Some(_), false) => {
+                    super::recovery::remove(&self.path);
+                    *info = "Discarded the unsaved changes".to_string();
+                }
+                (None, _) => {}
+            },
+            Command::Save => {
+                self.save_checked(info);
+            }
+            Command::Quit { save, force } => {
+                if save && !self.save_checked(info) {
+                    return;
+                }
+                match (force, self.saved) {
+                    (_, true) => self.quit_requested = true,
+                    (true, false) => {
+                        // the discarded changes must not be offered for recovery next time
+                        if self.recovery.is_none() {
+                            super::recovery::remove(&self.path);
                         }
+                        self.quit_requested = true;
+                    }
+                    (false, false) => {
+                        *info = "No write since last change, :q! to quit anyway".to_string();
+                    }
+                }
+            }
+            Command::Select { cells } => {
+                self.viewport.select(cells);
+                self.viewport.keep_selection_in_view();
+                *info = format!("Selected {cells} ({} cells)", cells.count());
+            }
+            Command::GoToCells { target } => {
+                let named = super::names::find(&self.names, target.trim()).and_then(|name| {
+                    match name.target {
+                        super::names::Target::Cells(cells) => Some(cells),
+                        _ => None,
+                    }
+                });
+                match cell::CellRect::parse(&target).or(named) {
+                    Some(cells) => self.run_command(Command::Select { cells }, info),
+                    None => *info = format!("No cells or name of cells \"{target}\""),
+                }
+            }
+            Command::Search { text, backwards } => {
+                let from = self
+                    .viewport
+                    .selection
+                    .as_ref()
+                    .map_or(cell::CellIndex::new(0, 0), |selection| selection.anchor);
+                match self.find_cell(&text, from, backwards) {
+                    Some(index) => {
+                        self.viewport.selection = Some(super::selection::Selection::cell(index));
+                        self.viewport.keep_selection_in_view();
+                        *info = format!("Found \"{text}\" in {index}");
+                    }
+                    None => *info = format!("\"{text}\" not found"),
+                }
+                if let Some(vim) = &mut self.vim {
+                    vim.search = Some(text);
+                }
+            }
+            Command::WriteCopy { path, .. } if path == self.path => {
+                self.save_checked(info);
+            }
+            Command::SaveAs {
+                ref path,
+                overwrite: false,
+            }
+            | Command::WriteCopy {
+                ref path,
+                overwrite: false,
+            }
+            | Command::ExportCells {
+                ref path,
+                overwrite: false,
+                ..
+            } if path.exists() && *path != self.path => {
+                *info = format!("{path:?} already exists");
+                let question = format!("Overwrite {path:?}? y/n: ");
+                let confirmed = match cmd {
+                    Command::SaveAs { path, .. } => Command::SaveAs {
+                        path,
+                        overwrite: true,
+                    },
+                    Command::WriteCopy { path, .. } => Command::WriteCopy {
+                        path,
+                        overwrite: true,
+                    },
+                    Command::ExportCells { cells, path, .. } => Command::ExportCells {
+                        cells,
+                        path,
+                        overwrite: true,
+                    },
+                    other => other,
+                };
+                self.state = super::state::State::Prompt(super::state::PromptState::new(
+                    super::state::PromptKind::Overwrite {
+                        cells: self
+                            .viewport
+                            .selected_cells()
+                            .unwrap_or(cell::CellRect::new(0, 0, 1, 1)),
+                        question,
+                        command: Box::new(confirmed),
+                    },
+                    "",
+                ));
+            }
+            Command::SaveAs { path, .. } => match self.save_as(path.clone()) {
+                Ok((bytes, true)) => *info = format!("Saved {bytes} bytes to {:?}", self.path),
+                Ok((bytes, false)) => *info = format!("Exported {bytes} bytes to {path:?}"),
+                Err(e) => *info = format!("Failed to save to {path:?}: {e}"),
+            },
+            Command::WriteCopy { path, .. } => match self.write_copy(&path) {
+                Ok(bytes) => *info = format!("Wrote a copy of {bytes} bytes to {path:?}"),
+                Err(e) => *info = format!("Failed to write to {path:?}: {e}"),
+            },
+            Command::ExportCells { cells, path, .. } => match self.export_cells(cells, &path) {
+                Ok(bytes) => *info = format!("Exported {bytes} bytes of {cells} to {path:?}"),
+                Err(e) => *info = format!("Failed to export to {path:?}: {e}"),
+            },
+            Command::ResolveExternalChange { resolution } => {
+                use super::disk::Resolution;
+
+                let disk = match self.read_disk() {
+                    Ok(disk) => disk,
+                    Err(e) => {
+                        *info = format!("Failed to read {:?}: {e}", self.path);
+                        return;
+                    }
+                };
+                let theirs = &disk.content;
+                match resolution {
+                    Resolution::Reload => {
+                        let new_cells = replacing(&self.content, theirs);
+                        self.replace_cells(new_cells, Default::default(), Vec::new());
+                        self.changes.mark_saved();
+                        *info = format!("Reloaded {:?}", self.path);
+                    }
+                    Resolution::KeepMine => {
+                        self.changes.mark_unsaved();
+                        *info = "Kept your version, Ctrl+S to overwrite the file".to_string();
+                    }
+                    Resolution::Merge => {
+                        let merge = super::disk::merge(&self.disk.content, &self.content, theirs);
+                        let new_cells = replacing(&self.content, &merge.content);
+                        self.replace_cells(new_cells, Default::default(), Vec::new());
+                        match merge.content == *theirs {
+                            true => self.changes.mark_saved(),
+                            false => self.changes.mark_unsaved(),
+                        }
+                        *info = match merge.conflicts.as_slice() {
+                            [] => format!("Merged {} cells changed on disk", merge.theirs),
+                            conflicts => format!(
+                                "Merged {} cells changed on disk, kept yours in {} conflicts: {}",
+                                merge.theirs,
+                                conflicts.len(),
+                                conflicts
+                                    .iter()
+                                    .take(5)
+                                    .map(|index| index.to_string())
+                                    .collect::<Vec<_>>()
+                                    .join(" ")
+                            ),
+                        };
                     }
                 }
-                self.changes.redo();
-                *info = format!("Redo: updated {updated_cell_count} cells");
+                self.disk = disk;
+            }
+            Command::JumpHistory { node } => {
+                let steps = self.changes.jump(node);
+                for changes in &steps {
+                    self.apply_changes(changes);
+                }
+                *info = format!("History: went through {} changes", steps.len());
             }
             Command::RedrawRequest => { /* bubble up, but nothing to do */ }
         }
     }
+
+    /// Next cell after `from`, or before it, whose content or displayed value contains the
+    /// text, ignoring case. The search wraps around the sheet, ending on `from` itself.
+    fn find_cell(
+        &self,
+        text: &str,
+        from: crate::file::cell::CellIndex,
+        backwards: bool,
+    ) -> Option<crate::file::cell::CellIndex> {
+        use super::cell::Cell;
+        use std::ops::Bound;
+
+        let needle = text.to_lowercase();
+        let evaluator = super::formula::Evaluator::new(&self.content, &self.names);
+        let matches = |(index, cell): (&crate::file::cell::CellIndex, &Cell)| {
+            let found = cell.to_string().to_lowercase().contains(&needle)
+                || (matches!(cell, Cell::Formula(_))
+                    && evaluator
+                        .cell_value(*index)
+                        .display(None, &self.locale)
+                        .to_lowercase()
+                        .contains(&needle));
+            found.then_some(*index)
+        };
+        match backwards {
+            false => self
+                .content
+                .range((Bound::Excluded(from), Bound::Unbounded))
+                .chain(self.content.range(..=from))
+                .find_map(matches),
+            true => self
+                .content
+                .range(..from)
+                .rev()
+                .chain(self.content.range(from..).rev())
+                .find_map(matches),
+        }
+    }
+
+    /// Copy the cells to the internal clipboard, and as tab separated text to the system one.
+    fn copy_to_clipboard(&mut self, cells: super::cell::CellRect, cut: bool, info: &mut String) {
+        let copied = cells
+            .indices()
+            .map(|index| {
+                (
+                    self.content.get(&index).cloned(),
+                    self.formats.get(&index).cloned(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let text = copied
+            .chunks(usize::try_from(cells.width.max(1)).unwrap_or(usize::MAX))
+            .map(|row| {
+                row.iter()
+                    .map(|(content, _)| {
+                        content
+                            .as_ref()
+                            .map(|content| content.to_string())
+                            .unwrap_or_default()
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\t")
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let bytes = text.len();
+        match crossterm::execute!(
+            std::io::stdout(),
+            crossterm::clipboard::CopyToClipboard::to_clipboard_from(&text)
+        ) {
+            Ok(_) => *info = format!("Copied {bytes} bytes to clipboard"),
+            Err(e) => *info = format!("Failed to copy to clipboard: {e}"),
+        }
+        self.clipboard = Some(super::clipboard::Clipboard {
+            source: cells,
+            cells: copied,
+            text,
+            cut,
+        });
+    }
+
+    /// Paste the clipboard on the selected cells, returning where it was pasted, with the warning
+    /// of the data validations, or why they rejected it.
+    /// It is repeated over the selection when its size is a multiple of the copied one.
+    fn paste_clipboard(
+        &mut self,
+        clipboard: &super::clipboard::Clipboard,
+        cells: super::cell::CellRect,
+        options: super::clipboard::PasteOptions,
+    ) -> Result<(super::cell::CellRect, Option<String>), String> {
+        use super::clipboard::PastedParts;
+        use super::{cell, formula};
+
+        let (width, height) = clipboard.pasted_size(options.transpose);
+        let (tiles_x, tiles_y) = match width > 0
+            && height > 0
+            && cells.width.is_multiple_of(width)
+            && cells.height.is_multiple_of(height)
+        {
+            true => (cells.width / width, cells.height / height),
+            false => (1, 1),
+        };
+
+        let evaluator = formula::Evaluator::new(&self.content, &self.names);
+        let mut new_cells = std::collections::BTreeMap::new();
+        let mut new_formats = std::collections::BTreeMap::new();
+        for tile_y in 0..tiles_y {
+            for tile_x in 0..tiles_x {
+                let to = cell::CellIndex::new(cells.x + tile_x * width, cells.y + tile_y * height);
+                for pasted in clipboard.pasted_at(to, options.transpose) {
+                    if options.skip_blanks && pasted.content.is_none() {
+                        continue;
+                    }
+                    if options.parts != PastedParts::Formats {
+                        let content = match (options.parts, &pasted.content) {
+                            (PastedParts::Values, Some(cell::Cell::Formula(_))) => {
+                                cell::Cell::from_value(&evaluator.cell_value(pasted.source))
+                            }
+                            (_, content) => content.clone(),
+                        };
+                        let content = match (options.operation, &content) {
+                            (None, _) => content,
+                            // blanks count as 0, keeping the existing cell
+                            (Some(_), None) => self.content.get(&pasted.index).cloned(),
+                            (Some(operator), Some(content)) => {
+                                let copied = match content {
+                                    cell::Cell::Formula(_) => evaluator.cell_value(pasted.source),
+                                    other => other.value(),
+                                };
+                                let existing = evaluator.cell_value(pasted.index);
+                                match (existing.is_numeric() || existing == formula::Value::Empty)
+                                    && copied.is_numeric()
+                                {
+                                    true => cell::Cell::from_value(
+                                        &formula::binary(operator, &existing, &copied)
+                                            .unwrap_or_else(formula::Value::Error),
+                                    ),
+                                    false => self.content.get(&pasted.index).cloned(),
+                                }
+                            }
+                        };
+                        new_cells.insert(pasted.index, content);
+                    }
+                    if matches!(options.parts, PastedParts::All | PastedParts::Formats) {
+                        new_formats.insert(pasted.index, pasted.format);
+                    }
+                }
+            }
+        }
+        drop(evaluator);
+        let warning = self.validate(&new_cells)?;
+        self.replace_cells(new_cells, new_formats, Vec::new());
+        Ok((
+            cell::CellRect::new(cells.x, cells.y, width * tiles_x, height * tiles_y),
+            warning,
+        ))
+    }
+
+    /// Ranges a command on the given cells acts on: every range of the selection, as added with
+    /// Ctrl+click, when the cells are its current one, otherwise only the cells.
+    fn ranges_of(&self, cells: super::cell::CellRect) -> Vec<super::cell::CellRect> {
+        match &self.viewport.selection {
+            Some(selection) if selection.cells() == cells => selection.ranges().collect(),
+            _ => vec![cells],
+        }
+    }
+
+    /// Check the cells about to be written against the data validations of their ranges, the
+    /// other cells being as they are. Returns why the first invalid value is rejected, or
+    /// otherwise the warning of validations letting it through.
+    pub(super) fn validate(
+        &self,
+        new_cells: &std::collections::BTreeMap<super::cell::CellIndex, Option<super::cell::Cell>>,
+    ) -> Result<Option<String>, String> {
+        self.validate_against(new_cells, &self.validations)
+    }
+
+    /// Check the cells about to be written against the given data validations, as they will be
+    /// once moved along with the cells.
+    fn validate_against(
+        &self,
+        new_cells: &std::collections::BTreeMap<super::cell::CellIndex, Option<super::cell::Cell>>,
+        validations: &[super::validation::Validation],
+    ) -> Result<Option<String>, String> {
+        let validations = validations
+            .iter()
+            .filter(|validation| {
+                new_cells
+                    .keys()
+                    .any(|index| validation.cells.contains(*index))
+            })
+            .collect::<Vec<_>>();
+        if validations.is_empty() {
+            return Ok(None);
+        }
+        // values are checked as they will be, formulas seeing the other written cells
+        let mut content = self.content.clone();
+        for (index, cell) in new_cells {
+            match cell {
+                Some(cell) => content.insert(*index, cell.clone()),
+                None => content.remove(index),
+            };
+        }
+        let evaluator = super::formula::Evaluator::new(&content, &self.names);
+        let mut warning = None;
+        for index in new_cells.keys() {
+            let value = evaluator.cell_value(*index);
+            for validation in &validations {
+                if let Err(expected) = validation.check(*index, &value, &evaluator) {
+                    let reason = format!("{index} should be {expected}");
+                    if !validation.warn {
+                        return Err(reason);
+                    }
+                    warning.get_or_insert(reason);
+                }
+            }
+        }
+        Ok(warning)
+    }
+
+    /// Fill each lane of the cells from its seeds, returning the number of filled cells and the
+    /// warning of data validations, or why they reject the filled values.
+    /// Seeds are the first cell of the lane, or all its leading non-empty cells for a series.
+    fn fill_cells(
+        &mut self,
+        cells: super::cell::CellRect,
+        direction: super::fill::FillDirection,
+        series: bool,
+    ) -> Result<(usize, Option<String>), String> {
+        let mut new_cells = std::collections::BTreeMap::new();
+        let mut new_formats = std::collections::BTreeMap::new();
+        for lane in direction.lanes(cells) {
+            let seeds = match series {
+                true => lane
+                    .iter()
+                    .take_while(|index| self.content.contains_key(index))
+                    .count(),
+                false => 1,
+            };
+            if seeds == 0 {
+                continue;
+            }
+            let seed_cells = lane[..seeds]
+                .iter()
+                .map(|index| self.content.get(index).cloned())
+                .collect::<Vec<_>>();
+            let filled = super::fill::fill_lane(&seed_cells, lane.len(), direction, series);
+            for (position, (index, content)) in lane[seeds..].iter().zip(filled).enumerate() {
+                let seed = lane[position % seeds];
+                new_cells.insert(*index, content);
+                new_formats.insert(*index, self.formats.get(&seed).cloned());
+            }
+        }
+        let warning = self.validate(&new_cells)?;
+        let count = new_cells.len();
+        self.replace_cells(new_cells, new_formats, Vec::new());
+        Ok((count, warning))
+    }
+
+    /// Set the content and format of many cells at once, as a single undoable change, along with
+    /// other changes already applied. Cells mapped to None are cleared.
+    fn replace_cells(
+        &mut self,
+        new_cells: std::collections::BTreeMap<super::cell::CellIndex, Option<super::cell::Cell>>,
+        new_formats: std::collections::BTreeMap<
+            super::cell::CellIndex,
+            Option<super::format::CellFormat>,
+        >,
+        mut changes: Vec<super::change_history::Change>,
+    ) {
+        use super::change_history::{Change, Changes};
+
+        for (index, content) in new_cells {
+            let previous = self.content.get(&index).cloned();
+            if previous != content {
+                changes.push(Change::cell(index, previous, content));
+            }
+        }
+        for (index, format) in new_formats {
+            let previous = self.formats.get(&index).cloned();
+            if previous != format {
+                changes.push(Change::format(index, previous, format));
+            }
+        }
+        let changes = Changes::from_vec(changes);
+        self.apply_changes(&changes);
+        self.changes.push(changes);
+    }
+
+    /// Changes setting the notes of cells, those mapped to None being deleted.
+    fn note_changes(
+        &self,
+        new_notes: std::collections::BTreeMap<super::cell::CellIndex, Option<String>>,
+    ) -> Vec<super::change_history::Change> {
+        new_notes
+            .into_iter()
+            .filter_map(|(index, note)| {
+                let previous_value = self.notes.get(&index).cloned();
+                (previous_value != note).then_some(super::change_history::Change::Note {
+                    index,
+                    previous_value,
+                    new_value: note,
+                })
+            })
+            .collect()
+    }
+
+    /// Apply the new values of the given changes, returning the number of updated cells.
+    /// The selection recorded with the changes is restored and scrolled into view.
+    fn apply_changes(&mut self, changes: &super::change_history::Changes) -> usize {
+        use super::change_history::Change;
+
+        for change in changes.as_slice() {
+            match change {
+                Change::Selection { new_value, .. } => {
+                    self.viewport.selection = new_value.map(super::selection::Selection::range);
+                    self.viewport.keep_selection_in_view();
+                }
+                change => change.apply(&mut super::change_history::Sheet {
+                    content: &mut self.content,
+                    formats: &mut self.formats,
+                    rules: &mut self.rules,
+                    validations: &mut self.validations,
+                    notes: &mut self.notes,
+                    merges: &mut self.merges,
+                    names: &mut self.names,
+                }),
+            }
+        }
+        changes
+            .as_slice()
+            .iter()
+            .filter(|change| !matches!(change, Change::Selection { .. }))
+            .count()
+    }
+}
+
+/// New value of every cell of either map, to replace the first one by the second.
+fn replacing<T: Clone>(
+    current: &std::collections::BTreeMap<super::cell::CellIndex, T>,
+    new: &std::collections::BTreeMap<super::cell::CellIndex, T>,
+) -> std::collections::BTreeMap<super::cell::CellIndex, Option<T>> {
+    current
+        .keys()
+        .chain(new.keys())
+        .map(|index| (*index, new.get(index).cloned()))
+        .collect()
+}
+
+/// Ranges written as in the selection, `A1:B2, D4`.
+fn cells_list(ranges: &[super::cell::CellRect]) -> String {
+    ranges
+        .iter()
+        .map(|cells| cells.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
 }
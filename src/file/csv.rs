@@ -0,0 +1,41 @@
+/// Field as written in a row, quoted when it holds the separator, a line break or starts with a
+/// quote, its quotes being doubled.
+pub fn quote(field: &str, separator: char) -> std::borrow::Cow<'_, str> {
+    match field.contains([separator, '\n', '\r']) || field.starts_with('"') {
+        true => format!("\"{}\"", field.replace('"', "\"\"")).into(),
+        false => field.into(),
+    }
+}
+
+/// Split the text into rows of fields. Quoted fields may hold separators and line breaks.
+pub fn rows(text: &str, separator: char) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut chars = text.chars().peekable();
+    // quotes only count at the start of a field, as written by `quote`
+    let mut quoted = false;
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ch if quoted => field.push(ch),
+            ch if ch == separator => row.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            ch => field.push(ch),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows
+}
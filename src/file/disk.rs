@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+
+use crate::file::cell;
+
+/// The file as it was last read or written by us, to notice when another process rewrites it.
+pub struct DiskState {
+    /// Modification time and size of the file.
+    stamp: Option<(std::time::SystemTime, u64)>,
+    /// Content of the file, the common base when merging our changes with theirs.
+    pub content: BTreeMap<cell::CellIndex, cell::Cell>,
+    /// Typed values as written in the file, as `01/03/2024` or `vrai`, to write them back in
+    /// the same form rather than in the ISO one.
+    fields: BTreeMap<cell::CellIndex, String>,
+}
+
+impl DiskState {
+    /// State of the file holding the `text`, read as the `content`.
+    pub fn new(
+        path: &std::path::Path,
+        text: &str,
+        content: BTreeMap<cell::CellIndex, cell::Cell>,
+    ) -> DiskState {
+        let fields = super::csv::rows(text, ';')
+            .into_iter()
+            .enumerate()
+            .flat_map(|(y, row)| {
+                row.into_iter()
+                    .enumerate()
+                    .map(move |(x, field)| (cell::CellIndex::new(x as u64, y as u64), field))
+            })
+            .filter(|(index, _)| {
+                !matches!(
+                    content.get(index),
+                    None | Some(cell::Cell::Text(_) | cell::Cell::Formula(_))
+                )
+            })
+            .collect();
+        DiskState {
+            stamp: stamp(path),
+            content,
+            fields,
+        }
+    }
+
+    /// The field read for the cell, while it keeps the value it was read with.
+    pub fn field(&self, index: cell::CellIndex, cell: &cell::Cell) -> Option<&str> {
+        match self.content.get(&index) == Some(cell) {
+            true => self.fields.get(&index).map(String::as_str),
+            false => None,
+        }
+    }
+
+    /// Whether the file was modified since it was last read or written by us.
+    pub fn changed(&self, path: &std::path::Path) -> bool {
+        stamp(path) != self.stamp
+    }
+}
+
+fn stamp(path: &std::path::Path) -> Option<(std::time::SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// What to do when the file changed on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// Replace our content with theirs.
+    Reload,
+    /// Keep our content, to overwrite theirs on the next save.
+    KeepMine,
+    /// Take their changes to the cells we did not change.
+    Merge,
+}
+
+impl Resolution {
+    pub fn parse(answer: &str) -> Result<Resolution, String> {
+        match answer.trim().to_ascii_lowercase().as_str() {
+            "r" | "reload" => Ok(Resolution::Reload),
+            "k" | "keep" => Ok(Resolution::KeepMine),
+            "m" | "merge" => Ok(Resolution::Merge),
+            _ => Err("Answer r to reload, k to keep your version, m to merge".to_string()),
+        }
+    }
+}
+
+/// Result of a three-way merge, cell by cell.
+pub struct Merge {
+    pub content: BTreeMap<cell::CellIndex, cell::Cell>,
+    /// Cells taken from their version.
+    pub theirs: usize,
+    /// Cells changed differently by both sides, where ours were kept.
+    pub conflicts: Vec<cell::CellIndex>,
+}
+
+/// Merge our changes and theirs, both made from the `base` content. Each cell changed on one
+/// side only takes this change, and a cell changed on both sides keeps ours.
+pub fn merge(
+    base: &BTreeMap<cell::CellIndex, cell::Cell>,
+    mine: &BTreeMap<cell::CellIndex, cell::Cell>,
+    theirs: &BTreeMap<cell::CellIndex, cell::Cell>,
+) -> Merge {
+    let indices = base
+        .keys()
+        .chain(mine.keys())
+        .chain(theirs.keys())
+        .copied()
+        .collect::<std::collections::BTreeSet<_>>();
+    let mut merge = Merge {
+        content: BTreeMap::new(),
+        theirs: 0,
+        conflicts: Vec::new(),
+    };
+    for index in indices {
+        let (base, mine, theirs) = (base.get(&index), mine.get(&index), theirs.get(&index));
+        let merged = if mine == theirs || theirs == base {
+            mine
+        } else if mine == base {
+            merge.theirs += 1;
+            theirs
+        } else {
+            merge.conflicts.push(index);
+            mine
+        };
+        if let Some(merged) = merged {
+            merge.content.insert(index, merged.clone());
+        }
+    }
+    merge
+}
+
+/// Count the cells differing between two contents, as `cells: 3 changed, 1 added`.
+pub fn summary(
+    from: &BTreeMap<cell::CellIndex, cell::Cell>,
+    to: &BTreeMap<cell::CellIndex, cell::Cell>,
+) -> String {
+    let changed = from
+        .iter()
+        .filter(|(index, content)| to.get(index).is_some_and(|other| other != *content))
+        .count();
+    let added = to.keys().filter(|index| !from.contains_key(index)).count();
+    let removed = from.keys().filter(|index| !to.contains_key(index)).count();
+    let parts = [(changed, "changed"), (added, "added"), (removed, "removed")]
+        .iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, what)| format!("{count} {what}"))
+        .collect::<Vec<_>>();
+    match parts.is_empty() {
+        true => "no cell changed".to_string(),
+        false => format!("cells: {}", parts.join(", ")),
+    }
+}
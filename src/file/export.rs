@@ -0,0 +1,173 @@
+use std::collections::BTreeMap;
+
+use crate::file::formula::{Evaluator, Value};
+use crate::file::{cell, csv, format, names};
+
+/// File format written by Save As and by exporting the selection, chosen by the file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// The format files are opened and saved in, cells separated by `;`.
+    Csv,
+    Tsv,
+    /// Table of the displayed values, the first row being its header.
+    Markdown,
+    /// Array of rows, each an array of values.
+    Json,
+}
+
+impl ExportFormat {
+    pub fn from_path(path: &std::path::Path) -> Result<ExportFormat, String> {
+        let extension = path
+            .extension()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_ascii_lowercase();
+        match extension.as_str() {
+            "csv" => Ok(ExportFormat::Csv),
+            "tsv" | "tab" => Ok(ExportFormat::Tsv),
+            "md" | "markdown" => Ok(ExportFormat::Markdown),
+            "json" => Ok(ExportFormat::Json),
+            other => Err(format!(
+                "Unknown file type \"{other}\": use .csv, .tsv, .md or .json"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportFormat::Csv => write!(f, "CSV"),
+            ExportFormat::Tsv => write!(f, "TSV"),
+            ExportFormat::Markdown => write!(f, "Markdown"),
+            ExportFormat::Json => write!(f, "JSON"),
+        }
+    }
+}
+
+/// Smallest rectangle from A1 holding all the cells.
+pub fn used_cells(content: &BTreeMap<cell::CellIndex, cell::Cell>) -> cell::CellRect {
+    let width = content.keys().map(|index| index.x + 1).max();
+    let height = content.keys().map(|index| index.y + 1).max();
+    cell::CellRect::new(0, 0, width.unwrap_or_default(), height.unwrap_or_default())
+}
+
+/// Write the given cells in the format. CSV and TSV keep formulas as written unless `values` is
+/// set, which is needed when the cells don't start at A1 anymore. Markdown and JSON always
+/// write the values.
+pub fn export(
+    export_format: ExportFormat,
+    content: &BTreeMap<cell::CellIndex, cell::Cell>,
+    formats: &BTreeMap<cell::CellIndex, format::CellFormat>,
+    names: &[names::Name],
+    cells: cell::CellRect,
+    locale: &crate::locale::Locale,
+    values: bool,
+) -> String {
+    let evaluator = Evaluator::new(content, names);
+    let rows = (cells.y..cells.y + cells.height)
+        .map(|y| {
+            (cells.x..cells.x + cells.width)
+                .map(|x| cell::CellIndex::new(x, y))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    match export_format {
+        ExportFormat::Csv | ExportFormat::Tsv => {
+            let separator = match export_format {
+                ExportFormat::Csv => ';',
+                _ => '\t',
+            };
+            let written = |index: &cell::CellIndex| match (content.get(index), values) {
+                (Some(cell::Cell::Formula(_)), true) => {
+                    cell::Cell::from_value(&evaluator.cell_value(*index))
+                        .map(|cell| cell.to_string())
+                        .unwrap_or_default()
+                }
+                (Some(cell), _) => cell.to_string(),
+                (None, _) => String::new(),
+            };
+            rows.iter()
+                .map(|row| {
+                    let mut fields = row
+                        .iter()
+                        .map(|index| csv::quote(&written(index), separator).into_owned())
+                        .collect::<Vec<_>>();
+                    // like saved files, without the separators of trailing empty cells
+                    while fields.last().is_some_and(String::is_empty) {
+                        fields.pop();
+                    }
+                    fields.join(&separator.to_string())
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+        ExportFormat::Markdown => {
+            let displayed = |index: &cell::CellIndex| {
+                let number_format = formats.get(index).and_then(|format| format.number.as_ref());
+                evaluator
+                    .cell_value(*index)
+                    .display(number_format, locale)
+                    .replace('|', "\\|")
+                    .replace('\n', "<br>")
+            };
+            let mut lines = Vec::new();
+            for (row, indices) in rows.iter().enumerate() {
+                let fields = indices.iter().map(displayed).collect::<Vec<_>>();
+                lines.push(format!("| {} |", fields.join(" | ")));
+                if row == 0 {
+                    lines.push(format!("|{}", " --- |".repeat(indices.len())));
+                }
+            }
+            lines.join("\n") + "\n"
+        }
+        ExportFormat::Json => {
+            let rows = rows
+                .iter()
+                .map(|row| {
+                    let fields = row
+                        .iter()
+                        .map(|index| json_value(&evaluator.cell_value(*index)))
+                        .collect::<Vec<_>>();
+                    format!("  [{}]", fields.join(", "))
+                })
+                .collect::<Vec<_>>();
+            format!("[\n{}\n]\n", rows.join(",\n"))
+        }
+    }
+}
+
+/// Value as JSON: numbers and booleans as such, empty cells as null, dates and times in ISO
+/// format and errors as their text.
+fn json_value(value: &Value) -> String {
+    match value {
+        Value::Empty => "null".to_string(),
+        Value::Number(number) if number.is_finite() => number.to_string(),
+        Value::Bool(value) => value.to_string(),
+        other => {
+            let text = cell::Cell::from_value(other)
+                .map(|cell| cell.to_string())
+                .unwrap_or_default();
+            json_string(&text)
+        }
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for ch in text.chars() {
+        match ch {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            ch if u32::from(ch) < 0x20 => quoted.push_str(&format!("\\u{:04x}", u32::from(ch))),
+            ch => quoted.push(ch),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
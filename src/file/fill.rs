@@ -0,0 +1,532 @@
+use crate::file::cell::{Cell, CellIndex, CellRect};
+use crate::file::format::date;
+use crate::file::formula;
+
+/// Direction in which a selection is filled from the cells at its start.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FillDirection {
+    Down,
+    Right,
+}
+
+impl FillDirection {
+    /// Cells to fill, starting one line before a selection that is a single line thick,
+    /// so a lone row is filled from the one above it, as in Excel.
+    pub fn extended(self, cells: CellRect) -> CellRect {
+        match self {
+            FillDirection::Down if cells.height == 1 && cells.y > 0 => {
+                CellRect::new(cells.x, cells.y - 1, cells.width, 2)
+            }
+            FillDirection::Right if cells.width == 1 && cells.x > 0 => {
+                CellRect::new(cells.x - 1, cells.y, 2, cells.height)
+            }
+            _ => cells,
+        }
+    }
+
+    /// Lines of cells filled independently: the columns when filling down, the rows otherwise.
+    pub fn lanes(self, cells: CellRect) -> Vec<Vec<CellIndex>> {
+        match self {
+            FillDirection::Down => (cells.x..cells.x + cells.width)
+                .map(|x| {
+                    (cells.y..cells.y + cells.height)
+                        .map(|y| CellIndex::new(x, y))
+                        .collect()
+                })
+                .collect(),
+            FillDirection::Right => (cells.y..cells.y + cells.height)
+                .map(|y| {
+                    (cells.x..cells.x + cells.width)
+                        .map(|x| CellIndex::new(x, y))
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+
+    /// Move of a cell copied `distance` cells further in the lane.
+    fn offset(self, distance: usize) -> (i64, i64) {
+        let distance = i64::try_from(distance).unwrap_or(i64::MAX);
+        match self {
+            FillDirection::Down => (0, distance),
+            FillDirection::Right => (distance, 0),
+        }
+    }
+}
+
+impl std::fmt::Display for FillDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FillDirection::Down => write!(f, "down"),
+            FillDirection::Right => write!(f, "right"),
+        }
+    }
+}
+
+/// How the seed cells of a lane continue.
+#[derive(Debug, Clone)]
+enum Series {
+    /// Repeat the seeds, formulas following their cell.
+    Repeat,
+    /// Numbers, date times or durations with a constant step.
+    Linear {
+        first: f64,
+        step: f64,
+        cell: fn(f64) -> Cell,
+    },
+    /// Dates a constant number of days apart.
+    Days { first: i64, step: i64 },
+    /// Dates on the same day of the month, a constant number of months apart.
+    Months { first: i64, day: u32, step: i64 },
+    /// Names of weekdays or months, cased like the last seed.
+    Names {
+        names: &'static [&'static str],
+        first: usize,
+        step: i64,
+        abbreviated: bool,
+        model: String,
+    },
+    /// Text ending with a number, as `Item 1`, `Item 2`.
+    Numbered {
+        prefix: String,
+        suffix: String,
+        first: i64,
+        step: i64,
+        /// Digits count of zero padded numbers, as in `Q01`.
+        width: usize,
+    },
+}
+
+impl Series {
+    /// Find how the given seeds go on. A single number is repeated, while a single date, time,
+    /// name or numbered text is incremented by one, as Excel does.
+    fn detect(seeds: &[Option<Cell>]) -> Series {
+        let seeds = match seeds.iter().map(Option::as_ref).collect::<Option<Vec<_>>>() {
+            Some(seeds) if !seeds.is_empty() => seeds,
+            _ => return Series::Repeat,
+        };
+        let all = |kind: fn(&Cell) -> Option<f64>| {
+            seeds
+                .iter()
+                .map(|seed| kind(seed))
+                .collect::<Option<Vec<_>>>()
+        };
+
+        if let Some(numbers) = all(|seed| match seed {
+            Cell::Num(number) => Some(*number),
+            _ => None,
+        }) {
+            return match linear_step(&numbers) {
+                Some(step) if numbers.len() > 1 => Series::Linear {
+                    first: numbers[0],
+                    step,
+                    cell: Cell::Num,
+                },
+                _ => Series::Repeat,
+            };
+        }
+        if let Some(days) = all(|seed| match seed {
+            Cell::Date(serial) => Some(*serial as f64),
+            _ => None,
+        }) {
+            return detect_dates(&days);
+        }
+        if let Some(serials) = all(|seed| match seed {
+            Cell::DateTime(serial) => Some(*serial),
+            _ => None,
+        }) {
+            return linear_series(&serials, 1.0, Cell::DateTime);
+        }
+        if let Some(durations) = all(|seed| match seed {
+            Cell::Duration(days) => Some(*days),
+            _ => None,
+        }) {
+            return linear_series(&durations, 1.0 / 24.0, Cell::Duration);
+        }
+
+        let texts = seeds
+            .iter()
+            .map(|seed| match seed {
+                Cell::Text(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>();
+        match texts {
+            Some(texts) => detect_names(&texts)
+                .or_else(|| detect_numbered(&texts))
+                .unwrap_or(Series::Repeat),
+            None => Series::Repeat,
+        }
+    }
+
+    /// Cell at the given position of the lane, counted from the first seed.
+    /// None past the end of the series, when its numbers or dates overflow.
+    fn cell(
+        &self,
+        seeds: &[Option<Cell>],
+        position: usize,
+        direction: FillDirection,
+    ) -> Option<Cell> {
+        let number = position as f64;
+        let steps = i64::try_from(position).unwrap_or(i64::MAX);
+        match self {
+            Series::Repeat => {
+                let seed = position % seeds.len().max(1);
+                let (dx, dy) = direction.offset(position - seed);
+                match seeds.get(seed)?.as_ref()? {
+                    Cell::Formula(formula::Formula::Expr(expr)) => {
+                        Some(Cell::Formula(formula::Formula::Expr(
+                            expr.map_references(&|reference, _| reference.offset(dx, dy)),
+                        )))
+                    }
+                    other => Some(other.clone()),
+                }
+            }
+            Series::Linear { first, step, cell } => Some(cell(tidy(first + step * number, *step))),
+            Series::Days { first, step } => first
+                .checked_add(step.checked_mul(steps)?)
+                .filter(|serial| date::SERIAL_RANGE.contains(serial))
+                .map(Cell::Date),
+            Series::Months { first, day, step } => {
+                let month = first.checked_add(step.checked_mul(steps)?)?;
+                let (year, month) = (month.div_euclid(12), month.rem_euclid(12) as u32 + 1);
+                let day = (*day).min(date::days_in_month(year, month));
+                date::civil_to_serial(year, month, day)
+                    .filter(|serial| date::SERIAL_RANGE.contains(serial))
+                    .map(Cell::Date)
+            }
+            Series::Names {
+                names,
+                first,
+                step,
+                abbreviated,
+                model,
+            } => {
+                let count = names.len() as i64;
+                let name =
+                    names[(*first as i64 + step * (steps % count)).rem_euclid(count) as usize];
+                let name = match abbreviated {
+                    true => &name[..3],
+                    false => name,
+                };
+                Some(Cell::Text(cased_like(name, model)))
+            }
+            Series::Numbered {
+                prefix,
+                suffix,
+                first,
+                step,
+                width,
+            } => Some(Cell::Text(format!(
+                "{prefix}{:0width$}{suffix}",
+                first.checked_add(step.checked_mul(steps)?)?
+            ))),
+        }
+    }
+}
+
+/// Content of a lane after its seeds, up to the given length of the lane.
+/// Without `series`, the seeds are only repeated.
+pub fn fill_lane(
+    seeds: &[Option<Cell>],
+    length: usize,
+    direction: FillDirection,
+    series: bool,
+) -> Vec<Option<Cell>> {
+    let series = match series {
+        true => Series::detect(seeds),
+        false => Series::Repeat,
+    };
+    (seeds.len()..length)
+        .map(|position| series.cell(seeds, position, direction))
+        .collect()
+}
+
+/// Common difference of the values, if they are evenly spaced.
+fn linear_step(values: &[f64]) -> Option<f64> {
+    let step = match values {
+        [first, second, ..] => second - first,
+        _ => 0.0,
+    };
+    let tolerance = 1e-9 * step.abs().max(1.0);
+    values
+        .windows(2)
+        .all(|pair| ((pair[1] - pair[0]) - step).abs() <= tolerance)
+        .then_some(step)
+}
+
+/// Linear series of date times or durations, a single seed being incremented by `unit`.
+fn linear_series(values: &[f64], unit: f64, cell: fn(f64) -> Cell) -> Series {
+    let step = match values.len() {
+        1 => Some(unit),
+        _ => linear_step(values),
+    };
+    match step {
+        Some(step) => Series::Linear {
+            first: values[0],
+            step,
+            cell,
+        },
+        None => Series::Repeat,
+    }
+}
+
+fn detect_dates(days: &[f64]) -> Series {
+    let Some(civil) = days
+        .iter()
+        .map(|serial| date::serial_to_civil(*serial as i64))
+        .collect::<Option<Vec<_>>>()
+    else {
+        return Series::Repeat;
+    };
+    let months = civil
+        .iter()
+        .map(|(year, month, _)| (year * 12 + i64::from(*month) - 1) as f64)
+        .collect::<Vec<_>>();
+    // month ends stay month ends, 31 being clamped to the length of each month
+    let month_ends = civil
+        .iter()
+        .all(|(year, month, day)| *day == date::days_in_month(*year, *month));
+    let day = match month_ends {
+        true => 31,
+        false => civil[0].2,
+    };
+    if days.len() > 1
+        && (month_ends || civil.iter().all(|(_, _, other)| *other == day))
+        && let Some(step) = linear_step(&months).filter(|step| *step != 0.0)
+    {
+        return Series::Months {
+            first: months[0] as i64,
+            day,
+            step: step as i64,
+        };
+    }
+    let step = match days.len() {
+        1 => Some(1.0),
+        _ => linear_step(days),
+    };
+    match step {
+        Some(step) => Series::Days {
+            first: days[0] as i64,
+            step: step as i64,
+        },
+        None => Series::Repeat,
+    }
+}
+
+/// Series of weekday or month names, full or abbreviated to their first three letters.
+fn detect_names(texts: &[&str]) -> Option<Series> {
+    let lists: [&'static [&'static str]; 2] = [&date::DAY_NAMES, &date::MONTH_NAMES];
+    lists.into_iter().find_map(|names| {
+        let indices = texts
+            .iter()
+            .map(|text| {
+                names.iter().position(|name| {
+                    name.eq_ignore_ascii_case(text)
+                        || (text.len() == 3 && name[..3].eq_ignore_ascii_case(text))
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let step = match indices.as_slice() {
+            [_] => 1,
+            [first, second, ..] => {
+                let count = names.len() as i64;
+                let step = (*second as i64 - *first as i64).rem_euclid(count);
+                indices
+                    .windows(2)
+                    .all(|pair| (pair[1] as i64 - pair[0] as i64).rem_euclid(count) == step)
+                    .then_some(step)?
+            }
+            [] => return None,
+        };
+        Some(Series::Names {
+            names,
+            first: indices[0],
+            step,
+            abbreviated: texts
+                .iter()
+                .zip(&indices)
+                .any(|(text, index)| text.len() < names[*index].len()),
+            model: texts.last()?.to_string(),
+        })
+    })
+}
+
+/// Series of texts sharing everything but their last number.
+fn detect_numbered(texts: &[&str]) -> Option<Series> {
+    let parts = texts
+        .iter()
+        .map(|text| {
+            let end = text.rfind(|ch: char| ch.is_ascii_digit())? + 1;
+            let start = text[..end]
+                .rfind(|ch: char| !ch.is_ascii_digit())
+                .map_or(0, |index| index + 1);
+            let number = text[start..end].parse::<i64>().ok()?;
+            Some((&text[..start], number, &text[start..end], &text[end..]))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let (prefix, first, digits, suffix) = *parts.first()?;
+    if !parts.iter().all(|(other_prefix, _, _, other_suffix)| {
+        *other_prefix == prefix && *other_suffix == suffix
+    }) {
+        return None;
+    }
+    let numbers = parts
+        .iter()
+        .map(|(_, number, _, _)| *number as f64)
+        .collect::<Vec<_>>();
+    let step = match numbers.len() {
+        1 => 1,
+        _ => linear_step(&numbers)? as i64,
+    };
+    Some(Series::Numbered {
+        prefix: prefix.to_string(),
+        suffix: suffix.to_string(),
+        first,
+        step,
+        width: match digits.starts_with('0') {
+            true => digits.len(),
+            false => 0,
+        },
+    })
+}
+
+/// Name in upper or lower case if the model is, as it is written otherwise.
+fn cased_like(name: &str, model: &str) -> String {
+    if !model.chars().any(|ch| ch.is_lowercase()) {
+        name.to_uppercase()
+    } else if !model.chars().any(|ch| ch.is_uppercase()) {
+        name.to_lowercase()
+    } else {
+        name.to_string()
+    }
+}
+
+/// Round away the floating point noise of repeated steps, as `0.1 * 3`. The rounding follows
+/// the size of the step, so series of tiny numbers as `1e-12`, `2e-12` keep their digits.
+fn tidy(value: f64, step: f64) -> f64 {
+    let precision = (step.abs() * 1e-10).max(value.abs() * 1e-13);
+    if precision == 0.0 || !precision.is_finite() {
+        return value;
+    }
+    let decimals = (-precision.log10().ceil()).clamp(0.0, 340.0) as usize;
+    format!("{value:.decimals$}").parse().unwrap_or(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seeds(texts: &[&str]) -> Vec<Option<Cell>> {
+        let locale = crate::locale::Locale::default();
+        texts
+            .iter()
+            .map(|text| match text.is_empty() {
+                true => None,
+                false => Some(Cell::parse(text, &locale)),
+            })
+            .collect()
+    }
+
+    fn filled(texts: &[&str], length: usize) -> Vec<String> {
+        fill_lane(&seeds(texts), length, FillDirection::Down, true)
+            .iter()
+            .map(|cell| cell.as_ref().map(Cell::to_string).unwrap_or_default())
+            .collect()
+    }
+
+    #[test]
+    fn detects_how_the_seeds_go_on() {
+        assert!(matches!(
+            Series::detect(&seeds(&["1", "3"])),
+            Series::Linear {
+                first: 1.0,
+                step: 2.0,
+                ..
+            }
+        ));
+        assert!(matches!(Series::detect(&seeds(&["1"])), Series::Repeat));
+        assert!(matches!(
+            Series::detect(&seeds(&["1", "2", "4"])),
+            Series::Repeat
+        ));
+        assert!(matches!(Series::detect(&seeds(&["1", ""])), Series::Repeat));
+        assert!(matches!(
+            Series::detect(&seeds(&["1", "a"])),
+            Series::Repeat
+        ));
+        assert!(matches!(
+            Series::detect(&seeds(&["2024-01-31", "2024-02-29"])),
+            Series::Months {
+                day: 31,
+                step: 1,
+                ..
+            }
+        ));
+        assert!(matches!(
+            Series::detect(&seeds(&["2024-03-01"])),
+            Series::Days { step: 1, .. }
+        ));
+        assert!(matches!(
+            Series::detect(&seeds(&["Mon", "Wed"])),
+            Series::Names {
+                step: 2,
+                abbreviated: true,
+                ..
+            }
+        ));
+        assert!(matches!(
+            Series::detect(&seeds(&["Q01"])),
+            Series::Numbered {
+                first: 1,
+                step: 1,
+                width: 2,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn fills_numbers_without_floating_point_noise() {
+        assert_eq!(filled(&["0.1", "0.2"], 5), ["0.3", "0.4", "0.5"]);
+        assert_eq!(filled(&["10", "7"], 4), ["4", "1"]);
+        assert_eq!(
+            filled(&["1e-12", "2e-12"], 4),
+            ["0.000000000003", "0.000000000004"]
+        );
+        assert_eq!(filled(&["5"], 3), ["5", "5"]);
+    }
+
+    #[test]
+    fn fills_dates_by_days_or_months() {
+        assert_eq!(filled(&["2024-02-28"], 3), ["2024-02-29", "2024-03-01"]);
+        assert_eq!(
+            filled(&["2024-01-31", "2024-02-29"], 4),
+            ["2024-03-31", "2024-04-30"]
+        );
+        assert_eq!(filled(&["2024-01-15", "2024-04-15"], 3), ["2024-07-15"]);
+        assert_eq!(filled(&["2024-01-01 08:00"], 2), ["2024-01-02 8:00"]);
+        assert_eq!(filled(&["8:00", "8:30"], 4), ["9:00", "9:30"]);
+    }
+
+    #[test]
+    fn fills_names_and_numbered_texts() {
+        assert_eq!(filled(&["Saturday"], 3), ["Sunday", "Monday"]);
+        assert_eq!(filled(&["DEC"], 2), ["JAN"]);
+        assert_eq!(filled(&["Item 1", "Item 3"], 4), ["Item 5", "Item 7"]);
+        assert_eq!(filled(&["Q09"], 3), ["Q10", "Q11"]);
+        assert_eq!(filled(&["a", "b"], 5), ["a", "b", "a"]);
+    }
+
+    #[test]
+    fn repeats_formulas_following_their_cell() {
+        let formulas = fill_lane(&seeds(&["=A1*2"]), 3, FillDirection::Down, true);
+        let formulas = formulas
+            .iter()
+            .map(|cell| cell.as_ref().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(formulas, ["=A2*2", "=A3*2"]);
+        let copies = fill_lane(&seeds(&["0.1", "0.2"]), 4, FillDirection::Right, false);
+        assert_eq!(copies, seeds(&["0.1", "0.2"]));
+    }
+}
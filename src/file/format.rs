@@ -0,0 +1,268 @@
+pub mod conditional;
+pub mod date;
+mod number;
+
+pub use number::NumberFormat;
+
+use ratatui::layout::Alignment;
+use ratatui::style::Color;
+use ratatui::widgets::Borders;
+
+const BORDER_COLOR: Color = Color::Gray;
+
+/// Formatting attributes of a single cell, applied on top of its content.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CellFormat {
+    /// How numbers are displayed, general formatting when none.
+    pub number: Option<NumberFormat>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    /// Text colour, the colour of the value type when none.
+    pub foreground: Option<Color>,
+    /// Fill colour, the alternating grid colour when none.
+    pub background: Option<Color>,
+    /// Horizontal alignment, depending on the value type when none.
+    pub alignment: Option<Alignment>,
+    pub borders: Borders,
+}
+
+impl CellFormat {
+    /// Whether this format does not change anything to the default cell rendering.
+    pub fn is_default(&self) -> bool {
+        *self == CellFormat::default()
+    }
+
+    /// Text style of the cell content, on top of the given value colour.
+    pub fn text_style(&self, color: Color) -> ratatui::style::Style {
+        use ratatui::style::{Modifier, Style};
+
+        let mut modifiers = Modifier::empty();
+        modifiers.set(Modifier::BOLD, self.bold);
+        modifiers.set(Modifier::ITALIC, self.italic);
+        modifiers.set(Modifier::UNDERLINED, self.underline);
+        Style::new()
+            .fg(self.foreground.unwrap_or(color))
+            .add_modifier(modifiers)
+    }
+
+    /// Read a format written by its `Display` implementation.
+    pub fn parse(text: &str) -> Result<CellFormat, String> {
+        let mut format = CellFormat::default();
+        // the number format comes last, as its code may contain spaces
+        let (attributes, number) = match text.split_once("number=") {
+            Some((attributes, code)) => (attributes, Some(code)),
+            None => (text, None),
+        };
+        if let Some(code) = number {
+            format.number = Some(NumberFormat::parse(code)?);
+        }
+        for word in attributes.split_whitespace() {
+            match word.split_once('=') {
+                None if word == "bold" => format.bold = true,
+                None if word == "italic" => format.italic = true,
+                None if word == "underline" => format.underline = true,
+                Some(("fg", color)) => format.foreground = FormatChange::parse_color(color)?,
+                Some(("bg", color)) => format.background = FormatChange::parse_color(color)?,
+                Some(("align", "left")) => format.alignment = Some(Alignment::Left),
+                Some(("align", "center")) => format.alignment = Some(Alignment::Center),
+                Some(("align", "right")) => format.alignment = Some(Alignment::Right),
+                Some(("borders", sides)) => {
+                    if let FormatChange::Borders(borders) = FormatChange::parse_borders(sides)? {
+                        format.borders = borders;
+                    }
+                }
+                _ => return Err(format!("Unknown format \"{word}\"")),
+            }
+        }
+        Ok(format)
+    }
+}
+
+/// Format as a list of attributes, such as `bold fg=Red borders=left,top number=0.00`.
+impl std::fmt::Display for CellFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut attributes = Vec::new();
+        for (set, name) in [
+            (self.bold, "bold"),
+            (self.italic, "italic"),
+            (self.underline, "underline"),
+        ] {
+            if set {
+                attributes.push(name.to_string());
+            }
+        }
+        if let Some(color) = self.foreground {
+            attributes.push(format!("fg={color}"));
+        }
+        if let Some(color) = self.background {
+            attributes.push(format!("bg={color}"));
+        }
+        if let Some(alignment) = self.alignment {
+            attributes.push(format!("align={}", format!("{alignment:?}").to_lowercase()));
+        }
+        if self.borders != Borders::NONE {
+            attributes.push(format!(
+                "borders={}",
+                borders_to_string(self.borders).replace(' ', ",")
+            ));
+        }
+        if let Some(number) = &self.number {
+            attributes.push(format!("number={number}"));
+        }
+        write!(f, "{}", attributes.join(" "))
+    }
+}
+
+/// Draw box-drawing borders around a cell, returning the area left for its content.
+/// A cell one line high has no room for horizontal lines, so its bottom edge is underlined
+/// instead. This also stands for the top border of the cell below, hence `underline`.
+pub fn render_borders(
+    borders: Borders,
+    underline: bool,
+    frame: &mut ratatui::Frame,
+    area: ratatui::layout::Rect,
+) -> ratatui::layout::Rect {
+    use ratatui::style::{Style, Stylize};
+
+    let borders = match area.height {
+        0 | 1 => borders & (Borders::LEFT | Borders::RIGHT),
+        _ => borders,
+    };
+    let block = ratatui::widgets::Block::new()
+        .borders(borders)
+        .border_style(Style::new().fg(BORDER_COLOR));
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+    if underline && area.height == 1 {
+        frame
+            .buffer_mut()
+            .set_style(area, Style::new().underlined());
+    }
+    inner
+}
+
+/// Names of the border sides, as read and written by the borders prompt.
+const BORDER_NAMES: [(&str, Borders); 4] = [
+    ("left", Borders::LEFT),
+    ("right", Borders::RIGHT),
+    ("top", Borders::TOP),
+    ("bottom", Borders::BOTTOM),
+];
+
+/// Describe borders as a list of sides, such as `left top`.
+pub fn borders_to_string(borders: Borders) -> String {
+    match borders {
+        Borders::NONE => "none".to_string(),
+        Borders::ALL => "all".to_string(),
+        borders => BORDER_NAMES
+            .iter()
+            .filter(|(_, side)| borders.contains(*side))
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+/// Modification of the format of the selected cells.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormatChange {
+    NumberFormat(Option<NumberFormat>),
+    Bold(bool),
+    Italic(bool),
+    Underline(bool),
+    Foreground(Option<Color>),
+    Background(Option<Color>),
+    Alignment(Option<Alignment>),
+    /// Set the same borders on every cell.
+    Borders(Borders),
+    /// Set borders only around the outer edges of the selection.
+    Outline,
+    /// Reset the format to the default one.
+    Clear,
+}
+
+impl FormatChange {
+    /// Read the answer of the borders prompt: `none`, `all`, `outline` or a list of sides.
+    pub fn parse_borders(answer: &str) -> Result<FormatChange, String> {
+        let mut borders = Borders::NONE;
+        for word in answer.split([' ', ',']).filter(|word| !word.is_empty()) {
+            let word = word.to_ascii_lowercase();
+            match word.as_str() {
+                "none" => {}
+                "all" => borders |= Borders::ALL,
+                "outline" => return Ok(FormatChange::Outline),
+                side => match BORDER_NAMES.iter().find(|(name, _)| *name == side) {
+                    Some((_, side)) => borders |= *side,
+                    None => return Err(format!("Unknown border \"{word}\"")),
+                },
+            }
+        }
+        Ok(FormatChange::Borders(borders))
+    }
+
+    /// Read the answer of a colour prompt, an empty answer resets the colour.
+    pub fn parse_color(answer: &str) -> Result<Option<Color>, String> {
+        match answer.trim() {
+            "" => Ok(None),
+            name => name
+                .parse::<Color>()
+                .map(Some)
+                .map_err(|_| format!("Unknown colour \"{name}\"")),
+        }
+    }
+
+    /// Apply the change to the format of the cell at `index`, part of the `cells` selection.
+    pub fn apply(
+        &self,
+        format: &mut CellFormat,
+        index: crate::file::cell::CellIndex,
+        cells: crate::file::cell::CellRect,
+    ) {
+        match self {
+            FormatChange::NumberFormat(number_format) => format.number = number_format.clone(),
+            FormatChange::Bold(bold) => format.bold = *bold,
+            FormatChange::Italic(italic) => format.italic = *italic,
+            FormatChange::Underline(underline) => format.underline = *underline,
+            FormatChange::Foreground(color) => format.foreground = *color,
+            FormatChange::Background(color) => format.background = *color,
+            FormatChange::Alignment(alignment) => format.alignment = *alignment,
+            FormatChange::Borders(borders) => format.borders = *borders,
+            FormatChange::Outline => {
+                let mut borders = Borders::NONE;
+                borders.set(Borders::LEFT, index.x == cells.x);
+                borders.set(Borders::RIGHT, index.x == cells.x + cells.width - 1);
+                borders.set(Borders::TOP, index.y == cells.y);
+                borders.set(Borders::BOTTOM, index.y == cells.y + cells.height - 1);
+                format.borders = borders;
+            }
+            FormatChange::Clear => *format = CellFormat::default(),
+        }
+    }
+}
+
+impl std::fmt::Display for FormatChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let on_off = |value: bool| if value { "on" } else { "off" };
+        match self {
+            FormatChange::NumberFormat(Some(number_format)) => {
+                write!(f, "number format \"{number_format}\"")
+            }
+            FormatChange::NumberFormat(None) => write!(f, "general number format"),
+            FormatChange::Bold(bold) => write!(f, "bold {}", on_off(*bold)),
+            FormatChange::Italic(italic) => write!(f, "italic {}", on_off(*italic)),
+            FormatChange::Underline(underline) => write!(f, "underline {}", on_off(*underline)),
+            FormatChange::Foreground(Some(color)) => write!(f, "text colour {color}"),
+            FormatChange::Foreground(None) => write!(f, "default text colour"),
+            FormatChange::Background(Some(color)) => write!(f, "fill colour {color}"),
+            FormatChange::Background(None) => write!(f, "no fill colour"),
+            FormatChange::Alignment(Some(alignment)) => {
+                write!(f, "{} alignment", format!("{alignment:?}").to_lowercase())
+            }
+            FormatChange::Alignment(None) => write!(f, "default alignment"),
+            FormatChange::Borders(borders) => write!(f, "borders {}", borders_to_string(*borders)),
+            FormatChange::Outline => write!(f, "outline borders"),
+            FormatChange::Clear => write!(f, "cleared format"),
+        }
+    }
+}
@@ -0,0 +1,422 @@
+use ratatui::style::Color;
+
+use crate::file::cell;
+use crate::file::formula::{Evaluator, Expr, Value};
+
+/// Formatting applied to the cells of a range whose value matches a condition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub cells: cell::CellRect,
+    pub condition: Condition,
+    pub style: RuleStyle,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Greater(f64),
+    Less(f64),
+    /// Inclusive bounds.
+    Between(f64, f64),
+    /// Text contained in the value, case insensitive.
+    Contains(String),
+    /// The N highest numbers of the range.
+    Top(usize),
+    /// Formula written for the top left cell of the range, moved as if copied on the others.
+    Formula(Expr),
+    /// Background colour interpolated from the minimum to the maximum of the range.
+    ColorScale(Color, Color),
+    /// Bar proportional to the value, between the minimum and the maximum of the range.
+    DataBar(Color),
+}
+
+/// Style applied by the rules with a condition.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RuleStyle {
+    pub foreground: Option<Color>,
+    pub background: Option<Color>,
+    pub bold: bool,
+}
+
+impl RuleStyle {
+    /// Apply on top of the format of a cell.
+    pub fn apply(&self, format: &mut super::CellFormat) {
+        format.foreground = self.foreground.or(format.foreground);
+        format.background = self.background.or(format.background);
+        format.bold |= self.bold;
+    }
+}
+
+/// What a rule does to a matching cell.
+pub enum Effect {
+    Style(RuleStyle),
+    /// Data bar, filling the given ratio of the cell width.
+    DataBar {
+        ratio: f64,
+        color: Color,
+    },
+}
+
+/// Statistics over the numbers of a rule range, computed once per render.
+pub struct RuleStats {
+    min: f64,
+    max: f64,
+    /// Smallest number part of the top N.
+    top_threshold: Option<f64>,
+}
+
+impl Rule {
+    /// Parse a rule written as `<cells> <condition> [fg=<colour>] [bg=<colour>] [bold]`,
+    /// with one of these conditions: `> N`, `< N`, `between N M`, `contains <text>`, `top N`,
+    /// `formula =<formula>`, `scale <colour> <colour>` or `bar <colour>`.
+    pub fn parse(source: &str) -> Result<Rule, String> {
+        let source = source.trim();
+        let (cells, mut rest) = source.split_once(' ').unwrap_or((source, ""));
+        let cells =
+            cell::CellRect::parse(cells).ok_or(format!("Invalid cells \"{cells}\" in rule"))?;
+
+        // style options are read from the end, so conditions can contain spaces
+        let mut style = RuleStyle::default();
+        while let Some((start, last)) = rest.trim_end().rsplit_once(' ') {
+            if let Some(color) = last.strip_prefix("fg=") {
+                style.foreground = Some(parse_color(color)?);
+            } else if let Some(color) = last.strip_prefix("bg=") {
+                style.background = Some(parse_color(color)?);
+            } else if last.eq_ignore_ascii_case("bold") {
+                style.bold = true;
+            } else {
+                break;
+            }
+            rest = start;
+        }
+
+        let rest = rest.trim();
+        let (keyword, argument) = rest.split_once(' ').unwrap_or((rest, ""));
+        let argument = argument.trim();
+        let number = |number: &str| {
+            number
+                .parse::<f64>()
+                .map_err(|_| format!("Invalid number \"{number}\" in rule"))
+        };
+        let condition = match keyword.to_ascii_lowercase().as_str() {
+            ">" => Condition::Greater(number(argument)?),
+            "<" => Condition::Less(number(argument)?),
+            "between" => {
+                let (low, high) = argument
+                    .split_once(' ')
+                    .ok_or("Expected two numbers after \"between\"")?;
+                Condition::Between(number(low.trim())?, number(high.trim())?)
+            }
+            "contains" => {
+                let text = argument
+                    .strip_prefix('"')
+                    .and_then(|text| text.strip_suffix('"'))
+                    .unwrap_or(argument);
+                Condition::Contains(text.replace("\"\"", "\""))
+            }
+            "top" => Condition::Top(
+                argument
+                    .parse()
+                    .map_err(|_| format!("Invalid count \"{argument}\" in rule"))?,
+            ),
+            "formula" => {
+                let source = argument.strip_prefix('=').unwrap_or(argument);
+                match crate::file::formula::Formula::parse(source) {
+                    crate::file::formula::Formula::Expr(expr) => Condition::Formula(expr),
+                    crate::file::formula::Formula::Invalid { error, .. } => return Err(error),
+                }
+            }
+            "scale" => {
+                let (low, high) = argument
+                    .split_once(' ')
+                    .ok_or("Expected two colours after \"scale\"")?;
+                Condition::ColorScale(parse_color(low.trim())?, parse_color(high.trim())?)
+            }
+            "bar" => Condition::DataBar(parse_color(argument)?),
+            "" => return Err("Missing condition in rule".to_string()),
+            other => return Err(format!("Unknown condition \"{other}\" in rule")),
+        };
+        Ok(Rule {
+            cells,
+            condition,
+            style,
+        })
+    }
+
+    /// Rule following its cells when rows or columns are inserted or deleted.
+    /// None when all its cells are deleted.
+    pub fn shifted(&self, shift: &cell::LineShift) -> Option<Rule> {
+        let condition = match &self.condition {
+            Condition::Formula(expr) => Condition::Formula(
+                expr.map_references(&|reference, role| reference.shifted(shift, role)),
+            ),
+            other => other.clone(),
+        };
+        Some(Rule {
+            cells: shift.rect(self.cells)?,
+            condition,
+            style: self.style.clone(),
+        })
+    }
+
+    /// Rule following a block of cells moved by an offset, when its range is inside the block.
+    pub fn moved(&self, from: cell::CellRect, dx: i64, dy: i64) -> Rule {
+        let cells = match from.contains(cell::CellIndex::new(self.cells.x, self.cells.y))
+            && from.contains(cell::CellIndex::new(
+                self.cells.x + self.cells.width - 1,
+                self.cells.y + self.cells.height - 1,
+            )) {
+            true => self.cells.offset(dx, dy).unwrap_or(self.cells),
+            false => self.cells,
+        };
+        let condition = match &self.condition {
+            Condition::Formula(expr) => Condition::Formula(
+                expr.map_references(&|reference, role| reference.moved(from, dx, dy, role)),
+            ),
+            other => other.clone(),
+        };
+        Rule {
+            cells,
+            condition,
+            style: self.style.clone(),
+        }
+    }
+
+    /// Statistics over the numbers of the range, only read by the conditions comparing each value
+    /// to the others: the other rules skip evaluating their whole range on every render.
+    pub fn stats(&self, evaluator: &Evaluator) -> RuleStats {
+        let mut stats = RuleStats {
+            min: 0.0,
+            max: 0.0,
+            top_threshold: None,
+        };
+        if !matches!(
+            self.condition,
+            Condition::Top(_) | Condition::ColorScale(..) | Condition::DataBar(_)
+        ) {
+            return stats;
+        }
+        let mut numbers = evaluator
+            .values_in(self.cells)
+            .into_iter()
+            .filter(|(_, value)| value.is_numeric())
+            .filter_map(|(_, value)| value.as_number().ok())
+            .collect::<Vec<_>>();
+        if let (Some(min), Some(max)) = (
+            numbers.iter().copied().min_by(f64::total_cmp),
+            numbers.iter().copied().max_by(f64::total_cmp),
+        ) {
+            (stats.min, stats.max) = (min, max);
+        }
+        if let Condition::Top(count) = self.condition
+            && count > 0
+            && !numbers.is_empty()
+        {
+            let nth = count.min(numbers.len()) - 1;
+            let (_, threshold, _) = numbers.select_nth_unstable_by(nth, |a, b| b.total_cmp(a));
+            stats.top_threshold = Some(*threshold);
+        }
+        stats
+    }
+
+    /// Effect of the rule on the cell at `index`, with the given value, if it matches.
+    pub fn effect(
+        &self,
+        index: cell::CellIndex,
+        value: &Value,
+        stats: &RuleStats,
+        evaluator: &Evaluator,
+    ) -> Option<Effect> {
+        if !self.cells.contains(index) {
+            return None;
+        }
+        let number = value.is_numeric().then(|| value.as_number().ok()).flatten();
+        // ratio of the value between the range bounds, for scales and bars
+        let ratio = number.map(|number| match stats.max - stats.min {
+            0.0 => 1.0,
+            span => (number - stats.min) / span,
+        });
+        let matches = match &self.condition {
+            _ if *value == Value::Empty && !matches!(self.condition, Condition::Formula(_)) => {
+                false
+            }
+            Condition::Greater(bound) => number.is_some_and(|number| number > *bound),
+            Condition::Less(bound) => number.is_some_and(|number| number < *bound),
+            Condition::Between(low, high) => {
+                number.is_some_and(|number| (*low..=*high).contains(&number))
+            }
+            Condition::Contains(text) => value
+                .as_text()
+                .is_ok_and(|value| value.to_lowercase().contains(&text.to_lowercase())),
+            Condition::Top(_) => number
+                .zip(stats.top_threshold)
+                .is_some_and(|(number, threshold)| number >= threshold),
+            Condition::Formula(expr) => {
+                let dx = index.x as i64 - self.cells.x as i64;
+                let dy = index.y as i64 - self.cells.y as i64;
+                let expr = expr.map_references(&|reference, _| reference.offset(dx, dy));
+                evaluator
+                    .eval(&expr)
+                    .is_ok_and(|result| result.as_bool().unwrap_or(false))
+            }
+            Condition::ColorScale(low, high) => {
+                return ratio.map(|ratio| {
+                    Effect::Style(RuleStyle {
+                        background: Some(interpolate(*low, *high, ratio)),
+                        ..self.style.clone()
+                    })
+                });
+            }
+            Condition::DataBar(color) => {
+                return ratio.map(|ratio| Effect::DataBar {
+                    ratio,
+                    color: *color,
+                });
+            }
+        };
+        matches.then(|| Effect::Style(self.style.clone()))
+    }
+}
+
+impl std::fmt::Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ", self.cells)?;
+        match &self.condition {
+            Condition::Greater(bound) => write!(f, "> {bound}")?,
+            Condition::Less(bound) => write!(f, "< {bound}")?,
+            Condition::Between(low, high) => write!(f, "between {low} {high}")?,
+            Condition::Contains(text) => write!(f, "contains \"{}\"", text.replace('"', "\"\""))?,
+            Condition::Top(count) => write!(f, "top {count}")?,
+            Condition::Formula(expr) => write!(f, "formula ={expr}")?,
+            Condition::ColorScale(low, high) => write!(f, "scale {low} {high}")?,
+            Condition::DataBar(color) => write!(f, "bar {color}")?,
+        }
+        if let Some(color) = self.style.foreground {
+            write!(f, " fg={color}")?;
+        }
+        if let Some(color) = self.style.background {
+            write!(f, " bg={color}")?;
+        }
+        if self.style.bold {
+            write!(f, " bold")?;
+        }
+        Ok(())
+    }
+}
+
+/// Read the rules of the file from its sidecar, with a line for each rule, as written in the rules
+/// dialog. Invalid lines are skipped.
+pub fn read(path: &std::path::Path) -> Vec<Rule> {
+    let text = std::fs::read_to_string(crate::file::hidden_path(path, "rules")).unwrap_or_default();
+    text.lines()
+        .filter_map(|line| Rule::parse(line).ok())
+        .collect()
+}
+
+/// Write the rules of the file next to it, removing the sidecar when there are none.
+pub fn write(path: &std::path::Path, rules: &[Rule]) -> std::io::Result<()> {
+    let text = rules
+        .iter()
+        .map(|rule| format!("{rule}\n"))
+        .collect::<String>();
+    crate::file::write_hidden(path, "rules", &text)
+}
+
+fn parse_color(name: &str) -> Result<Color, String> {
+    name.parse::<Color>()
+        .map_err(|_| format!("Unknown colour \"{name}\""))
+}
+
+/// Approximate RGB value of a terminal colour, using the xterm palette.
+fn rgb(color: Color) -> (u8, u8, u8) {
+    const BASIC: [(u8, u8, u8); 16] = [
+        (0, 0, 0),
+        (205, 0, 0),
+        (0, 205, 0),
+        (205, 205, 0),
+        (0, 0, 238),
+        (205, 0, 205),
+        (0, 205, 205),
+        (229, 229, 229),
+        (127, 127, 127),
+        (255, 0, 0),
+        (0, 255, 0),
+        (255, 255, 0),
+        (92, 92, 255),
+        (255, 0, 255),
+        (0, 255, 255),
+        (255, 255, 255),
+    ];
+    const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+    let index = match color {
+        Color::Rgb(r, g, b) => return (r, g, b),
+        Color::Reset | Color::Black => 0,
+        Color::Red => 1,
+        Color::Green => 2,
+        Color::Yellow => 3,
+        Color::Blue => 4,
+        Color::Magenta => 5,
+        Color::Cyan => 6,
+        Color::Gray => 7,
+        Color::DarkGray => 8,
+        Color::LightRed => 9,
+        Color::LightGreen => 10,
+        Color::LightYellow => 11,
+        Color::LightBlue => 12,
+        Color::LightMagenta => 13,
+        Color::LightCyan => 14,
+        Color::White => 15,
+        Color::Indexed(index) => index,
+    };
+    match index {
+        0..16 => BASIC[usize::from(index)],
+        16..232 => {
+            let cube = usize::from(index - 16);
+            (
+                CUBE_LEVELS[cube / 36],
+                CUBE_LEVELS[cube / 6 % 6],
+                CUBE_LEVELS[cube % 6],
+            )
+        }
+        _ => {
+            let gray = 8 + (index - 232) * 10;
+            (gray, gray, gray)
+        }
+    }
+}
+
+fn interpolate(low: Color, high: Color, ratio: f64) -> Color {
+    let (low, high) = (rgb(low), rgb(high));
+    let ratio = ratio.clamp(0.0, 1.0);
+    let mix =
+        |low: u8, high: u8| (f64::from(low) + (f64::from(high) - f64::from(low)) * ratio) as u8;
+    Color::Rgb(mix(low.0, high.0), mix(low.1, high.1), mix(low.2, high.2))
+}
+
+/// Draw a data bar with block characters over a rendered cell.
+/// Where the cell content is written, the bar is drawn as a background instead.
+pub fn render_data_bar(
+    ratio: f64,
+    color: Color,
+    frame: &mut ratatui::Frame,
+    area: ratatui::layout::Rect,
+) {
+    const EIGHTHS: [&str; 8] = ["▏", "▎", "▍", "▌", "▋", "▊", "▉", "█"];
+
+    let eighths = (ratio.clamp(0.0, 1.0) * f64::from(area.width) * 8.0).round() as u16;
+    let buffer = frame.buffer_mut();
+    for y in area.top()..area.bottom() {
+        for x in 0..eighths.div_ceil(8) {
+            let Some(cell) = buffer.cell_mut((area.x + x, y)) else {
+                continue;
+            };
+            if cell.symbol() == " " {
+                let filled = (eighths - x * 8).min(8);
+                cell.set_symbol(EIGHTHS[usize::from(filled - 1)])
+                    .set_fg(color);
+            } else {
+                cell.set_bg(color);
+            }
+        }
+    }
+}
@@ -0,0 +1,101 @@
+/// Calendar helpers, working on serial day numbers as spreadsheets do:
+/// the serial is the number of days since 1899-12-30, so that 1900-03-01 is 61 like in Excel.
+const SERIAL_EPOCH_OFFSET: i64 = 25569; // 1970-01-01 as a serial day
+
+/// Serial days of the handled dates, from 0000-01-01 to 9999-12-31.
+pub const SERIAL_RANGE: std::ops::RangeInclusive<i64> = -693959..=2958465;
+
+pub const MONTH_NAMES: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+pub const DAY_NAMES: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+
+/// Whether the serial date, with an optional time of day, is in the handled range of dates.
+pub fn in_range(serial: f64) -> bool {
+    (*SERIAL_RANGE.start() as f64..(*SERIAL_RANGE.end() + 1) as f64).contains(&serial)
+}
+
+/// Convert a serial day number to a (year, month, day) triple, months and days starting at 1.
+/// None when the year overflows.
+pub fn serial_to_civil(serial: i64) -> Option<(i64, u32, u32)> {
+    // Howard Hinnant's days_from_civil inverse, shifted from the unix epoch.
+    let z = serial
+        .checked_sub(SERIAL_EPOCH_OFFSET)?
+        .checked_add(719468)?;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = u32::try_from(doy - (153 * mp + 2) / 5 + 1).unwrap_or(1);
+    let month = u32::try_from(if mp < 10 { mp + 3 } else { mp - 9 }).unwrap_or(1);
+    let year = era
+        .checked_mul(400)?
+        .checked_add(yoe + i64::from(month <= 2))?;
+    Some((year, month, day))
+}
+
+/// Day of the week of the given serial day, 0 being monday.
+pub fn serial_weekday(serial: i64) -> usize {
+    // 1899-12-30 was a saturday
+    usize::try_from((serial.rem_euclid(7) + 5) % 7).unwrap_or(0)
+}
+
+/// Convert a (year, month, day) triple to a serial day number, months and days starting at 1.
+/// None when the serial overflows.
+pub fn civil_to_serial(year: i64, month: u32, day: u32) -> Option<i64> {
+    let year = year.checked_sub(i64::from(month <= 2))?;
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = i64::from(month);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era.checked_mul(146097)?
+        .checked_add(doe - 719468 + SERIAL_EPOCH_OFFSET)
+}
+
+/// Number of days in the given month.
+pub fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        _ if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        _ => 28,
+    }
+}
+
+/// Split a serial date time into its serial day and its time of day, as a fraction of day.
+pub fn split_serial(serial: f64) -> (i64, f64) {
+    let days = serial.floor();
+    (days as i64, serial - days)
+}
+
+/// Current date and time as a serial, in UTC.
+pub fn now_serial() -> f64 {
+    let seconds = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs_f64())
+        .unwrap_or(0.0);
+    SERIAL_EPOCH_OFFSET as f64 + seconds / 86400.0
+}
@@ -0,0 +1,514 @@
+use super::date;
+
+/// Number format, built from an Excel-like format code such as `#,##0.00`, `0%`, `€#,##0.00`,
+/// `0.00E+00` or `yyyy-mm-dd`.
+/// A code can have up to three sections separated by `;`, for positive, negative and zero values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NumberFormat {
+    code: String,
+    sections: Vec<Section>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Section {
+    General,
+    Number(NumberSection),
+    Date(Vec<DateToken>),
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct NumberSection {
+    prefix: String,
+    suffix: String,
+    has_digits: bool,
+    integer_digits: usize,
+    min_decimals: usize,
+    max_decimals: usize,
+    thousands: bool,
+    percent: bool,
+    exponent_digits: Option<usize>,
+    exponent_plus: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum DateToken {
+    Literal(String),
+    Year {
+        digits: usize,
+    },
+    /// 1 or 2 digits are numeric, 3 is the short name, more is the full name.
+    Month {
+        digits: usize,
+    },
+    /// 1 or 2 digits are numeric, 3 is the short week day name, more is the full name.
+    Day {
+        digits: usize,
+    },
+    Hour {
+        digits: usize,
+    },
+    Minute {
+        digits: usize,
+    },
+    Second {
+        digits: usize,
+    },
+    AmPm,
+}
+
+/// Format code pieces, once quotes, escapes and brackets are resolved.
+#[derive(Debug, Clone, PartialEq)]
+enum RawToken {
+    Literal(String),
+    Code(char),
+}
+
+impl NumberFormat {
+    pub fn parse(code: &str) -> Result<NumberFormat, String> {
+        let sections = split_sections(code);
+        // Excel's fourth section formats texts, which are shown as they are here
+        if sections.len() > 3 {
+            return Err(format!("Too many sections in number format \"{code}\""));
+        }
+        let sections = sections
+            .iter()
+            .map(|section| parse_section(section))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(NumberFormat {
+            code: code.to_string(),
+            sections,
+        })
+    }
+
+    /// The format code this format was created from.
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    /// Format a value with the general format, used when a cell has no number format.
+    pub fn general(value: f64) -> String {
+        format_general(value)
+    }
+
+    pub fn format(&self, value: f64) -> String {
+        if !value.is_finite() {
+            return "#NUM!".to_string();
+        }
+        match self.sections.as_slice() {
+            [] => format_general(value),
+            [section] => section.format(value),
+            [_, negative, ..] if value < 0.0 => negative.format(value.abs()),
+            [_, _, zero, ..] if value == 0.0 => zero.format(value),
+            [positive, ..] => positive.format(value),
+        }
+    }
+}
+
+impl std::fmt::Display for NumberFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.code)
+    }
+}
+
+impl Section {
+    fn format(&self, value: f64) -> String {
+        match self {
+            Section::General => format_general(value),
+            Section::Number(number) => number.format(value),
+            Section::Date(tokens) => format_date(tokens, value),
+        }
+    }
+}
+
+impl NumberSection {
+    fn format(&self, value: f64) -> String {
+        let mut abs_value = value.abs();
+        if self.percent {
+            abs_value *= 100.0;
+        }
+        let body = match (self.has_digits, self.exponent_digits) {
+            (false, _) => String::with_capacity(0),
+            (true, Some(exponent_digits)) => format_scientific(
+                abs_value,
+                self.integer_digits.max(1),
+                self.min_decimals,
+                self.max_decimals,
+                exponent_digits,
+                self.exponent_plus,
+            ),
+            (true, None) => format_fixed(
+                abs_value,
+                self.integer_digits,
+                self.min_decimals,
+                self.max_decimals,
+                self.thousands,
+            ),
+        };
+        let is_zero = !body.chars().any(|ch| ch.is_ascii_digit() && ch != '0');
+        let sign = if value < 0.0 && !is_zero { "-" } else { "" };
+        format!("{sign}{}{body}{}", self.prefix, self.suffix)
+    }
+}
+
+/// Split a format code on its `;` separators, ignoring the ones in quotes.
+fn split_sections(code: &str) -> Vec<String> {
+    let mut sections = vec![String::new()];
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for ch in code.chars() {
+        match ch {
+            ';' if !in_quotes && !escaped => sections.push(String::new()),
+            _ => {
+                if let Some(section) = sections.last_mut() {
+                    section.push(ch);
+                }
+            }
+        }
+        escaped = !escaped && !in_quotes && ch == '\\';
+        if ch == '"' && !escaped {
+            in_quotes = !in_quotes;
+        }
+    }
+    sections
+}
+
+fn tokenize(section: &str) -> Result<Vec<RawToken>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = section.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' => {
+                let literal = chars.by_ref().take_while(|ch| *ch != '"').collect();
+                tokens.push(RawToken::Literal(literal));
+            }
+            '\\' => match chars.next() {
+                Some(escaped) => tokens.push(RawToken::Literal(escaped.to_string())),
+                None => return Err("Trailing escape in number format".to_string()),
+            },
+            '_' => {
+                chars.next();
+                tokens.push(RawToken::Literal(" ".to_string()));
+            }
+            '*' => {
+                chars.next();
+            }
+            '[' => {
+                let bracket = chars
+                    .by_ref()
+                    .take_while(|ch| *ch != ']')
+                    .collect::<String>();
+                // currency and locale blocks such as [$€-407], colors and conditions are ignored
+                if let Some(currency) = bracket.strip_prefix('$') {
+                    let symbol = currency.split('-').next().unwrap_or_default();
+                    tokens.push(RawToken::Literal(symbol.to_string()));
+                }
+            }
+            ch => tokens.push(RawToken::Code(ch)),
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_section(section: &str) -> Result<Section, String> {
+    if section.trim().eq_ignore_ascii_case("general") || section.is_empty() {
+        return Ok(Section::General);
+    }
+
+    let tokens = tokenize(section)?;
+    let codes = tokens
+        .iter()
+        .filter_map(|token| match token {
+            RawToken::Code(ch) => Some(ch.to_ascii_lowercase()),
+            RawToken::Literal(_) => None,
+        })
+        .collect::<Vec<_>>();
+    let has_placeholders = codes.iter().any(|ch| matches!(ch, '0' | '#' | '?'));
+    let has_date_codes = codes.iter().any(|ch| matches!(ch, 'y' | 'd' | 'h' | 's'))
+        || (codes.contains(&'m') && !has_placeholders);
+
+    if has_date_codes {
+        Ok(Section::Date(parse_date_tokens(&tokens)))
+    } else {
+        Ok(Section::Number(parse_number_tokens(&tokens)))
+    }
+}
+
+fn parse_number_tokens(tokens: &[RawToken]) -> NumberSection {
+    let mut section = NumberSection::default();
+    let mut after_point = false;
+    let mut index = 0;
+
+    let push_literal = |section: &mut NumberSection, literal: &str| {
+        if section.has_digits {
+            section.suffix.push_str(literal);
+        } else {
+            section.prefix.push_str(literal);
+        }
+    };
+
+    while let Some(token) = tokens.get(index) {
+        match token {
+            RawToken::Literal(literal) => push_literal(&mut section, literal),
+            RawToken::Code(ch @ ('0' | '#' | '?')) => {
+                match (after_point, ch) {
+                    (false, '0') => section.integer_digits += 1,
+                    (false, _) => {}
+                    (true, '0') => {
+                        section.min_decimals += 1;
+                        section.max_decimals += 1;
+                    }
+                    (true, _) => section.max_decimals += 1,
+                }
+                section.has_digits = true;
+            }
+            RawToken::Code('.') if section.exponent_digits.is_none() => {
+                after_point = true;
+                section.has_digits = true;
+            }
+            RawToken::Code(',') if section.has_digits && !after_point => {
+                section.thousands = true;
+            }
+            RawToken::Code('%') => {
+                section.percent = true;
+                push_literal(&mut section, "%");
+            }
+            RawToken::Code('E' | 'e')
+                if section.has_digits
+                    && matches!(tokens.get(index + 1), Some(RawToken::Code('+' | '-'))) =>
+            {
+                section.exponent_plus = tokens.get(index + 1) == Some(&RawToken::Code('+'));
+                index += 2;
+                let mut exponent_digits = 0;
+                while let Some(RawToken::Code('0' | '#')) = tokens.get(index) {
+                    exponent_digits += 1;
+                    index += 1;
+                }
+                section.exponent_digits = Some(exponent_digits.max(1));
+                continue;
+            }
+            RawToken::Code(ch) => push_literal(&mut section, &ch.to_string()),
+        }
+        index += 1;
+    }
+
+    section
+}
+
+fn parse_date_tokens(tokens: &[RawToken]) -> Vec<DateToken> {
+    let mut result = Vec::new();
+    let mut index = 0;
+
+    while let Some(token) = tokens.get(index) {
+        let ch = match token {
+            RawToken::Literal(literal) => {
+                result.push(DateToken::Literal(literal.clone()));
+                index += 1;
+                continue;
+            }
+            RawToken::Code(ch) => *ch,
+        };
+
+        let am_pm = ['M', '/', 'P', 'M'];
+        let is_am_pm = ch.eq_ignore_ascii_case(&'a')
+            && am_pm.iter().enumerate().all(|(offset, expected)| {
+                matches!(tokens.get(index + 1 + offset), Some(RawToken::Code(c)) if c.eq_ignore_ascii_case(expected))
+            });
+        if is_am_pm {
+            result.push(DateToken::AmPm);
+            index += 1 + am_pm.len();
+            continue;
+        }
+
+        let lower = ch.to_ascii_lowercase();
+        let mut digits = 1;
+        while matches!(tokens.get(index + digits), Some(RawToken::Code(c)) if c.to_ascii_lowercase() == lower)
+        {
+            digits += 1;
+        }
+        index += digits;
+
+        result.push(match lower {
+            'y' => DateToken::Year { digits },
+            'm' => DateToken::Month { digits },
+            'd' => DateToken::Day { digits },
+            'h' => DateToken::Hour { digits },
+            's' => DateToken::Second { digits },
+            _ => DateToken::Literal(std::iter::repeat_n(ch, digits).collect()),
+        });
+    }
+
+    // "m" means minutes when right after hours or right before seconds
+    let codes = result
+        .iter()
+        .enumerate()
+        .filter(|(_, token)| !matches!(token, DateToken::Literal(_)))
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    for (position, &token_index) in codes.iter().enumerate() {
+        let previous = position
+            .checked_sub(1)
+            .and_then(|p| codes.get(p))
+            .and_then(|i| result.get(*i));
+        let next = codes.get(position + 1).and_then(|i| result.get(*i));
+        let is_minute = matches!(previous, Some(DateToken::Hour { .. }))
+            || matches!(next, Some(DateToken::Second { .. }));
+        if let Some(&DateToken::Month {
+            digits: digits @ (1 | 2),
+        }) = result.get(token_index)
+            && is_minute
+        {
+            result[token_index] = DateToken::Minute { digits };
+        }
+    }
+
+    result
+}
+
+/// Excel-like general format: as many digits as fit in about 11 characters,
+/// switching to scientific notation for very large or small numbers.
+fn format_general(value: f64) -> String {
+    if !value.is_finite() {
+        return "#NUM!".to_string();
+    }
+    if value == 0.0 {
+        return "0".to_string();
+    }
+    let sign = if value < 0.0 { "-" } else { "" };
+    let abs_value = value.abs();
+    let magnitude = abs_value.log10().floor();
+    let body = if !(-9.0..11.0).contains(&magnitude) {
+        format_scientific(abs_value, 1, 0, 5, 2, true)
+    } else {
+        let integer_len = (magnitude + 1.0).max(1.0);
+        let decimals = (10.0 - integer_len).max(0.0);
+        format_fixed(abs_value, 1, 0, decimals as usize, false)
+    };
+    format!("{sign}{body}")
+}
+
+fn format_fixed(
+    value: f64,
+    integer_digits: usize,
+    min_decimals: usize,
+    max_decimals: usize,
+    thousands: bool,
+) -> String {
+    let repr = format!("{value:.max_decimals$}");
+    let (integer, fraction) = repr.split_once('.').unwrap_or((&repr, ""));
+
+    let trimmed_len = fraction.trim_end_matches('0').len().max(min_decimals);
+    let fraction = &fraction[..trimmed_len.min(fraction.len())];
+
+    let integer = match integer {
+        "0" if integer_digits == 0 => "",
+        integer => integer,
+    };
+    let padding = integer_digits.saturating_sub(integer.len());
+    let integer = "0".repeat(padding) + integer;
+    let integer = if thousands {
+        group_thousands(&integer)
+    } else {
+        integer
+    };
+
+    match fraction.is_empty() {
+        true => integer,
+        false => format!("{integer}.{fraction}"),
+    }
+}
+
+fn format_scientific(
+    value: f64,
+    integer_digits: usize,
+    min_decimals: usize,
+    max_decimals: usize,
+    exponent_digits: usize,
+    exponent_plus: bool,
+) -> String {
+    let integer_digits = i32::try_from(integer_digits).unwrap_or(1);
+    let (mut mantissa, mut exponent) = match value {
+        0.0 => (0.0, 0),
+        value => {
+            let exponent = value.log10().floor() as i32 - (integer_digits - 1);
+            (value / 10f64.powi(exponent), exponent)
+        }
+    };
+    // rounding may carry into an extra integer digit, as in 9.999 -> 10.00
+    let rounded = format!("{mantissa:.max_decimals$}")
+        .parse::<f64>()
+        .unwrap_or(mantissa);
+    if rounded >= 10f64.powi(integer_digits) {
+        mantissa = rounded / 10.0;
+        exponent += 1;
+    }
+
+    let body = format_fixed(mantissa, 1, min_decimals, max_decimals, false);
+    let sign = match (exponent < 0, exponent_plus) {
+        (true, _) => "-",
+        (false, true) => "+",
+        (false, false) => "",
+    };
+    format!("{body}E{sign}{:0exponent_digits$}", exponent.unsigned_abs())
+}
+
+fn group_thousands(digits: &str) -> String {
+    let mut result = String::with_capacity(digits.len() + digits.len() / 3);
+    for (index, ch) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            result.push(',');
+        }
+        result.push(ch);
+    }
+    result
+}
+
+fn format_date(tokens: &[DateToken], value: f64) -> String {
+    // numbers out of the range of dates are shown as errors, as spreadsheets do
+    if !date::in_range(value) {
+        return "#NUM!".to_string();
+    }
+    let mut days = value.floor() as i64;
+    let mut seconds = ((value - value.floor()) * 86400.0).round() as i64;
+    if seconds >= 86400 {
+        days += 1;
+        seconds -= 86400;
+    }
+    let Some((year, month, day)) = date::serial_to_civil(days) else {
+        return "#NUM!".to_string();
+    };
+    let (hour, minute, second) = (seconds / 3600, (seconds / 60) % 60, seconds % 60);
+    let twelve_hours = tokens.contains(&DateToken::AmPm);
+    let month_name = date::MONTH_NAMES[usize::try_from(month).unwrap_or(1).saturating_sub(1) % 12];
+    let day_name = date::DAY_NAMES[date::serial_weekday(days)];
+
+    let mut result = String::new();
+    for token in tokens {
+        match token {
+            DateToken::Literal(literal) => result.push_str(literal),
+            DateToken::Year { digits: 1 | 2 } => {
+                result.push_str(&format!("{:02}", year.rem_euclid(100)))
+            }
+            DateToken::Year { .. } => result.push_str(&format!("{year:04}")),
+            DateToken::Month { digits: 1 } => result.push_str(&format!("{month}")),
+            DateToken::Month { digits: 2 } => result.push_str(&format!("{month:02}")),
+            DateToken::Month { digits: 3 } => result.push_str(&month_name[..3]),
+            DateToken::Month { .. } => result.push_str(month_name),
+            DateToken::Day { digits: 1 } => result.push_str(&format!("{day}")),
+            DateToken::Day { digits: 2 } => result.push_str(&format!("{day:02}")),
+            DateToken::Day { digits: 3 } => result.push_str(&day_name[..3]),
+            DateToken::Day { .. } => result.push_str(day_name),
+            DateToken::Hour { digits } => {
+                let hour = match (twelve_hours, hour % 12) {
+                    (true, 0) => 12,
+                    (true, hour) => hour,
+                    (false, _) => hour,
+                };
+                result.push_str(&format!("{hour:0digits$}"));
+            }
+            DateToken::Minute { digits } => result.push_str(&format!("{minute:0digits$}")),
+            DateToken::Second { digits } => result.push_str(&format!("{second:0digits$}")),
+            DateToken::AmPm => result.push_str(if hour < 12 { "AM" } else { "PM" }),
+        }
+    }
+    result
+}
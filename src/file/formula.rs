@@ -0,0 +1,325 @@
+mod completion;
+mod eval;
+mod functions;
+mod parser;
+mod value;
+
+pub use completion::*;
+pub use eval::*;
+pub use functions::*;
+pub use value::*;
+
+use crate::file::cell;
+
+/// Content of a formula cell, the part after the `=`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Formula {
+    Expr(Expr),
+    /// Formula that could not be parsed, kept as written.
+    Invalid {
+        source: String,
+        error: String,
+    },
+}
+
+impl Formula {
+    /// Parse a formula source, without the leading `=`.
+    pub fn parse(source: &str) -> Formula {
+        match parser::parse(source) {
+            Ok(expr) => Formula::Expr(expr),
+            Err(error) => Formula::Invalid {
+                source: source.to_string(),
+                error,
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for Formula {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Formula::Expr(expr) => write!(f, "={expr}"),
+            Formula::Invalid { source, .. } => write!(f, "={source}"),
+        }
+    }
+}
+
+/// Reference to a single cell, where each axis can be absolute (`$A$1`) or relative (`A1`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reference {
+    pub index: cell::CellIndex,
+    pub absolute_x: bool,
+    pub absolute_y: bool,
+}
+
+impl Reference {
+    /// Parse a reference as written in formulas, as `B12` or `$B$12`, its column having 1 to 3
+    /// letters: longer words are names.
+    pub fn parse(word: &str) -> Option<Reference> {
+        parser::parse_reference(word)
+    }
+
+    /// Reference moved by an offset on its relative axes, as when a formula is copied.
+    /// None when it would go out of the sheet.
+    pub fn offset(&self, dx: i64, dy: i64) -> Option<Reference> {
+        let dx = if self.absolute_x { 0 } else { dx };
+        let dy = if self.absolute_y { 0 } else { dy };
+        Some(Reference {
+            index: self.index.offset(dx, dy)?,
+            ..*self
+        })
+    }
+
+    /// Reference of a formula copied from `source` to `destination` with rows and columns
+    /// swapped: the relative offsets to the formula cell are swapped as well.
+    pub fn transposed(
+        &self,
+        source: cell::CellIndex,
+        destination: cell::CellIndex,
+    ) -> Option<Reference> {
+        let x = match self.absolute_x {
+            true => self.index.x,
+            false => destination
+                .x
+                .checked_add_signed(self.index.y as i64 - source.y as i64)?,
+        };
+        let y = match self.absolute_y {
+            true => self.index.y,
+            false => destination
+                .y
+                .checked_add_signed(self.index.x as i64 - source.x as i64)?,
+        };
+        Some(Reference {
+            index: cell::CellIndex::new(x, y),
+            ..*self
+        })
+    }
+
+    /// Reference following its cell when a block of cells is moved by an offset.
+    /// Absolute references move too, as they target the same cell, and references to cells
+    /// overwritten by the move are lost. Ranges only follow when they are entirely moved.
+    pub fn moved(
+        &self,
+        from: cell::CellRect,
+        dx: i64,
+        dy: i64,
+        role: ReferenceRole,
+    ) -> Option<Reference> {
+        let destination = from.offset(dx, dy)?;
+        let in_block = match role {
+            ReferenceRole::Single => from.contains(self.index),
+            ReferenceRole::RangeStart { end: other } | ReferenceRole::RangeEnd { start: other } => {
+                from.contains(self.index) && from.contains(other.index)
+            }
+        };
+        if in_block {
+            Some(Reference {
+                index: self.index.offset(dx, dy)?,
+                ..*self
+            })
+        } else if role == ReferenceRole::Single
+            && destination.contains(self.index)
+            && !from.contains(self.index)
+        {
+            None
+        } else {
+            Some(*self)
+        }
+    }
+
+    /// Reference following its cell when rows or columns are inserted or deleted.
+    /// Absolute references move too, as they target the same cell.
+    pub fn shifted(&self, shift: &cell::LineShift, role: ReferenceRole) -> Option<Reference> {
+        let line = |line| match role {
+            ReferenceRole::Single => shift.line(line),
+            ReferenceRole::RangeStart { .. } => shift.bound(line, false),
+            ReferenceRole::RangeEnd { .. } => shift.bound(line, true),
+        };
+        let index = match shift.axis {
+            cell::Axis::Rows => cell::CellIndex::new(self.index.x, line(self.index.y)?),
+            cell::Axis::Columns => cell::CellIndex::new(line(self.index.x)?, self.index.y),
+        };
+        Some(Reference { index, ..*self })
+    }
+}
+
+impl std::fmt::Display for Reference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let dollar = |absolute: bool| if absolute { "$" } else { "" };
+        write!(
+            f,
+            "{}{}{}{}",
+            dollar(self.absolute_x),
+            cell::format_column(self.index.x),
+            dollar(self.absolute_y),
+            cell::format_row(self.index.y)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOperator {
+    Plus,
+    Minus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOperator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Concat,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+impl BinaryOperator {
+    fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOperator::Add => "+",
+            BinaryOperator::Sub => "-",
+            BinaryOperator::Mul => "*",
+            BinaryOperator::Div => "/",
+            BinaryOperator::Pow => "^",
+            BinaryOperator::Concat => "&",
+            BinaryOperator::Equal => "=",
+            BinaryOperator::NotEqual => "<>",
+            BinaryOperator::Less => "<",
+            BinaryOperator::LessEqual => "<=",
+            BinaryOperator::Greater => ">",
+            BinaryOperator::GreaterEqual => ">=",
+        }
+    }
+}
+
+/// Formula expression tree.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Text(String),
+    Bool(bool),
+    Reference(Reference),
+    Range(Reference, Reference),
+    Unary(UnaryOperator, Box<Expr>),
+    Percent(Box<Expr>),
+    Binary(BinaryOperator, Box<Expr>, Box<Expr>),
+    Function(String, Vec<Expr>),
+    /// Name of cells or of a constant, looked up when evaluating.
+    Name(String),
+    Paren(Box<Expr>),
+    /// Reference to deleted cells.
+    RefError,
+}
+
+/// Where a reference stands in an expression.
+/// Deleting the bound of a range shrinks it, while a single deleted reference is lost.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReferenceRole {
+    Single,
+    RangeStart { end: Reference },
+    RangeEnd { start: Reference },
+}
+
+impl Expr {
+    /// Copy of this expression where every reference goes through the `map` function.
+    /// Rejected references, and ranges whose bounds get reversed, become `#REF!`.
+    pub fn map_references(
+        &self,
+        map: &impl Fn(&Reference, ReferenceRole) -> Option<Reference>,
+    ) -> Expr {
+        let boxed = |expr: &Expr| Box::new(expr.map_references(map));
+        match self {
+            Expr::Number(_) | Expr::Text(_) | Expr::Bool(_) | Expr::Name(_) | Expr::RefError => {
+                self.clone()
+            }
+            Expr::Reference(reference) => match map(reference, ReferenceRole::Single) {
+                Some(reference) => Expr::Reference(reference),
+                None => Expr::RefError,
+            },
+            Expr::Range(from, to) => match (
+                map(from, ReferenceRole::RangeStart { end: *to }),
+                map(to, ReferenceRole::RangeEnd { start: *from }),
+            ) {
+                (Some(new_from), Some(new_to)) => {
+                    let emptied = |from: u64, to: u64, new_from: u64, new_to: u64| {
+                        from <= to && new_from > new_to
+                    };
+                    match emptied(from.index.x, to.index.x, new_from.index.x, new_to.index.x)
+                        || emptied(from.index.y, to.index.y, new_from.index.y, new_to.index.y)
+                    {
+                        true => Expr::RefError,
+                        false => Expr::Range(new_from, new_to),
+                    }
+                }
+                _ => Expr::RefError,
+            },
+            Expr::Unary(operator, expr) => Expr::Unary(*operator, boxed(expr)),
+            Expr::Percent(expr) => Expr::Percent(boxed(expr)),
+            Expr::Binary(operator, left, right) => {
+                Expr::Binary(*operator, boxed(left), boxed(right))
+            }
+            Expr::Function(name, args) => Expr::Function(
+                name.clone(),
+                args.iter().map(|arg| arg.map_references(map)).collect(),
+            ),
+            Expr::Paren(expr) => Expr::Paren(boxed(expr)),
+        }
+    }
+
+    /// Copy of this expression where a name is replaced by another, ignoring case.
+    pub fn renamed(&self, from: &str, to: &str) -> Expr {
+        let boxed = |expr: &Expr| Box::new(expr.renamed(from, to));
+        match self {
+            Expr::Name(name) if name.eq_ignore_ascii_case(from) => Expr::Name(to.to_string()),
+            Expr::Unary(operator, expr) => Expr::Unary(*operator, boxed(expr)),
+            Expr::Percent(expr) => Expr::Percent(boxed(expr)),
+            Expr::Binary(operator, left, right) => {
+                Expr::Binary(*operator, boxed(left), boxed(right))
+            }
+            Expr::Function(name, args) => Expr::Function(
+                name.clone(),
+                args.iter().map(|arg| arg.renamed(from, to)).collect(),
+            ),
+            Expr::Paren(expr) => Expr::Paren(boxed(expr)),
+            other => other.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expr::Number(number) => write!(f, "{number}"),
+            Expr::Text(text) => write!(f, "\"{}\"", text.replace('"', "\"\"")),
+            Expr::Bool(true) => write!(f, "TRUE"),
+            Expr::Bool(false) => write!(f, "FALSE"),
+            Expr::Reference(reference) => write!(f, "{reference}"),
+            Expr::Range(from, to) => write!(f, "{from}:{to}"),
+            Expr::Unary(UnaryOperator::Plus, expr) => write!(f, "+{expr}"),
+            Expr::Unary(UnaryOperator::Minus, expr) => write!(f, "-{expr}"),
+            Expr::Percent(expr) => write!(f, "{expr}%"),
+            Expr::Binary(operator, left, right) => {
+                write!(f, "{left}{}{right}", operator.symbol())
+            }
+            Expr::Function(name, args) => {
+                write!(f, "{name}(")?;
+                for (index, arg) in args.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{arg}")?;
+                }
+                write!(f, ")")
+            }
+            Expr::Name(name) => write!(f, "{name}"),
+            Expr::Paren(expr) => write!(f, "({expr})"),
+            Expr::RefError => write!(f, "#REF!"),
+        }
+    }
+}
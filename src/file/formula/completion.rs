@@ -0,0 +1,114 @@
+use super::functions::{FUNCTIONS, Function, find_function};
+use super::parser::{Token, TokenKind, tokenize};
+use crate::file::cell;
+
+/// Function name being typed before the cursor of a formula source, without its `=`, with
+/// where it starts, and the functions it may be completed to. Positions are in chars.
+pub fn completions(source: &[char], cursor: usize) -> Option<(usize, Vec<&'static Function>)> {
+    if in_text(&source[..cursor]) || source.get(cursor).is_some_and(|ch| ch.is_alphanumeric()) {
+        return None;
+    }
+    let start = source[..cursor]
+        .iter()
+        .rposition(|ch| !ch.is_ascii_alphabetic())
+        .map_or(0, |before| before + 1);
+    // part of a reference or a number, as `$A` or `1E`
+    let after_word = start > 0 && matches!(source[start - 1], '$' | '.' | '_' | '0'..='9');
+    if start == cursor || after_word {
+        return None;
+    }
+    let prefix = source[start..cursor].iter().collect::<String>();
+    let functions = FUNCTIONS
+        .iter()
+        .filter(|function| {
+            function.name.len() >= prefix.len()
+                && function.name[..prefix.len()].eq_ignore_ascii_case(&prefix)
+        })
+        .collect::<Vec<_>>();
+    (!functions.is_empty()).then_some((start, functions))
+}
+
+/// Innermost function whose arguments are being typed at the cursor.
+pub fn enclosing_function(source: &[char], cursor: usize) -> Option<&'static Function> {
+    let mut open = Vec::new();
+    let mut text = false;
+    for (index, ch) in source[..cursor].iter().enumerate() {
+        match ch {
+            '"' => text = !text,
+            '(' if !text => open.push(index),
+            ')' if !text => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+    open.iter().rev().find_map(|paren| {
+        let start = source[..*paren]
+            .iter()
+            .rposition(|ch| !ch.is_ascii_alphabetic())
+            .map_or(0, |before| before + 1);
+        find_function(&source[start..*paren].iter().collect::<String>())
+    })
+}
+
+/// Parentheses without a pair, outside texts.
+pub fn unmatched_parens(source: &[char]) -> Vec<usize> {
+    let mut open = Vec::new();
+    let mut unmatched = Vec::new();
+    let mut text = false;
+    for (index, ch) in source.iter().enumerate() {
+        match ch {
+            '"' => text = !text,
+            '(' if !text => open.push(index),
+            ')' if !text && open.pop().is_none() => unmatched.push(index),
+            _ => {}
+        }
+    }
+    unmatched.extend(open);
+    unmatched
+}
+
+/// References written in the formula, ranges as a whole, with where they are written.
+/// None are found while the formula can't be split into tokens, as in an unclosed text.
+pub fn references(source: &[char]) -> Vec<(std::ops::Range<usize>, cell::CellRect)> {
+    let Ok(tokens) = tokenize(&source.iter().collect::<String>()) else {
+        return Vec::new();
+    };
+    let mut references = Vec::new();
+    let mut index = 0;
+    while let Some(token) = tokens.get(index) {
+        if let TokenKind::Reference(from) = &token.kind {
+            let (to, span) = match (tokens.get(index + 1), tokens.get(index + 2)) {
+                (
+                    Some(Token {
+                        kind: TokenKind::Colon,
+                        ..
+                    }),
+                    Some(Token {
+                        kind: TokenKind::Reference(to),
+                        span,
+                    }),
+                ) => {
+                    index += 2;
+                    (to, token.span.start..span.end)
+                }
+                _ => (from, token.span.clone()),
+            };
+            let (from, to) = (from.index, to.index);
+            let cells = cell::CellRect::new(
+                from.x.min(to.x),
+                from.y.min(to.y),
+                from.x.abs_diff(to.x) + 1,
+                from.y.abs_diff(to.y) + 1,
+            );
+            references.push((span, cells));
+        }
+        index += 1;
+    }
+    references
+}
+
+/// Whether the end of the text is inside a quoted text.
+pub fn in_text(source: &[char]) -> bool {
+    source.iter().filter(|ch| **ch == '"').count() % 2 == 1
+}
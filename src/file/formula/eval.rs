@@ -0,0 +1,537 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use super::{BinaryOperator, Expr, Formula, FormulaError, Reference, UnaryOperator, Value};
+use crate::file::{cell, format::date, names};
+
+/// Evaluates formulas against the file content and its names.
+/// Values are cached, so an evaluator should be dropped as soon as the content changes.
+pub struct Evaluator<'a> {
+    content: &'a BTreeMap<cell::CellIndex, cell::Cell>,
+    names: &'a [names::Name],
+    now: f64,
+    cache: RefCell<HashMap<cell::CellIndex, Value>>,
+    evaluating: RefCell<HashSet<cell::CellIndex>>,
+}
+
+/// Kind of values that changes the type of arithmetic results.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Temporal {
+    Plain,
+    Date,
+    DateTime,
+    Duration,
+}
+
+impl<'a> Evaluator<'a> {
+    pub fn new(
+        content: &'a BTreeMap<cell::CellIndex, cell::Cell>,
+        names: &'a [names::Name],
+    ) -> Evaluator<'a> {
+        Evaluator {
+            content,
+            names,
+            now: crate::file::format::date::now_serial(),
+            cache: RefCell::new(HashMap::new()),
+            evaluating: RefCell::new(HashSet::new()),
+        }
+    }
+
+    /// Current date and time, as a serial. It is fixed for the evaluator lifetime.
+    pub fn now(&self) -> f64 {
+        self.now
+    }
+
+    /// Value of the given cell, evaluating it if it is a formula.
+    pub fn cell_value(&self, index: cell::CellIndex) -> Value {
+        if let Some(value) = self.cache.borrow().get(&index) {
+            return value.clone();
+        }
+        match self.content.get(&index) {
+            None => Value::Empty,
+            Some(cell::Cell::Formula(formula)) => {
+                if !self.evaluating.borrow_mut().insert(index) {
+                    return Value::Error(FormulaError::Circular);
+                }
+                self.evaluate_dependencies(formula);
+                let value = self.evaluate(formula);
+                self.evaluating.borrow_mut().remove(&index);
+                self.cache.borrow_mut().insert(index, value.clone());
+                value
+            }
+            Some(cell) => cell.value(),
+        }
+    }
+
+    /// Evaluate the formula cells a formula depends on, deepest ones first, so that evaluating
+    /// the formula only reads cached values. It walks with its own stack rather than recursing,
+    /// as a column of running totals chains thousands of references.
+    fn evaluate_dependencies(&self, formula: &Formula) {
+        let Formula::Expr(expr) = formula else {
+            return;
+        };
+        // cells to visit, and whether their own dependencies are already evaluated
+        let mut stack = Vec::new();
+        self.push_dependencies(expr, &mut stack);
+        while let Some((index, ready)) = stack.pop() {
+            let Some(cell::Cell::Formula(formula)) = self.content.get(&index) else {
+                continue;
+            };
+            if ready {
+                let value = self.evaluate(formula);
+                self.evaluating.borrow_mut().remove(&index);
+                self.cache.borrow_mut().insert(index, value);
+                continue;
+            }
+            // cells being evaluated are left to `cell_value`, finding the circular reference
+            if self.cache.borrow().contains_key(&index)
+                || !self.evaluating.borrow_mut().insert(index)
+            {
+                continue;
+            }
+            stack.push((index, true));
+            if let Formula::Expr(expr) = formula {
+                self.push_dependencies(expr, &mut stack);
+            }
+        }
+    }
+
+    /// Push the formula cells the expression refers to, directly or through names.
+    fn push_dependencies(&self, expr: &Expr, stack: &mut Vec<(cell::CellIndex, bool)>) {
+        let mut push_cells = |cells: cell::CellRect| {
+            let last_x = cells.x.saturating_add(cells.width.saturating_sub(1));
+            let last_y = cells.y.saturating_add(cells.height.saturating_sub(1));
+            let formulas = self
+                .content
+                .range(
+                    cell::CellIndex::new(cells.x, cells.y)..=cell::CellIndex::new(last_x, last_y),
+                )
+                .filter(|(index, cell)| {
+                    (cells.x..=last_x).contains(&index.x) && matches!(cell, cell::Cell::Formula(_))
+                });
+            stack.extend(formulas.map(|(index, _)| (*index, false)));
+        };
+        match expr {
+            Expr::Reference(reference) => push_cells(cell::CellRect::new(
+                reference.index.x,
+                reference.index.y,
+                1,
+                1,
+            )),
+            Expr::Range(from, to) => {
+                let (min_x, max_x) = (from.index.x.min(to.index.x), from.index.x.max(to.index.x));
+                let (min_y, max_y) = (from.index.y.min(to.index.y), from.index.y.max(to.index.y));
+                push_cells(cell::CellRect::new(
+                    min_x,
+                    min_y,
+                    max_x - min_x + 1,
+                    max_y - min_y + 1,
+                ))
+            }
+            Expr::Name(name) => {
+                if let Ok(names::Target::Cells(cells)) = self.name_target(name) {
+                    push_cells(*cells);
+                }
+            }
+            Expr::Unary(_, expr) | Expr::Percent(expr) | Expr::Paren(expr) => {
+                self.push_dependencies(expr, stack)
+            }
+            Expr::Binary(_, left, right) => {
+                self.push_dependencies(left, stack);
+                self.push_dependencies(right, stack);
+            }
+            Expr::Function(_, args) => {
+                for arg in args {
+                    self.push_dependencies(arg, stack);
+                }
+            }
+            Expr::Number(_) | Expr::Text(_) | Expr::Bool(_) | Expr::RefError => {}
+        }
+    }
+
+    pub fn evaluate(&self, formula: &Formula) -> Value {
+        match formula {
+            Formula::Expr(expr) => match self.eval(expr) {
+                Ok(value) => value,
+                Err(error) => Value::Error(error),
+            },
+            Formula::Invalid { .. } => Value::Error(FormulaError::Parse),
+        }
+    }
+
+    /// Evaluate an expression to a single value.
+    pub fn eval(&self, expr: &Expr) -> Result<Value, FormulaError> {
+        match expr {
+            Expr::Number(number) => Ok(Value::Number(*number)),
+            Expr::Text(text) => Ok(Value::Text(text.clone())),
+            Expr::Bool(value) => Ok(Value::Bool(*value)),
+            Expr::Reference(reference) => match self.cell_value(reference.index) {
+                Value::Error(error) => Err(error),
+                value => Ok(value),
+            },
+            Expr::Range(from, to) => match self.range_values(from, to)?.as_slice() {
+                [single] => Ok(single.clone()),
+                _ => Err(FormulaError::Value),
+            },
+            Expr::Name(name) => match self.name_target(name)? {
+                names::Target::Cells(cells) => match self.cells_values(*cells)?.as_slice() {
+                    [single] => Ok(single.clone()),
+                    [] if cells.count() == 1 => Ok(Value::Empty),
+                    _ => Err(FormulaError::Value),
+                },
+                names::Target::Constant(constant) => Ok(constant.value()),
+                names::Target::Deleted => Err(FormulaError::Reference),
+            },
+            Expr::Paren(expr) => self.eval(expr),
+            Expr::RefError => Err(FormulaError::Reference),
+            Expr::Unary(operator, expr) => {
+                let value = self.eval(expr)?;
+                match (operator, temporal(&value)) {
+                    (UnaryOperator::Plus, _) => Ok(value),
+                    (UnaryOperator::Minus, Temporal::Duration) => {
+                        Ok(Value::Duration(-value.as_number()?))
+                    }
+                    (UnaryOperator::Minus, _) => Ok(Value::Number(-value.as_number()?)),
+                }
+            }
+            Expr::Percent(expr) => Ok(Value::Number(self.eval(expr)?.as_number()? / 100.0)),
+            Expr::Binary(operator, left, right) => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                binary(*operator, &left, &right)
+            }
+            Expr::Function(name, args) => match super::find_function(name) {
+                Some(function) => (function.eval)(self, args),
+                None => Err(FormulaError::Name),
+            },
+        }
+    }
+
+    /// What a name of the formula stands for, `#NAME?` for unknown names.
+    fn name_target(&self, name: &str) -> Result<&names::Target, FormulaError> {
+        names::find(self.names, name)
+            .map(|name| &name.target)
+            .ok_or(FormulaError::Name)
+    }
+
+    /// Values of the non empty cells in the given rectangle, row by row.
+    pub fn values_in(&self, cells: cell::CellRect) -> Vec<(cell::CellIndex, Value)> {
+        let mut values = Vec::new();
+        for y in cells.y..cells.y.saturating_add(cells.height) {
+            let row_start = cell::CellIndex::new(cells.x, y);
+            let row_end = cell::CellIndex::new(cells.x + cells.width.saturating_sub(1), y);
+            for index in self
+                .content
+                .range(row_start..=row_end)
+                .map(|(index, _)| *index)
+            {
+                values.push((index, self.cell_value(index)));
+            }
+        }
+        values
+    }
+
+    /// All the values in the rectangle between two references, row by row.
+    /// Empty cells are skipped.
+    pub fn range_values(
+        &self,
+        from: &Reference,
+        to: &Reference,
+    ) -> Result<Vec<Value>, FormulaError> {
+        let (min_x, max_x) = (from.index.x.min(to.index.x), from.index.x.max(to.index.x));
+        let (min_y, max_y) = (from.index.y.min(to.index.y), from.index.y.max(to.index.y));
+        self.cells_values(cell::CellRect::new(
+            min_x,
+            min_y,
+            max_x - min_x + 1,
+            max_y - min_y + 1,
+        ))
+    }
+
+    /// All the values in the rectangle, row by row. Empty cells are skipped.
+    fn cells_values(&self, cells: cell::CellRect) -> Result<Vec<Value>, FormulaError> {
+        self.values_in(cells)
+            .into_iter()
+            .map(|(_, value)| match value {
+                Value::Error(error) => Err(error),
+                value => Ok(value),
+            })
+            .collect()
+    }
+
+    /// Values of function arguments, where ranges are flattened.
+    /// Values from ranges and references that do not match the filter are skipped,
+    /// while direct values are always kept.
+    pub fn flatten_args(
+        &self,
+        args: &[Expr],
+        filter: fn(&Value) -> bool,
+    ) -> Result<Vec<Value>, FormulaError> {
+        let mut values = Vec::new();
+        for arg in args {
+            // names of many cells are flattened as ranges
+            let named_cells = match arg {
+                Expr::Name(name) => match self.name_target(name) {
+                    Ok(names::Target::Cells(cells)) if cells.count() > 1 => Some(*cells),
+                    _ => None,
+                },
+                _ => None,
+            };
+            match (arg, named_cells) {
+                (_, Some(cells)) => {
+                    values.extend(self.cells_values(cells)?.into_iter().filter(filter))
+                }
+                (Expr::Range(from, to), _) => {
+                    values.extend(self.range_values(from, to)?.into_iter().filter(filter))
+                }
+                (Expr::Reference(_) | Expr::Name(_), _) => {
+                    let value = self.eval(arg)?;
+                    if filter(&value) {
+                        values.push(value);
+                    }
+                }
+                (other, _) => values.push(self.eval(other)?),
+            }
+        }
+        Ok(values)
+    }
+}
+
+fn temporal(value: &Value) -> Temporal {
+    match value {
+        Value::Date(_) => Temporal::Date,
+        Value::DateTime(_) => Temporal::DateTime,
+        Value::Duration(_) => Temporal::Duration,
+        _ => Temporal::Plain,
+    }
+}
+
+/// Date with an optional time of day: fractional dates become date times.
+/// `#NUM!` out of the handled range of dates.
+fn date_or_datetime(serial: f64) -> Result<Value, FormulaError> {
+    match (date::in_range(serial), serial.fract()) {
+        (false, _) => Err(FormulaError::Number),
+        (true, 0.0) => Ok(Value::Date(serial as i64)),
+        (true, _) => Ok(Value::DateTime(serial)),
+    }
+}
+
+/// Apply a binary operator to two values, keeping dates, date times and durations typed.
+pub fn binary(
+    operator: BinaryOperator,
+    left: &Value,
+    right: &Value,
+) -> Result<Value, FormulaError> {
+    use BinaryOperator::*;
+    use Temporal::*;
+
+    match operator {
+        Concat => return Ok(Value::Text(left.as_text()? + &right.as_text()?)),
+        Equal | NotEqual | Less | LessEqual | Greater | GreaterEqual => {
+            let ordering = compare(left, right)?;
+            return Ok(Value::Bool(match operator {
+                Equal => ordering.is_eq(),
+                NotEqual => ordering.is_ne(),
+                Less => ordering.is_lt(),
+                LessEqual => ordering.is_le(),
+                Greater => ordering.is_gt(),
+                _ => ordering.is_ge(),
+            }));
+        }
+        _ => {}
+    }
+
+    let (a, b) = (left.as_number()?, right.as_number()?);
+    let value = match (operator, temporal(left), temporal(right)) {
+        (Add, Date, Plain) | (Add, Plain, Date) => date_or_datetime(a + b)?,
+        (Add, Date | DateTime, Duration | Plain)
+        | (Add, Duration | Plain, Date | DateTime)
+        | (Add, DateTime, DateTime) => Value::DateTime(a + b),
+        (Add, Duration, Duration | Plain) | (Add, Plain, Duration) => Value::Duration(a + b),
+        (Sub, Date, Date) => Value::Number(a - b),
+        (Sub, Date | DateTime, Date | DateTime) => Value::Duration(a - b),
+        (Sub, Date, Plain) => date_or_datetime(a - b)?,
+        (Sub, Date | DateTime, Plain | Duration) => Value::DateTime(a - b),
+        (Sub, Duration, Duration | Plain) | (Sub, Plain, Duration) => Value::Duration(a - b),
+        (Add, _, _) => Value::Number(a + b),
+        (Sub, _, _) => Value::Number(a - b),
+        (Mul, Duration, Plain) | (Mul, Plain, Duration) => Value::Duration(a * b),
+        (Mul, _, _) => Value::Number(a * b),
+        (Div, _, _) if b == 0.0 => return Err(FormulaError::DivisionByZero),
+        (Div, Duration, Plain) => Value::Duration(a / b),
+        (Div, _, _) => Value::Number(a / b),
+        (_, _, _) => Value::Number(a.powf(b)),
+    };
+    match value {
+        Value::Number(number) if !number.is_finite() => Err(FormulaError::Number),
+        Value::DateTime(serial) if !date::in_range(serial) => Err(FormulaError::Number),
+        value => Ok(value),
+    }
+}
+
+/// Compare two values as Excel does: numbers are before texts, texts are compared without case.
+fn compare(left: &Value, right: &Value) -> Result<std::cmp::Ordering, FormulaError> {
+    match (left, right) {
+        (Value::Error(error), _) | (_, Value::Error(error)) => Err(*error),
+        (Value::Text(left), Value::Text(right)) => {
+            Ok(left.to_lowercase().cmp(&right.to_lowercase()))
+        }
+        (Value::Text(_), _) => Ok(std::cmp::Ordering::Greater),
+        (_, Value::Text(_)) => Ok(std::cmp::Ordering::Less),
+        (left, right) => Ok(left
+            .as_number()?
+            .partial_cmp(&right.as_number()?)
+            .unwrap_or(std::cmp::Ordering::Equal)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Content with the given cells, written as typed.
+    fn content(cells: &[(&str, &str)]) -> BTreeMap<cell::CellIndex, cell::Cell> {
+        cells
+            .iter()
+            .map(|(index, text)| {
+                (
+                    cell::CellIndex::parse(index).unwrap(),
+                    cell::Cell::parse(text, &crate::locale::Locale::default()),
+                )
+            })
+            .collect()
+    }
+
+    fn value(content: &BTreeMap<cell::CellIndex, cell::Cell>, index: &str) -> Value {
+        Evaluator::new(content, &[]).cell_value(cell::CellIndex::parse(index).unwrap())
+    }
+
+    #[test]
+    fn formulas_read_other_cells() {
+        let content = content(&[
+            ("A1", "2"),
+            ("A2", "3"),
+            ("A3", "=A1*A2+1"),
+            ("B1", "=SUM(A1:A3)"),
+            ("B2", "=IF(B1>10;\"big\";\"small\")"),
+            ("B3", "=A1/0"),
+            ("C1", "=B3+1"),
+            ("C2", "=UNKNOWN(1)"),
+            ("C3", "=1+"),
+        ]);
+        assert_eq!(value(&content, "A3"), Value::Number(7.0));
+        assert_eq!(value(&content, "B1"), Value::Number(12.0));
+        assert_eq!(value(&content, "B2"), Value::Text("big".to_string()));
+        assert_eq!(
+            value(&content, "B3"),
+            Value::Error(FormulaError::DivisionByZero)
+        );
+        assert_eq!(
+            value(&content, "C1"),
+            Value::Error(FormulaError::DivisionByZero)
+        );
+        assert_eq!(value(&content, "C2"), Value::Error(FormulaError::Name));
+        assert_eq!(value(&content, "C3"), Value::Error(FormulaError::Parse));
+        assert_eq!(value(&content, "D1"), Value::Empty);
+    }
+
+    #[test]
+    fn circular_references_are_errors() {
+        let content = content(&[("A1", "=B1+1"), ("B1", "=C1"), ("C1", "=A1"), ("D1", "=D1")]);
+        for index in ["A1", "B1", "C1", "D1"] {
+            assert_eq!(value(&content, index), Value::Error(FormulaError::Circular));
+        }
+    }
+
+    #[test]
+    fn long_chains_do_not_overflow_the_stack() {
+        let rows = 100_000;
+        let mut content = content(&[("A1", "1")]);
+        for y in 1..rows {
+            content.insert(
+                cell::CellIndex::new(0, y),
+                cell::Cell::parse(&format!("=A{y}+1"), &crate::locale::Locale::default()),
+            );
+        }
+        let last = cell::CellIndex::new(0, rows - 1);
+        let evaluator = Evaluator::new(&content, &[]);
+        assert_eq!(evaluator.cell_value(last), Value::Number(rows as f64));
+
+        content.insert(
+            cell::CellIndex::new(1, 0),
+            cell::Cell::parse(
+                &format!("=SUM(A1:A{rows})"),
+                &crate::locale::Locale::default(),
+            ),
+        );
+        let total = (rows * (rows + 1) / 2) as f64;
+        assert_eq!(value(&content, "B1"), Value::Number(total));
+    }
+
+    #[test]
+    fn names_stand_for_cells_and_constants() {
+        let content = content(&[("A1", "4"), ("A2", "6"), ("B1", "=SUM(Cards)*Rate")]);
+        let names = [
+            names::Name::parse("Cards A1:A2").unwrap(),
+            names::Name::parse("Rate 0.5").unwrap(),
+            names::Name::parse("Gone #REF!").unwrap(),
+        ];
+        let evaluator = Evaluator::new(&content, &names);
+        let eval = |source: &str| evaluator.evaluate(&Formula::parse(source));
+        assert_eq!(
+            evaluator.cell_value(cell::CellIndex::new(1, 0)),
+            Value::Number(5.0)
+        );
+        assert_eq!(eval("Cards"), Value::Error(FormulaError::Value));
+        assert_eq!(eval("Gone"), Value::Error(FormulaError::Reference));
+        assert_eq!(eval("Missing"), Value::Error(FormulaError::Name));
+    }
+
+    #[test]
+    fn operators_keep_dates_and_durations_typed() {
+        let date = Value::Date(45000);
+        let duration = Value::Duration(0.25);
+        let number = Value::Number(2.0);
+        assert_eq!(
+            binary(BinaryOperator::Add, &date, &number),
+            Ok(Value::Date(45002))
+        );
+        assert_eq!(
+            binary(BinaryOperator::Sub, &date, &Value::Date(44990)),
+            Ok(Value::Number(10.0))
+        );
+        assert_eq!(
+            binary(BinaryOperator::Add, &date, &duration),
+            Ok(Value::DateTime(45000.25))
+        );
+        assert_eq!(
+            binary(BinaryOperator::Mul, &duration, &number),
+            Ok(Value::Duration(0.5))
+        );
+        assert_eq!(
+            binary(BinaryOperator::Add, &date, &Value::Number(1e9)),
+            Err(FormulaError::Number)
+        );
+        assert_eq!(
+            binary(BinaryOperator::Div, &number, &Value::Empty),
+            Err(FormulaError::DivisionByZero)
+        );
+    }
+
+    #[test]
+    fn comparisons_put_numbers_before_texts() {
+        let text = |text: &str| Value::Text(text.to_string());
+        assert_eq!(
+            binary(BinaryOperator::Less, &Value::Number(1e9), &text("a")),
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(
+            binary(BinaryOperator::Equal, &text("Abc"), &text("aBC")),
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(
+            binary(BinaryOperator::Concat, &Value::Number(1.5), &text("x")),
+            Ok(text("1.5x"))
+        );
+    }
+}
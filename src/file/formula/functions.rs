@@ -0,0 +1,424 @@
+use super::{Evaluator, Expr, FormulaError, Value};
+use crate::file::format::date;
+
+type FunctionResult = Result<Value, FormulaError>;
+
+/// Built-in formula function.
+pub struct Function {
+    pub name: &'static str,
+    /// Arguments as shown while typing the function, optional ones in brackets.
+    pub signature: &'static str,
+    pub eval: fn(&Evaluator, &[Expr]) -> FunctionResult,
+}
+
+pub const FUNCTIONS: &[Function] = &[
+    Function {
+        name: "ABS",
+        signature: "ABS(number)",
+        eval: abs,
+    },
+    Function {
+        name: "AND",
+        signature: "AND(logical1, [logical2], ...)",
+        eval: and,
+    },
+    Function {
+        name: "AVERAGE",
+        signature: "AVERAGE(number1, [number2], ...)",
+        eval: average,
+    },
+    Function {
+        name: "CONCAT",
+        signature: "CONCAT(text1, [text2], ...)",
+        eval: concat,
+    },
+    Function {
+        name: "COUNT",
+        signature: "COUNT(value1, [value2], ...)",
+        eval: count,
+    },
+    Function {
+        name: "COUNTA",
+        signature: "COUNTA(value1, [value2], ...)",
+        eval: counta,
+    },
+    Function {
+        name: "DATE",
+        signature: "DATE(year, month, day)",
+        eval: date_fn,
+    },
+    Function {
+        name: "DAY",
+        signature: "DAY(date)",
+        eval: day,
+    },
+    Function {
+        name: "DAYS",
+        signature: "DAYS(end_date, start_date)",
+        eval: days,
+    },
+    Function {
+        name: "EDATE",
+        signature: "EDATE(start_date, months)",
+        eval: edate,
+    },
+    Function {
+        name: "HOUR",
+        signature: "HOUR(time)",
+        eval: hour,
+    },
+    Function {
+        name: "IF",
+        signature: "IF(condition, value_if_true, [value_if_false])",
+        eval: if_fn,
+    },
+    Function {
+        name: "LEN",
+        signature: "LEN(text)",
+        eval: len,
+    },
+    Function {
+        name: "LOWER",
+        signature: "LOWER(text)",
+        eval: lower,
+    },
+    Function {
+        name: "MAX",
+        signature: "MAX(number1, [number2], ...)",
+        eval: max,
+    },
+    Function {
+        name: "MIN",
+        signature: "MIN(number1, [number2], ...)",
+        eval: min,
+    },
+    Function {
+        name: "MINUTE",
+        signature: "MINUTE(time)",
+        eval: minute,
+    },
+    Function {
+        name: "MONTH",
+        signature: "MONTH(date)",
+        eval: month,
+    },
+    Function {
+        name: "NOT",
+        signature: "NOT(logical)",
+        eval: not,
+    },
+    Function {
+        name: "NOW",
+        signature: "NOW()",
+        eval: now,
+    },
+    Function {
+        name: "OR",
+        signature: "OR(logical1, [logical2], ...)",
+        eval: or,
+    },
+    Function {
+        name: "ROUND",
+        signature: "ROUND(number, digits)",
+        eval: round,
+    },
+    Function {
+        name: "SECOND",
+        signature: "SECOND(time)",
+        eval: second,
+    },
+    Function {
+        name: "SUM",
+        signature: "SUM(number1, [number2], ...)",
+        eval: sum,
+    },
+    Function {
+        name: "TIME",
+        signature: "TIME(hour, minute, second)",
+        eval: time,
+    },
+    Function {
+        name: "TODAY",
+        signature: "TODAY()",
+        eval: today,
+    },
+    Function {
+        name: "UPPER",
+        signature: "UPPER(text)",
+        eval: upper,
+    },
+    Function {
+        name: "WEEKDAY",
+        signature: "WEEKDAY(date)",
+        eval: weekday,
+    },
+    Function {
+        name: "YEAR",
+        signature: "YEAR(date)",
+        eval: year,
+    },
+];
+
+pub fn find_function(name: &str) -> Option<&'static Function> {
+    FUNCTIONS
+        .iter()
+        .find(|function| function.name.eq_ignore_ascii_case(name))
+}
+
+/// Evaluate the arguments of a function that expects exactly N of them.
+fn exact_args<const N: usize>(
+    evaluator: &Evaluator,
+    args: &[Expr],
+) -> Result<[Value; N], FormulaError> {
+    let args: &[Expr; N] = args.try_into().map_err(|_| FormulaError::Value)?;
+    let mut values = std::array::from_fn(|_| Value::Empty);
+    for (value, arg) in values.iter_mut().zip(args) {
+        *value = evaluator.eval(arg)?;
+    }
+    Ok(values)
+}
+
+fn numbers(evaluator: &Evaluator, args: &[Expr]) -> Result<Vec<f64>, FormulaError> {
+    evaluator
+        .flatten_args(args, Value::is_numeric)?
+        .iter()
+        .map(Value::as_number)
+        .collect()
+}
+
+/// Year, month and day of a date value, `#NUM!` out of the handled range of dates.
+fn civil(value: &Value) -> Result<(i64, u32, u32), FormulaError> {
+    let serial = value.as_number()?;
+    let (days, _) = date::split_serial(serial);
+    date::serial_to_civil(days)
+        .filter(|_| date::in_range(serial))
+        .ok_or(FormulaError::Number)
+}
+
+/// Date of a serial day, `#NUM!` when it overflowed or is out of the handled range of dates.
+fn date_value(serial: Option<i64>) -> FunctionResult {
+    serial
+        .filter(|serial| date::SERIAL_RANGE.contains(serial))
+        .map(Value::Date)
+        .ok_or(FormulaError::Number)
+}
+
+/// Serial day of the first day of the month, months counted from year 0.
+fn month_serial(months: i64) -> Option<i64> {
+    date::civil_to_serial(months.div_euclid(12), (months.rem_euclid(12) + 1) as u32, 1)
+}
+
+fn time_of_day(value: &Value) -> Result<(u64, u64, u64), FormulaError> {
+    let (_, time) = date::split_serial(value.as_number()?);
+    let seconds = (time * 86400.0).round() as u64;
+    Ok((seconds / 3600 % 24, seconds / 60 % 60, seconds % 60))
+}
+
+fn abs(evaluator: &Evaluator, args: &[Expr]) -> FunctionResult {
+    let [number] = exact_args(evaluator, args)?;
+    Ok(Value::Number(number.as_number()?.abs()))
+}
+
+fn and(evaluator: &Evaluator, args: &[Expr]) -> FunctionResult {
+    let values = evaluator.flatten_args(args, |value| !matches!(value, Value::Text(_)))?;
+    let mut result = true;
+    for value in values {
+        result &= value.as_bool()?;
+    }
+    Ok(Value::Bool(result))
+}
+
+fn or(evaluator: &Evaluator, args: &[Expr]) -> FunctionResult {
+    let values = evaluator.flatten_args(args, |value| !matches!(value, Value::Text(_)))?;
+    let mut result = false;
+    for value in values {
+        result |= value.as_bool()?;
+    }
+    Ok(Value::Bool(result))
+}
+
+fn not(evaluator: &Evaluator, args: &[Expr]) -> FunctionResult {
+    let [value] = exact_args(evaluator, args)?;
+    Ok(Value::Bool(!value.as_bool()?))
+}
+
+fn average(evaluator: &Evaluator, args: &[Expr]) -> FunctionResult {
+    let numbers = numbers(evaluator, args)?;
+    match numbers.len() {
+        0 => Err(FormulaError::DivisionByZero),
+        count => Ok(Value::Number(numbers.iter().sum::<f64>() / count as f64)),
+    }
+}
+
+fn sum(evaluator: &Evaluator, args: &[Expr]) -> FunctionResult {
+    Ok(Value::Number(numbers(evaluator, args)?.iter().sum()))
+}
+
+fn min(evaluator: &Evaluator, args: &[Expr]) -> FunctionResult {
+    let numbers = numbers(evaluator, args)?;
+    Ok(Value::Number(
+        numbers.into_iter().reduce(f64::min).unwrap_or_default(),
+    ))
+}
+
+fn max(evaluator: &Evaluator, args: &[Expr]) -> FunctionResult {
+    let numbers = numbers(evaluator, args)?;
+    Ok(Value::Number(
+        numbers.into_iter().reduce(f64::max).unwrap_or_default(),
+    ))
+}
+
+fn count(evaluator: &Evaluator, args: &[Expr]) -> FunctionResult {
+    let values = evaluator.flatten_args(args, Value::is_numeric)?;
+    let count = values
+        .iter()
+        .filter(|value| value.as_number().is_ok())
+        .count();
+    Ok(Value::Number(count as f64))
+}
+
+fn counta(evaluator: &Evaluator, args: &[Expr]) -> FunctionResult {
+    let values = evaluator.flatten_args(args, |value| *value != Value::Empty)?;
+    Ok(Value::Number(values.len() as f64))
+}
+
+fn concat(evaluator: &Evaluator, args: &[Expr]) -> FunctionResult {
+    let mut result = String::new();
+    for value in evaluator.flatten_args(args, |_| true)? {
+        result.push_str(&value.as_text()?);
+    }
+    Ok(Value::Text(result))
+}
+
+fn len(evaluator: &Evaluator, args: &[Expr]) -> FunctionResult {
+    let [text] = exact_args(evaluator, args)?;
+    Ok(Value::Number(text.as_text()?.chars().count() as f64))
+}
+
+fn lower(evaluator: &Evaluator, args: &[Expr]) -> FunctionResult {
+    let [text] = exact_args(evaluator, args)?;
+    Ok(Value::Text(text.as_text()?.to_lowercase()))
+}
+
+fn upper(evaluator: &Evaluator, args: &[Expr]) -> FunctionResult {
+    let [text] = exact_args(evaluator, args)?;
+    Ok(Value::Text(text.as_text()?.to_uppercase()))
+}
+
+fn if_fn(evaluator: &Evaluator, args: &[Expr]) -> FunctionResult {
+    match args {
+        [condition, if_true] => match evaluator.eval(condition)?.as_bool()? {
+            true => evaluator.eval(if_true),
+            false => Ok(Value::Bool(false)),
+        },
+        [condition, if_true, if_false] => match evaluator.eval(condition)?.as_bool()? {
+            true => evaluator.eval(if_true),
+            false => evaluator.eval(if_false),
+        },
+        _ => Err(FormulaError::Value),
+    }
+}
+
+fn round(evaluator: &Evaluator, args: &[Expr]) -> FunctionResult {
+    let [number, digits] = exact_args(evaluator, args)?;
+    let factor = 10f64.powi(digits.as_number()? as i32);
+    Ok(Value::Number(
+        (number.as_number()? * factor).round() / factor,
+    ))
+}
+
+fn today(evaluator: &Evaluator, args: &[Expr]) -> FunctionResult {
+    let [] = exact_args(evaluator, args)?;
+    date_value(Some(evaluator.now().floor() as i64))
+}
+
+fn now(evaluator: &Evaluator, args: &[Expr]) -> FunctionResult {
+    let [] = exact_args(evaluator, args)?;
+    Ok(Value::DateTime(evaluator.now()))
+}
+
+fn date_fn(evaluator: &Evaluator, args: &[Expr]) -> FunctionResult {
+    let [year, month, day] = exact_args(evaluator, args)?;
+    let year = year.as_number()?.trunc() as i64;
+    let month = month.as_number()?.trunc() as i64;
+    let day = day.as_number()?.trunc() as i64;
+    // months and days overflow on the next years and months, as in Excel
+    let months = year
+        .checked_mul(12)
+        .and_then(|months| months.checked_add(month)?.checked_sub(1));
+    let first_of_month = months.and_then(month_serial);
+    date_value(first_of_month.and_then(|serial| serial.checked_add(day)?.checked_sub(1)))
+}
+
+fn time(evaluator: &Evaluator, args: &[Expr]) -> FunctionResult {
+    let [hour, minute, second] = exact_args(evaluator, args)?;
+    let seconds = hour.as_number()? * 3600.0 + minute.as_number()? * 60.0 + second.as_number()?;
+    Ok(Value::Duration(seconds / 86400.0))
+}
+
+fn year(evaluator: &Evaluator, args: &[Expr]) -> FunctionResult {
+    let [value] = exact_args(evaluator, args)?;
+    let (year, _, _) = civil(&value)?;
+    Ok(Value::Number(year as f64))
+}
+
+fn month(evaluator: &Evaluator, args: &[Expr]) -> FunctionResult {
+    let [value] = exact_args(evaluator, args)?;
+    let (_, month, _) = civil(&value)?;
+    Ok(Value::Number(f64::from(month)))
+}
+
+fn day(evaluator: &Evaluator, args: &[Expr]) -> FunctionResult {
+    let [value] = exact_args(evaluator, args)?;
+    let (_, _, day) = civil(&value)?;
+    Ok(Value::Number(f64::from(day)))
+}
+
+fn weekday(evaluator: &Evaluator, args: &[Expr]) -> FunctionResult {
+    let [value] = exact_args(evaluator, args)?;
+    let serial = value.as_number()?;
+    if !date::in_range(serial) {
+        return Err(FormulaError::Number);
+    }
+    let (days, _) = date::split_serial(serial);
+    // sunday is 1, as Excel default
+    let weekday = (date::serial_weekday(days) + 1) % 7 + 1;
+    Ok(Value::Number(weekday as f64))
+}
+
+fn hour(evaluator: &Evaluator, args: &[Expr]) -> FunctionResult {
+    let [value] = exact_args(evaluator, args)?;
+    let (hour, _, _) = time_of_day(&value)?;
+    Ok(Value::Number(hour as f64))
+}
+
+fn minute(evaluator: &Evaluator, args: &[Expr]) -> FunctionResult {
+    let [value] = exact_args(evaluator, args)?;
+    let (_, minute, _) = time_of_day(&value)?;
+    Ok(Value::Number(minute as f64))
+}
+
+fn second(evaluator: &Evaluator, args: &[Expr]) -> FunctionResult {
+    let [value] = exact_args(evaluator, args)?;
+    let (_, _, second) = time_of_day(&value)?;
+    Ok(Value::Number(second as f64))
+}
+
+fn days(evaluator: &Evaluator, args: &[Expr]) -> FunctionResult {
+    let [end, start] = exact_args(evaluator, args)?;
+    let (end, _) = date::split_serial(end.as_number()?);
+    let (start, _) = date::split_serial(start.as_number()?);
+    let days = end.checked_sub(start).ok_or(FormulaError::Number)?;
+    Ok(Value::Number(days as f64))
+}
+
+fn edate(evaluator: &Evaluator, args: &[Expr]) -> FunctionResult {
+    let [start, months] = exact_args(evaluator, args)?;
+    let (year, month, day) = civil(&start)?;
+    let months = (year * 12 + i64::from(month) - 1).checked_add(months.as_number()?.trunc() as i64);
+    let serial = months.and_then(|months| {
+        let (year, month) = (months.div_euclid(12), (months.rem_euclid(12) + 1) as u32);
+        date::civil_to_serial(year, month, day.min(date::days_in_month(year, month)))
+    });
+    date_value(serial)
+}
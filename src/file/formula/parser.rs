@@ -0,0 +1,450 @@
+use super::{BinaryOperator, Expr, Reference, UnaryOperator};
+use crate::file::cell;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Number(f64),
+    Text(String),
+    /// Function names, names of cells and constants such as `TRUE`.
+    Identifier(String),
+    Reference(Reference),
+    Operator(&'static str),
+    LeftParen,
+    RightParen,
+    /// Argument separator, either `,` or `;`.
+    Separator,
+    Colon,
+    /// `#REF!`, left by deleted cells.
+    RefError,
+}
+
+/// Lexed piece of formula, with its position in the source, in chars.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: std::ops::Range<usize>,
+}
+
+/// Split a formula source (without the leading `=`) into tokens.
+pub fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let chars = source.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+
+    while let Some(&ch) = chars.get(index) {
+        let start = index;
+        let kind = match ch {
+            ' ' | '\t' | '\n' => {
+                index += 1;
+                continue;
+            }
+            '(' => TokenKind::LeftParen,
+            ')' => TokenKind::RightParen,
+            ',' | ';' => TokenKind::Separator,
+            ':' => TokenKind::Colon,
+            '+' => TokenKind::Operator("+"),
+            '-' => TokenKind::Operator("-"),
+            '*' => TokenKind::Operator("*"),
+            '/' => TokenKind::Operator("/"),
+            '^' => TokenKind::Operator("^"),
+            '&' => TokenKind::Operator("&"),
+            '%' => TokenKind::Operator("%"),
+            '=' => TokenKind::Operator("="),
+            '<' => match chars.get(index + 1) {
+                Some('=') => {
+                    index += 1;
+                    TokenKind::Operator("<=")
+                }
+                Some('>') => {
+                    index += 1;
+                    TokenKind::Operator("<>")
+                }
+                _ => TokenKind::Operator("<"),
+            },
+            '>' => match chars.get(index + 1) {
+                Some('=') => {
+                    index += 1;
+                    TokenKind::Operator(">=")
+                }
+                _ => TokenKind::Operator(">"),
+            },
+            '#' if chars[index..]
+                .iter()
+                .take(5)
+                .collect::<String>()
+                .eq_ignore_ascii_case("#REF!") =>
+            {
+                index += 4;
+                TokenKind::RefError
+            }
+            '"' => {
+                let mut text = String::new();
+                loop {
+                    index += 1;
+                    match (chars.get(index), chars.get(index + 1)) {
+                        (Some('"'), Some('"')) => {
+                            text.push('"');
+                            index += 1;
+                        }
+                        (Some('"'), _) => break,
+                        (Some(ch), _) => text.push(*ch),
+                        (None, _) => return Err("Unterminated text in formula".to_string()),
+                    }
+                }
+                TokenKind::Text(text)
+            }
+            ch if ch.is_ascii_digit() || ch == '.' => {
+                let mut end = index;
+                while chars
+                    .get(end)
+                    .is_some_and(|ch| ch.is_ascii_digit() || *ch == '.')
+                {
+                    end += 1;
+                }
+                // exponent part, as in 1.5E+3
+                if matches!(chars.get(end), Some('e' | 'E'))
+                    && (chars.get(end + 1).is_some_and(|ch| ch.is_ascii_digit())
+                        || (matches!(chars.get(end + 1), Some('+' | '-'))
+                            && chars.get(end + 2).is_some_and(|ch| ch.is_ascii_digit())))
+                {
+                    end += 2;
+                    while chars.get(end).is_some_and(|ch| ch.is_ascii_digit()) {
+                        end += 1;
+                    }
+                }
+                let number = chars[index..end].iter().collect::<String>();
+                index = end - 1;
+                match number.parse::<f64>() {
+                    Ok(number) => TokenKind::Number(number),
+                    Err(_) => return Err(format!("Invalid number \"{number}\" in formula")),
+                }
+            }
+            ch if ch.is_alphabetic() || ch == '$' || ch == '_' => {
+                let mut end = index;
+                while chars
+                    .get(end)
+                    .is_some_and(|ch| ch.is_alphanumeric() || matches!(ch, '$' | '_' | '.'))
+                {
+                    end += 1;
+                }
+                let word = chars[index..end].iter().collect::<String>();
+                index = end - 1;
+                match parse_reference(&word) {
+                    Some(reference) => TokenKind::Reference(reference),
+                    None if word.contains('$') => {
+                        return Err(format!("Invalid reference \"{word}\" in formula"));
+                    }
+                    None => TokenKind::Identifier(word),
+                }
+            }
+            other => return Err(format!("Unexpected \"{other}\" in formula")),
+        };
+        index += 1;
+        tokens.push(Token {
+            kind,
+            span: start..index,
+        });
+    }
+
+    Ok(tokens)
+}
+
+/// Parse a cell reference such as `B12`, `$B12` or `$B$12`.
+pub fn parse_reference(word: &str) -> Option<Reference> {
+    let (absolute_x, word) = match word.strip_prefix('$') {
+        Some(word) => (true, word),
+        None => (false, word),
+    };
+    let letters = word
+        .chars()
+        .take_while(|ch| ch.is_ascii_alphabetic())
+        .count();
+    let (column, row) = word.split_at(letters);
+    let (absolute_y, row) = match row.strip_prefix('$') {
+        Some(row) => (true, row),
+        None => (false, row),
+    };
+    if !(1..=3).contains(&column.len()) {
+        return None;
+    }
+    let x = cell::parse_column(column)?;
+    let y = cell::parse_row(row)?;
+    Some(Reference {
+        index: cell::CellIndex::new(x, y),
+        absolute_x,
+        absolute_y,
+    })
+}
+
+/// Parse a formula source (without the leading `=`) into an expression tree.
+pub fn parse(source: &str) -> Result<Expr, String> {
+    let tokens = tokenize(source)?;
+    let mut parser = Parser { tokens, index: 0 };
+    let expr = parser.comparison()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(_) => Err("Unexpected trailing content in formula".to_string()),
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    index: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.index).map(|token| &token.kind)
+    }
+
+    fn next(&mut self) -> Option<TokenKind> {
+        let token = self.tokens.get(self.index).map(|token| token.kind.clone());
+        self.index += 1;
+        token
+    }
+
+    fn peek_operator(&self, operators: &[&'static str]) -> Option<&'static str> {
+        match self.peek() {
+            Some(TokenKind::Operator(operator)) if operators.contains(operator) => Some(operator),
+            _ => None,
+        }
+    }
+
+    fn binary_level(
+        &mut self,
+        operators: &[&'static str],
+        next_level: fn(&mut Parser) -> Result<Expr, String>,
+    ) -> Result<Expr, String> {
+        let mut left = next_level(self)?;
+        while let Some(operator) = self.peek_operator(operators) {
+            self.index += 1;
+            let right = next_level(self)?;
+            let operator = match operator {
+                "=" => BinaryOperator::Equal,
+                "<>" => BinaryOperator::NotEqual,
+                "<" => BinaryOperator::Less,
+                "<=" => BinaryOperator::LessEqual,
+                ">" => BinaryOperator::Greater,
+                ">=" => BinaryOperator::GreaterEqual,
+                "&" => BinaryOperator::Concat,
+                "+" => BinaryOperator::Add,
+                "-" => BinaryOperator::Sub,
+                "*" => BinaryOperator::Mul,
+                "/" => BinaryOperator::Div,
+                _ => BinaryOperator::Pow,
+            };
+            left = Expr::Binary(operator, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        self.binary_level(&["=", "<>", "<", "<=", ">", ">="], Parser::concat)
+    }
+
+    fn concat(&mut self) -> Result<Expr, String> {
+        self.binary_level(&["&"], Parser::additive)
+    }
+
+    fn additive(&mut self) -> Result<Expr, String> {
+        self.binary_level(&["+", "-"], Parser::multiplicative)
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr, String> {
+        self.binary_level(&["*", "/"], Parser::power)
+    }
+
+    fn power(&mut self) -> Result<Expr, String> {
+        self.binary_level(&["^"], Parser::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.peek_operator(&["+", "-"]) {
+            Some(operator) => {
+                self.index += 1;
+                let operator = match operator {
+                    "-" => UnaryOperator::Minus,
+                    _ => UnaryOperator::Plus,
+                };
+                Ok(Expr::Unary(operator, Box::new(self.unary()?)))
+            }
+            None => self.postfix(),
+        }
+    }
+
+    fn postfix(&mut self) -> Result<Expr, String> {
+        let mut expr = self.primary()?;
+        while self.peek_operator(&["%"]).is_some() {
+            self.index += 1;
+            expr = Expr::Percent(Box::new(expr));
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(TokenKind::Number(number)) => Ok(Expr::Number(number)),
+            Some(TokenKind::Text(text)) => Ok(Expr::Text(text)),
+            Some(TokenKind::RefError) => Ok(Expr::RefError),
+            Some(TokenKind::Reference(from)) => match self.peek() {
+                Some(TokenKind::Colon) => {
+                    self.index += 1;
+                    match self.next() {
+                        Some(TokenKind::Reference(to)) => Ok(Expr::Range(from, to)),
+                        _ => Err("Expected a cell after \":\" in formula".to_string()),
+                    }
+                }
+                _ => Ok(Expr::Reference(from)),
+            },
+            Some(TokenKind::Identifier(name)) => match self.peek() {
+                Some(TokenKind::LeftParen) => {
+                    self.index += 1;
+                    let args = self.arguments()?;
+                    Ok(Expr::Function(name.to_ascii_uppercase(), args))
+                }
+                _ if name.eq_ignore_ascii_case("TRUE") => Ok(Expr::Bool(true)),
+                _ if name.eq_ignore_ascii_case("FALSE") => Ok(Expr::Bool(false)),
+                _ => Ok(Expr::Name(name)),
+            },
+            Some(TokenKind::LeftParen) => {
+                let expr = self.comparison()?;
+                match self.next() {
+                    Some(TokenKind::RightParen) => Ok(Expr::Paren(Box::new(expr))),
+                    _ => Err("Missing \")\" in formula".to_string()),
+                }
+            }
+            Some(_) => Err("Unexpected symbol in formula".to_string()),
+            None => Err("Unexpected end of formula".to_string()),
+        }
+    }
+
+    /// Function arguments, after the opening parenthesis.
+    fn arguments(&mut self) -> Result<Vec<Expr>, String> {
+        let mut args = Vec::new();
+        if let Some(TokenKind::RightParen) = self.peek() {
+            self.index += 1;
+            return Ok(args);
+        }
+        loop {
+            args.push(self.comparison()?);
+            match self.next() {
+                Some(TokenKind::Separator) => {}
+                Some(TokenKind::RightParen) => return Ok(args),
+                _ => return Err("Missing \")\" in formula".to_string()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reference(x: u64, y: u64) -> Reference {
+        Reference {
+            index: cell::CellIndex::new(x, y),
+            absolute_x: false,
+            absolute_y: false,
+        }
+    }
+
+    #[test]
+    fn references_need_one_to_three_letters() {
+        assert_eq!(parse_reference("B12"), Some(reference(1, 11)));
+        assert_eq!(
+            parse_reference("$B$12"),
+            Some(Reference {
+                absolute_x: true,
+                absolute_y: true,
+                ..reference(1, 11)
+            })
+        );
+        assert!(parse_reference("ABC1").is_some());
+        assert_eq!(parse_reference("Sales2024"), None);
+        assert_eq!(parse_reference("B"), None);
+        assert_eq!(parse_reference("12"), None);
+    }
+
+    #[test]
+    fn operators_follow_precedence() {
+        let expr = parse("1+2*3^2").unwrap();
+        assert_eq!(
+            expr,
+            Expr::Binary(
+                BinaryOperator::Add,
+                Box::new(Expr::Number(1.0)),
+                Box::new(Expr::Binary(
+                    BinaryOperator::Mul,
+                    Box::new(Expr::Number(2.0)),
+                    Box::new(Expr::Binary(
+                        BinaryOperator::Pow,
+                        Box::new(Expr::Number(3.0)),
+                        Box::new(Expr::Number(2.0)),
+                    )),
+                )),
+            )
+        );
+        assert_eq!(
+            parse("-A1%").unwrap(),
+            Expr::Unary(
+                UnaryOperator::Minus,
+                Box::new(Expr::Percent(Box::new(Expr::Reference(reference(0, 0))))),
+            )
+        );
+        assert!(matches!(
+            parse("A1&\"x\"=B1").unwrap(),
+            Expr::Binary(BinaryOperator::Equal, _, _)
+        ));
+    }
+
+    #[test]
+    fn functions_ranges_and_names() {
+        assert_eq!(
+            parse("sum(A1:B2; DeckSize)").unwrap(),
+            Expr::Function(
+                "SUM".to_string(),
+                vec![
+                    Expr::Range(reference(0, 0), reference(1, 1)),
+                    Expr::Name("DeckSize".to_string()),
+                ],
+            )
+        );
+        assert_eq!(
+            parse("NOW()").unwrap(),
+            Expr::Function("NOW".into(), vec![])
+        );
+        assert_eq!(parse("true").unwrap(), Expr::Bool(true));
+        assert_eq!(parse("#REF!+1").unwrap().to_string(), "#REF!+1");
+    }
+
+    #[test]
+    fn texts_and_numbers() {
+        assert_eq!(
+            parse("\"say \"\"hi\"\"\"").unwrap(),
+            Expr::Text("say \"hi\"".to_string())
+        );
+        assert_eq!(parse("1.5E+3").unwrap(), Expr::Number(1500.0));
+        assert_eq!(parse(".5").unwrap(), Expr::Number(0.5));
+    }
+
+    #[test]
+    fn formulas_print_back_as_parsed() {
+        for source in [
+            "SUM($A$1:B2,3)",
+            "(1+2)*-C3%",
+            "A1<>\"a\"\"b\"",
+            "IF(A1>=2,TRUE,\"\")",
+        ] {
+            assert_eq!(parse(source).unwrap().to_string(), source);
+        }
+    }
+
+    #[test]
+    fn invalid_formulas_are_errors() {
+        for source in [
+            "", "1+", "(1", "SUM(1,", "\"open", "1.2.3", "$Sales", "A1:", "1 2", "@",
+        ] {
+            assert!(parse(source).is_err(), "{source}");
+        }
+    }
+}
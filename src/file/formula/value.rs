@@ -0,0 +1,117 @@
+use crate::file::format;
+
+/// Errors a formula can evaluate to, displayed Excel style.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormulaError {
+    DivisionByZero,
+    Value,
+    Name,
+    Number,
+    NotAvailable,
+    Circular,
+    Parse,
+    /// Reference to a deleted cell.
+    Reference,
+}
+
+impl std::fmt::Display for FormulaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormulaError::DivisionByZero => write!(f, "#DIV/0!"),
+            FormulaError::Value => write!(f, "#VALUE!"),
+            FormulaError::Name => write!(f, "#NAME?"),
+            FormulaError::Number => write!(f, "#NUM!"),
+            FormulaError::NotAvailable => write!(f, "#N/A"),
+            FormulaError::Circular => write!(f, "#CIRC!"),
+            FormulaError::Parse => write!(f, "#ERROR!"),
+            FormulaError::Reference => write!(f, "#REF!"),
+        }
+    }
+}
+
+/// Value of a cell once evaluated.
+/// Dates, date times and durations are counted in days, dates and date times from 1899-12-30.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Empty,
+    Number(f64),
+    Text(String),
+    Bool(bool),
+    Date(i64),
+    DateTime(f64),
+    Duration(f64),
+    Error(FormulaError),
+}
+
+impl Value {
+    /// Numeric value, as used by arithmetic. Text is read as a number when possible.
+    pub fn as_number(&self) -> Result<f64, FormulaError> {
+        match self {
+            Value::Empty => Ok(0.0),
+            Value::Number(number) => Ok(*number),
+            Value::Bool(value) => Ok(f64::from(u8::from(*value))),
+            Value::Date(serial) => Ok(*serial as f64),
+            Value::DateTime(serial) => Ok(*serial),
+            Value::Duration(days) => Ok(*days),
+            Value::Text(text) => text.trim().parse().map_err(|_| FormulaError::Value),
+            Value::Error(error) => Err(*error),
+        }
+    }
+
+    pub fn as_bool(&self) -> Result<bool, FormulaError> {
+        match self {
+            Value::Bool(value) => Ok(*value),
+            Value::Text(text) if text.eq_ignore_ascii_case("TRUE") => Ok(true),
+            Value::Text(text) if text.eq_ignore_ascii_case("FALSE") => Ok(false),
+            Value::Text(_) => Err(FormulaError::Value),
+            other => other.as_number().map(|number| number != 0.0),
+        }
+    }
+
+    /// Text value, as used by text functions and concatenation.
+    pub fn as_text(&self) -> Result<String, FormulaError> {
+        match self {
+            Value::Empty => Ok(String::new()),
+            Value::Text(text) => Ok(text.clone()),
+            Value::Error(error) => Err(*error),
+            other => Ok(other.display(None, &crate::locale::Locale::default())),
+        }
+    }
+
+    /// Whether this value counts as a number in aggregations over ranges.
+    pub fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            Value::Number(_) | Value::Date(_) | Value::DateTime(_) | Value::Duration(_)
+        )
+    }
+
+    /// Text to display for this value, with the optional number format.
+    pub fn display(
+        &self,
+        number_format: Option<&format::NumberFormat>,
+        locale: &crate::locale::Locale,
+    ) -> String {
+        match (self, number_format) {
+            (Value::Empty, _) => String::new(),
+            (Value::Text(text), _) => text.clone(),
+            (Value::Bool(value), _) => locale.format_bool(*value).to_string(),
+            (Value::Error(error), _) => error.to_string(),
+            (Value::Number(number), None) => format::NumberFormat::general(*number),
+            (Value::Date(serial), None) => locale.format_date(*serial),
+            (Value::DateTime(serial), None) => locale.format_datetime(*serial),
+            (Value::Duration(days), None) => crate::locale::format_time(*days),
+            (other, Some(number_format)) => {
+                number_format.format(other.as_number().unwrap_or_default())
+            }
+        }
+    }
+
+    pub fn alignment(&self) -> ratatui::layout::Alignment {
+        match self {
+            Value::Empty | Value::Text(_) => ratatui::layout::Alignment::Left,
+            Value::Bool(_) | Value::Error(_) => ratatui::layout::Alignment::Center,
+            _ => ratatui::layout::Alignment::Right,
+        }
+    }
+}
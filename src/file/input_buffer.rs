@@ -1,124 +1,403 @@
 pub struct InputBuffer {
     buffer: Vec<char>,
     cursor: usize,
+    /// Where the selected text started, the cursor being at its other end.
+    anchor: Option<usize>,
+    /// Whether the text may hold line breaks, Up and Down moving between its lines.
+    multiline: bool,
 }
 
 impl InputBuffer {
     pub fn new(opening_chars: &str) -> InputBuffer {
-        let mut buffer = Vec::new();
-        for ch in opening_chars.chars() {
-            buffer.push(ch);
-        }
+        let buffer = opening_chars.chars().collect::<Vec<_>>();
         let cursor = buffer.len();
-        InputBuffer { buffer, cursor }
+        InputBuffer {
+            buffer,
+            cursor,
+            anchor: None,
+            multiline: false,
+        }
+    }
+
+    /// Buffer of a text area, where line breaks can be typed.
+    pub fn multiline(opening_chars: &str) -> InputBuffer {
+        InputBuffer {
+            multiline: true,
+            ..InputBuffer::new(opening_chars)
+        }
     }
 
+    /// Type a character, replacing the selected text.
     pub fn push(&mut self, ch: char) {
+        self.delete_selection();
         self.buffer.insert(self.cursor, ch);
         self.cursor += 1;
     }
 
-    pub fn del_front(&mut self) {
-        if self.cursor > 0 {
-            self.buffer.remove(self.cursor - 1);
-            self.cursor = self.cursor.saturating_sub(1);
+    /// Delete the selected text, or the character or word before the cursor.
+    pub fn del_front(&mut self, whole_word: bool) {
+        if !self.delete_selection() {
+            let start = match whole_word {
+                true => self.previous_word(self.cursor),
+                false => self.previous_boundary(self.cursor),
+            };
+            self.buffer.drain(start..self.cursor);
+            self.cursor = start;
         }
     }
 
-    pub fn del_back(&mut self) {
-        if self.cursor < self.buffer.len() {
-            self.buffer.remove(self.cursor);
+    /// Delete the selected text, or the character or word after the cursor.
+    pub fn del_back(&mut self, whole_word: bool) {
+        if !self.delete_selection() {
+            let end = match whole_word {
+                true => self.next_word(self.cursor),
+                false => self.next_boundary(self.cursor),
+            };
+            self.buffer.drain(self.cursor..end);
         }
     }
 
-    pub fn increment_cursor(&mut self, whole_word: bool) {
-        match whole_word {
-            true => {
-                let start_at_alphanumeric = self
-                    .buffer
-                    .get(self.cursor)
-                    .map(|ch| ch.is_alphanumeric())
-                    .unwrap_or(false);
-                while self.cursor < self.buffer.len()
-                    && match self.buffer.get(self.cursor) {
-                        Some(ch) => ch.is_alphanumeric() == start_at_alphanumeric,
-                        None => false,
-                    }
-                {
-                    self.cursor = self.cursor.saturating_add(1).min(self.buffer.len());
-                }
+    /// Replace the characters of the range by the text, the cursor going after it.
+    pub fn replace(&mut self, range: std::ops::Range<usize>, text: &str) {
+        let end = range.start + text.chars().count();
+        self.buffer.splice(range, text.chars());
+        self.anchor = None;
+        self.cursor = end;
+    }
+
+    pub fn chars(&self) -> &[char] {
+        &self.buffer
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Selected characters, from start to end.
+    fn selection(&self) -> Option<(usize, usize)> {
+        let anchor = self.anchor.filter(|anchor| *anchor != self.cursor)?;
+        Some((anchor.min(self.cursor), anchor.max(self.cursor)))
+    }
+
+    /// Returns whether there was selected text.
+    fn delete_selection(&mut self) -> bool {
+        let selection = self.selection();
+        self.anchor = None;
+        match selection {
+            Some((start, end)) => {
+                self.buffer.drain(start..end);
+                self.cursor = start;
+                true
             }
-            false => self.cursor = self.cursor.saturating_add(1).min(self.buffer.len()),
+            None => false,
         }
     }
 
-    pub fn decrement_cursor(&mut self, whole_word: bool) {
-        match whole_word {
+    /// Move the cursor, selecting the text it goes over when extending the selection.
+    fn move_cursor(&mut self, to: usize, extend: bool) {
+        match extend {
             true => {
-                let start_at_alphanumeric = self
-                    .buffer
-                    .get(self.cursor.saturating_sub(1))
-                    .map(|ch| ch.is_alphanumeric())
-                    .unwrap_or(false);
-                while self.cursor > 0
-                    && match self.buffer.get(self.cursor.saturating_sub(1)) {
-                        Some(ch) => ch.is_alphanumeric() == start_at_alphanumeric,
-                        None => false,
+                self.anchor.get_or_insert(self.cursor);
+            }
+            false => self.anchor = None,
+        }
+        self.cursor = to.min(self.buffer.len());
+    }
+
+    /// Start of the characters displayed as one: combining marks and other zero width
+    /// characters, emoji modifiers, characters joined by a zero width joiner and pairs of
+    /// regional indicators (flags) stick to the character before them.
+    fn boundaries(&self) -> Vec<usize> {
+        let regional_indicator = |ch: char| ('\u{1F1E6}'..='\u{1F1FF}').contains(&ch);
+        let mut boundaries = Vec::new();
+        let mut indicators = 0;
+        for (index, ch) in self.buffer.iter().enumerate() {
+            let joined = index > 0 && {
+                let previous = self.buffer[index - 1];
+                previous == '\u{200D}'
+                    || (ch != &'\n' && previous != '\n' && char_width(*ch) == 0)
+                    || ('\u{1F3FB}'..='\u{1F3FF}').contains(ch)
+                    || (regional_indicator(*ch) && indicators % 2 == 1)
+            };
+            indicators = match regional_indicator(*ch) {
+                true => indicators + 1,
+                false => 0,
+            };
+            if !joined {
+                boundaries.push(index);
+            }
+        }
+        boundaries.push(self.buffer.len());
+        boundaries
+    }
+
+    /// Closest boundary at or after the index.
+    fn boundary_after(&self, index: usize) -> usize {
+        self.boundaries()
+            .into_iter()
+            .find(|boundary| *boundary >= index)
+            .unwrap_or(self.buffer.len())
+    }
+
+    /// Closest boundary at or before the index.
+    fn boundary_before(&self, index: usize) -> usize {
+        self.boundaries()
+            .into_iter()
+            .rfind(|boundary| *boundary <= index)
+            .unwrap_or(0)
+    }
+
+    fn next_boundary(&self, index: usize) -> usize {
+        self.boundary_after(index.saturating_add(1))
+    }
+
+    fn previous_boundary(&self, index: usize) -> usize {
+        self.boundary_before(index.saturating_sub(1))
+    }
+
+    /// End of the word, or of the run of other characters, starting at the index.
+    fn next_word(&self, index: usize) -> usize {
+        let start_at_alphanumeric = self
+            .buffer
+            .get(index)
+            .is_some_and(|ch| ch.is_alphanumeric());
+        let mut end = index;
+        while self
+            .buffer
+            .get(end)
+            .is_some_and(|ch| ch.is_alphanumeric() == start_at_alphanumeric)
+        {
+            end += 1;
+        }
+        self.boundary_after(end)
+    }
+
+    /// Start of the word, or of the run of other characters, ending at the index.
+    fn previous_word(&self, index: usize) -> usize {
+        let start_at_alphanumeric = index > 0 && self.buffer[index - 1].is_alphanumeric();
+        let mut start = index;
+        while start > 0 && self.buffer[start - 1].is_alphanumeric() == start_at_alphanumeric {
+            start -= 1;
+        }
+        self.boundary_before(start)
+    }
+
+    /// Start and end of the line holding the index.
+    fn line_bounds(&self, index: usize) -> (usize, usize) {
+        let start = self.buffer[..index]
+            .iter()
+            .rposition(|ch| *ch == '\n')
+            .map_or(0, |newline| newline + 1);
+        let end = self.buffer[index..]
+            .iter()
+            .position(|ch| *ch == '\n')
+            .map_or(self.buffer.len(), |newline| index + newline);
+        (start, end)
+    }
+
+    /// Displayed width of the characters.
+    fn width(&self, start: usize, end: usize) -> usize {
+        self.buffer[start..end]
+            .iter()
+            .map(|ch| char_width(*ch))
+            .sum()
+    }
+
+    /// Index on the line above or below the cursor, as close as possible to its column.
+    fn vertical_move(&self, up: bool) -> Option<usize> {
+        let (start, end) = self.line_bounds(self.cursor);
+        let column = self.width(start, self.cursor);
+        let (start, end) = match up {
+            true => self.line_bounds(start.checked_sub(1)?),
+            false if end < self.buffer.len() => self.line_bounds(end + 1),
+            false => return None,
+        };
+        let boundaries = self.boundaries();
+        let index = boundaries
+            .iter()
+            .copied()
+            .filter(|boundary| (start..=end).contains(boundary))
+            .take_while(|boundary| self.width(start, *boundary) <= column)
+            .last()
+            .unwrap_or(start);
+        Some(index)
+    }
+
+    /// Handle the text editing events (typing, deleting, moving the cursor, pasting).
+    /// Returns whether the event was used by the buffer.
+    pub fn handle_edit_event(&mut self, event: &crossterm::event::Event) -> bool {
+        use crossterm::event::Event;
+        use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+
+        match event {
+            Event::Key(KeyEvent {
+                kind: KeyEventKind::Press | KeyEventKind::Repeat,
+                code,
+                modifiers,
+                ..
+            }) => {
+                let whole_word = modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+                let extend = modifiers.contains(KeyModifiers::SHIFT);
+                match code {
+                    // Ctrl+Backspace, as sent by some terminals, and Ctrl+W as in shells
+                    KeyCode::Char('h' | 'w') if modifiers.contains(KeyModifiers::CONTROL) => {
+                        self.del_front(true)
+                    }
+                    KeyCode::Char(ch) => self.push(*ch),
+                    KeyCode::Backspace => self.del_front(whole_word),
+                    KeyCode::Delete => self.del_back(whole_word),
+                    KeyCode::Left if self.selection().is_some() && !extend => {
+                        self.move_cursor(self.selection().map_or(0, |(start, _)| start), false)
+                    }
+                    KeyCode::Right if self.selection().is_some() && !extend => {
+                        self.move_cursor(self.selection().map_or(0, |(_, end)| end), false)
+                    }
+                    KeyCode::Left => {
+                        let to = match whole_word {
+                            true => self.previous_word(self.cursor),
+                            false => self.previous_boundary(self.cursor),
+                        };
+                        self.move_cursor(to, extend)
+                    }
+                    KeyCode::Right => {
+                        let to = match whole_word {
+                            true => self.next_word(self.cursor),
+                            false => self.next_boundary(self.cursor),
+                        };
+                        self.move_cursor(to, extend)
+                    }
+                    // Ctrl goes to the start or the end of the whole text
+                    KeyCode::Home => {
+                        let (start, _) = self.line_bounds(self.cursor);
+                        self.move_cursor(if whole_word { 0 } else { start }, extend)
+                    }
+                    KeyCode::End => {
+                        let (_, end) = self.line_bounds(self.cursor);
+                        let to = if whole_word { self.buffer.len() } else { end };
+                        self.move_cursor(to, extend)
+                    }
+                    KeyCode::Up | KeyCode::Down if self.multiline => {
+                        let to = self.vertical_move(*code == KeyCode::Up);
+                        // the first and last lines go to the start and the end of the text
+                        let to = to.unwrap_or(match code {
+                            KeyCode::Up => 0,
+                            _ => self.buffer.len(),
+                        });
+                        self.move_cursor(to, extend)
+                    }
+                    _ => return false,
+                }
+            }
+            Event::Paste(pasted_value) => {
+                // terminals may send pasted line breaks as carriage returns
+                let pasted_value = pasted_value.replace("\r\n", "\n").replace('\r', "\n");
+                for ch in pasted_value.chars() {
+                    match (ch, self.multiline) {
+                        ('\n', false) => self.push(' '),
+                        (ch, _) => self.push(ch),
                     }
-                {
-                    self.cursor = self.cursor.saturating_sub(1);
                 }
             }
-            false => self.cursor = self.cursor.saturating_sub(1),
+            _ => return false,
         }
+        true
     }
 
     pub fn string(&self) -> String {
         self.buffer.iter().cloned().collect()
     }
 
+    /// Width of the longest line, plus one for the cursor.
     pub fn required_width(&self) -> usize {
         self.buffer
             .split(|ch| *ch == '\n')
-            .map(|subslice| subslice.len())
+            .map(|line| line.iter().map(|ch| char_width(*ch)).sum::<usize>())
             .max()
             .unwrap_or(0)
             + 1 // for cursor
     }
 
+    /// Number of lines.
     pub fn required_height(&self) -> usize {
-        self.buffer.iter().filter(|ch| **ch == '\n').count()
+        self.buffer.iter().filter(|ch| **ch == '\n').count() + 1
     }
 
-    pub fn render(&self, frame: &mut ratatui::Frame, area: ratatui::layout::Rect) {
-        use ratatui::style::Stylize;
+    /// Render the lines, scrolled so that the cursor is visible when they don't fit in the area.
+    /// The highlighted ranges of characters get their style added.
+    pub fn render(
+        &self,
+        highlights: &[(std::ops::Range<usize>, ratatui::style::Style)],
+        frame: &mut ratatui::Frame,
+        area: ratatui::layout::Rect,
+    ) {
+        use crate::utils::usize_to_u16;
+        use ratatui::style::{Modifier, Style, Stylize};
+        use ratatui::text::{Line, Span};
 
-        let first_span = ratatui::text::Span::raw(
-            self.buffer[0..self.cursor]
+        let cursor_style = Style::default().add_modifier(Modifier::SLOW_BLINK | Modifier::REVERSED);
+        let selected_style = Style::default().add_modifier(Modifier::REVERSED);
+        let selection = self.selection();
+        let boundaries = self.boundaries();
+
+        let mut lines = vec![Line::default()];
+        for range in boundaries.windows(2) {
+            let (start, end) = (range[0], range[1]);
+            let highlight = highlights
                 .iter()
-                .cloned()
-                .collect::<String>(),
-        );
-        let cursor_span = ratatui::text::Span::styled(
-            self.buffer
-                .get(self.cursor)
-                .cloned()
-                .unwrap_or(' ')
-                .to_string(),
-            ratatui::style::Style::default().add_modifier(
-                ratatui::style::Modifier::SLOW_BLINK | ratatui::style::Modifier::REVERSED,
-            ),
-        );
-        let ending_span = ratatui::text::Span::raw(
-            self.buffer
-                .get(self.cursor + 1..self.buffer.len())
-                .map(|slice| slice.iter().cloned().collect::<String>())
-                .unwrap_or(String::with_capacity(0)),
+                .filter(|(range, _)| range.contains(&start))
+                .fold(Style::default(), |style, (_, highlight)| {
+                    style.patch(*highlight)
+                });
+            let style = match selection {
+                _ if start == self.cursor => highlight.patch(cursor_style),
+                Some((from, to)) if from <= start && start < to => highlight.patch(selected_style),
+                _ => highlight,
+            };
+            let text = match self.buffer[start] {
+                // the cursor on a line break shows at the end of the line
+                '\n' => {
+                    if start == self.cursor {
+                        lines
+                            .last_mut()
+                            .unwrap()
+                            .push_span(Span::styled(" ", style));
+                    }
+                    lines.push(Line::default());
+                    continue;
+                }
+                _ => self.buffer[start..end].iter().collect::<String>(),
+            };
+            lines
+                .last_mut()
+                .unwrap()
+                .push_span(Span::styled(text, style));
+        }
+        if self.cursor == self.buffer.len() {
+            lines
+                .last_mut()
+                .unwrap()
+                .push_span(Span::styled(" ", cursor_style));
+        }
+
+        let (line_start, _) = self.line_bounds(self.cursor);
+        let cursor_line = self.buffer[..self.cursor]
+            .iter()
+            .filter(|ch| **ch == '\n')
+            .count();
+        let cursor_column = self.width(line_start, self.cursor);
+        let scroll = (
+            usize_to_u16((cursor_line + 1).saturating_sub(usize::from(area.height))),
+            usize_to_u16((cursor_column + 1).saturating_sub(usize::from(area.width))),
         );
-        let line = ratatui::text::Line::from(vec![first_span, cursor_span, ending_span]);
-        let paragraph = ratatui::widgets::Paragraph::new(line)
+        let paragraph = ratatui::widgets::Paragraph::new(lines)
+            .scroll(scroll)
             .fg(ratatui::style::Color::White)
             .bg(ratatui::style::Color::Black);
         frame.render_widget(paragraph, area);
     }
 }
+
+/// Number of columns the character takes on screen.
+fn char_width(ch: char) -> usize {
+    ratatui::text::Span::raw(ch.to_string()).width()
+}
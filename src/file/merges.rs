@@ -0,0 +1,20 @@
+use crate::file::cell;
+
+/// Read the merged cells of the file from its sidecar, with a line for each merge, as `A1:C2`.
+/// Lines naming no cells, or a single one, are skipped.
+pub fn read(path: &std::path::Path) -> Vec<cell::CellRect> {
+    let text = std::fs::read_to_string(super::hidden_path(path, "merges")).unwrap_or_default();
+    text.lines()
+        .filter_map(cell::CellRect::parse)
+        .filter(|cells| cells.count() > 1)
+        .collect()
+}
+
+/// Write the merged cells of the file next to it, removing the sidecar when there are none.
+pub fn write(path: &std::path::Path, merges: &[cell::CellRect]) -> std::io::Result<()> {
+    let text = merges
+        .iter()
+        .map(|cells| format!("{cells}\n"))
+        .collect::<String>();
+    super::write_hidden(path, "merges", &text)
+}
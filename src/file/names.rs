@@ -0,0 +1,189 @@
+use crate::file::cell;
+
+/// Name standing for cells or a constant in formulas, as `DeckSize` for `C5`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Name {
+    pub name: String,
+    pub target: Target,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    Cells(cell::CellRect),
+    /// Value written as in a cell, formulas excepted.
+    Constant(cell::Cell),
+    /// All the cells of the name were deleted.
+    Deleted,
+}
+
+impl Name {
+    /// Parse a name written as `<name> <cells|constant>`. Texts looking like another value are
+    /// quoted, as `Code "0042"`.
+    pub fn parse(source: &str) -> Result<Name, String> {
+        let source = source.trim();
+        let (name, target) = source.split_once(' ').unwrap_or((source, ""));
+        check_name(name)?;
+        let target = target.trim();
+        let target = match target {
+            "" => return Err(format!("Missing cells or value for \"{name}\"")),
+            "#REF!" => Target::Deleted,
+            quoted if quoted.len() > 1 && quoted.starts_with('"') && quoted.ends_with('"') => {
+                Target::Constant(cell::Cell::Text(
+                    quoted[1..quoted.len() - 1].replace("\"\"", "\""),
+                ))
+            }
+            target => match cell::CellRect::parse(target) {
+                Some(cells) => Target::Cells(cells),
+                None => match cell::Cell::parse(target, &crate::locale::Locale::default()) {
+                    cell::Cell::Formula(_) => {
+                        return Err(format!("\"{name}\" can't stand for a formula"));
+                    }
+                    constant => Target::Constant(constant),
+                },
+            },
+        };
+        Ok(Name {
+            name: name.to_string(),
+            target,
+        })
+    }
+
+    /// Name following its cells when rows or columns are inserted or deleted.
+    pub fn shifted(&self, shift: &cell::LineShift) -> Name {
+        let target = match self.target {
+            Target::Cells(cells) => shift.rect(cells).map_or(Target::Deleted, Target::Cells),
+            _ => self.target.clone(),
+        };
+        Name {
+            name: self.name.clone(),
+            target,
+        }
+    }
+
+    /// Name following a block of cells moved by an offset, when its cells are inside the block.
+    pub fn moved(&self, from: cell::CellRect, dx: i64, dy: i64) -> Name {
+        let target = match self.target {
+            Target::Cells(cells)
+                if from.contains(cell::CellIndex::new(cells.x, cells.y))
+                    && from.contains(cell::CellIndex::new(
+                        cells.x + cells.width - 1,
+                        cells.y + cells.height - 1,
+                    )) =>
+            {
+                Target::Cells(cells.offset(dx, dy).unwrap_or(cells))
+            }
+            _ => self.target.clone(),
+        };
+        Name {
+            name: self.name.clone(),
+            target,
+        }
+    }
+}
+
+impl std::fmt::Display for Name {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.target {
+            Target::Cells(cells) => write!(f, "{} {cells}", self.name),
+            Target::Constant(cell::Cell::Text(text)) => {
+                write!(f, "{} \"{}\"", self.name, text.replace('"', "\"\""))
+            }
+            Target::Constant(constant) => write!(f, "{} {constant}", self.name),
+            Target::Deleted => write!(f, "{} #REF!", self.name),
+        }
+    }
+}
+
+/// Read the names of the file from its sidecar, with a line for each name, as written in the name
+/// manager. Invalid lines are skipped.
+pub fn read(path: &std::path::Path) -> Vec<Name> {
+    let text = std::fs::read_to_string(super::hidden_path(path, "names")).unwrap_or_default();
+    text.lines()
+        .filter_map(|line| Name::parse(line).ok())
+        .collect()
+}
+
+/// Write the names of the file next to it, removing the sidecar when there are none.
+pub fn write(path: &std::path::Path, names: &[Name]) -> std::io::Result<()> {
+    let text = names
+        .iter()
+        .map(|name| format!("{name}\n"))
+        .collect::<String>();
+    super::write_hidden(path, "names", &text)
+}
+
+/// The name of the table, ignoring case as formulas do.
+pub fn find<'a>(names: &'a [Name], name: &str) -> Option<&'a Name> {
+    names
+        .iter()
+        .find(|candidate| candidate.name.eq_ignore_ascii_case(name))
+}
+
+/// Names start with a letter or `_`, go on with letters, digits, `_` or `.`, and can't be read
+/// as a cell in formulas or as a boolean.
+fn check_name(name: &str) -> Result<(), String> {
+    let valid = name
+        .chars()
+        .next()
+        .is_some_and(|ch| ch.is_alphabetic() || ch == '_')
+        && name
+            .chars()
+            .all(|ch| ch.is_alphanumeric() || matches!(ch, '_' | '.'))
+        && crate::file::formula::Reference::parse(name).is_none()
+        && !name.eq_ignore_ascii_case("TRUE")
+        && !name.eq_ignore_ascii_case("FALSE");
+    match valid {
+        true => Ok(()),
+        false => Err(format!("Invalid name \"{name}\"")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_names_that_formulas_do_not_read_as_cells() {
+        for name in [
+            "DeckSize",
+            "Sales2024",
+            "Item1",
+            "_total",
+            "tax.rate",
+            "ABCD1",
+        ] {
+            assert_eq!(check_name(name), Ok(()), "{name}");
+        }
+        for name in [
+            "B12",
+            "abc1",
+            "XFD1048576",
+            "true",
+            "1st",
+            "with space",
+            "a-b",
+            "",
+        ] {
+            assert!(check_name(name).is_err(), "{name}");
+        }
+    }
+
+    #[test]
+    fn parses_names_of_cells_and_constants() {
+        let name = Name::parse("Sales2024 B2:C10").unwrap();
+        assert_eq!(name.target, Target::Cells(cell::CellRect::new(1, 1, 2, 9)));
+        assert_eq!(
+            Name::parse("Code \"0042\"").unwrap().target,
+            Target::Constant(cell::Cell::Text("0042".to_string()))
+        );
+        assert_eq!(
+            Name::parse("Rate 0.2").unwrap().target,
+            Target::Constant(cell::Cell::Num(0.2))
+        );
+        assert!(Name::parse("Total =A1+1").is_err());
+        assert!(Name::parse("Empty").is_err());
+        for source in ["Sales2024 B2:C10", "Code \"0042\"", "Gone #REF!"] {
+            assert_eq!(Name::parse(source).unwrap().to_string(), source);
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use crate::file::cell::{Axis, CellIndex};
+use crate::file::command::Command;
+use crate::file::selection::Selection;
+use crate::keymap::{Direction, Target};
+
+impl super::FileApp {
+    /// Select the target cell, or stretch the selection to it from the active cell. Targets are
+    /// reached from the active cell, or from the cursor of the selection when stretching it.
+    pub(super) fn go_to(
+        &mut self,
+        target: Target,
+        extend: bool,
+        info: &mut String,
+    ) -> Option<Command> {
+        let selection = self.viewport.selection.as_ref()?;
+        let from = match extend {
+            true => selection.cursor,
+            false => selection.anchor,
+        };
+        let to = self.target_cell(target, from);
+        let selection = match extend {
+            true => Selection::between(selection.anchor, to),
+            false => Selection::cell(to),
+        };
+        // pages scroll along with the selection
+        if matches!(target, Target::PageUp | Target::PageDown) {
+            self.viewport.scroll_rows(to.y as i64 - from.y as i64);
+        }
+
+        *info = format!("Selected {selection} ({} cells)", selection.count());
+        self.viewport.selection = Some(selection);
+        self.viewport.keep_selection_in_view();
+        Some(Command::RedrawRequest)
+    }
+
+    /// Select the row or column clicked in the axis headers, over the used and the visible
+    /// cells. Shift stretches the selection to it from the line of the active cell, Ctrl adds it.
+    pub(super) fn select_line(
+        &mut self,
+        axis: Axis,
+        line: u64,
+        modifiers: crossterm::event::KeyModifiers,
+        info: &mut String,
+    ) -> Option<Command> {
+        use crossterm::event::KeyModifiers;
+
+        let used = super::export::used_cells(&self.content);
+        let last_x = (self.viewport.visible_columns().last())
+            .unwrap_or(0)
+            .max(used.width.saturating_sub(1));
+        let last_y = (self.viewport.visible_rows().last())
+            .unwrap_or(0)
+            .max(used.height.saturating_sub(1));
+        let ends = |line: u64| match axis {
+            Axis::Rows => (CellIndex::new(0, line), CellIndex::new(last_x, line)),
+            Axis::Columns => (CellIndex::new(line, 0), CellIndex::new(line, last_y)),
+        };
+        let (start, end) = ends(line);
+
+        let selection = match self.viewport.selection.take() {
+            Some(selection) if modifiers.contains(KeyModifiers::SHIFT) => {
+                let (anchor, _) = ends(match axis {
+                    Axis::Rows => selection.anchor.y,
+                    Axis::Columns => selection.anchor.x,
+                });
+                Selection::between(anchor, end)
+            }
+            Some(mut selection) if modifiers.contains(KeyModifiers::CONTROL) => {
+                selection.add(start, end);
+                selection
+            }
+            _ => Selection::between(start, end),
+        };
+        *info = format!("Selected {selection} ({} cells)", selection.count());
+        self.viewport.selection = Some(selection);
+        Some(Command::RedrawRequest)
+    }
+
+    /// Cell reached by jumping to the target from the given one.
+    pub(super) fn target_cell(&self, target: Target, from: CellIndex) -> CellIndex {
+        match target {
+            Target::Edge(direction) => {
+                let forward = matches!(direction, Direction::Down | Direction::Right);
+                match direction {
+                    Direction::Left | Direction::Right => {
+                        let x = edge(
+                            from.x,
+                            forward,
+                            |x| self.content.contains_key(&CellIndex::new(x, from.y)),
+                            |x| self.next_in_row(from.y, x, forward),
+                        );
+                        CellIndex::new(x, from.y)
+                    }
+                    Direction::Up | Direction::Down => {
+                        let y = edge(
+                            from.y,
+                            forward,
+                            |y| self.content.contains_key(&CellIndex::new(from.x, y)),
+                            |y| self.next_in_column(from.x, y, forward),
+                        );
+                        CellIndex::new(from.x, y)
+                    }
+                }
+            }
+            Target::RowStart => CellIndex::new(0, from.y),
+            Target::RowEnd => {
+                CellIndex::new(self.row_cells(from.y).last().copied().unwrap_or(0), from.y)
+            }
+            Target::SheetStart => CellIndex::new(0, 0),
+            Target::SheetEnd => {
+                let used = super::export::used_cells(&self.content);
+                CellIndex::new(used.width.saturating_sub(1), used.height.saturating_sub(1))
+            }
+            Target::PageUp => {
+                CellIndex::new(from.x, from.y.saturating_sub(self.viewport.page_rows()))
+            }
+            Target::PageDown => {
+                CellIndex::new(from.x, from.y.saturating_add(self.viewport.page_rows()))
+            }
+        }
+    }
+
+    /// Column of the next written cell of the row, after or before the given one.
+    fn next_in_row(&self, y: u64, x: u64, forward: bool) -> Option<u64> {
+        let mut cells = match forward {
+            true => self
+                .content
+                .range(CellIndex::new(x.checked_add(1)?, y)..=CellIndex::new(u64::MAX, y)),
+            false => self
+                .content
+                .range(CellIndex::new(0, y)..CellIndex::new(x, y)),
+        };
+        let next = match forward {
+            true => cells.next(),
+            false => cells.next_back(),
+        };
+        next.map(|(index, _)| index.x)
+    }
+
+    /// Row of the next written cell of the column, after or before the given one. Cells being
+    /// ordered by row, each step goes straight to the next row with written cells.
+    fn next_in_column(&self, x: u64, y: u64, forward: bool) -> Option<u64> {
+        let mut row = y;
+        loop {
+            let next = match forward {
+                true => self
+                    .content
+                    .range(CellIndex::new(0, row.checked_add(1)?)..)
+                    .next(),
+                false => self.content.range(..CellIndex::new(0, row)).next_back(),
+            };
+            row = next?.0.y;
+            if self.content.contains_key(&CellIndex::new(x, row)) {
+                return Some(row);
+            }
+        }
+    }
+
+    /// Columns of the written cells of the row, in order.
+    fn row_cells(&self, y: u64) -> Vec<u64> {
+        self.content
+            .range(CellIndex::new(0, y)..=CellIndex::new(u64::MAX, y))
+            .map(|(index, _)| index.x)
+            .collect()
+    }
+}
+
+/// Position reached by a jump along a line, given whether a position is written and the next
+/// written one in the direction of the jump: the end of the block of written cells when the next
+/// one is written too, the next written cell otherwise. Without any, backwards jumps reach the
+/// start of the line and forward ones stay.
+fn edge(
+    from: u64,
+    forward: bool,
+    is_written: impl Fn(u64) -> bool,
+    next_written: impl Fn(u64) -> Option<u64>,
+) -> u64 {
+    let step = |position: u64| match forward {
+        true => position.checked_add(1),
+        false => position.checked_sub(1),
+    };
+
+    if is_written(from) && step(from).is_some_and(&is_written) {
+        let mut to = from;
+        while let Some(next) = step(to).filter(|next| is_written(*next)) {
+            to = next;
+        }
+        return to;
+    }
+    next_written(from).unwrap_or(match forward {
+        true => from,
+        false => 0,
+    })
+}
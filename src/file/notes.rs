@@ -0,0 +1,94 @@
+use std::collections::BTreeMap;
+
+use crate::file::{cell, csv};
+
+/// Marker drawn in the top right corner of cells with a note.
+const INDICATOR: &str = "◥";
+const INDICATOR_COLOR: ratatui::style::Color = ratatui::style::Color::Indexed(203);
+/// Widest line of a note popup, longer lines being wrapped.
+const POPUP_WIDTH: usize = 40;
+
+/// Read the notes of the file from its sidecar, with a row for each note, as `A1;text`. Rows
+/// naming no cell are skipped.
+pub fn read(path: &std::path::Path) -> BTreeMap<cell::CellIndex, String> {
+    let text = std::fs::read_to_string(super::hidden_path(path, "notes")).unwrap_or_default();
+    csv::rows(&text, ';')
+        .into_iter()
+        .filter_map(|row| match row.as_slice() {
+            [index, note, ..] if !note.is_empty() => {
+                Some((cell::CellIndex::parse(index)?, note.clone()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Write the notes of the file next to it, removing the sidecar when there are none.
+pub fn write(
+    path: &std::path::Path,
+    notes: &BTreeMap<cell::CellIndex, String>,
+) -> std::io::Result<()> {
+    let text = notes
+        .iter()
+        .map(|(index, note)| format!("{index};{}\n", csv::quote(note, ';')))
+        .collect::<String>();
+    super::write_hidden(path, "notes", &text)
+}
+
+/// Mark a rendered cell as having a note.
+pub fn render_indicator(frame: &mut ratatui::Frame, cell_area: ratatui::layout::Rect) {
+    if let Some(cell) = frame
+        .buffer_mut()
+        .cell_mut((cell_area.right().saturating_sub(1), cell_area.y))
+    {
+        cell.set_symbol(INDICATOR).set_fg(INDICATOR_COLOR);
+    }
+}
+
+/// Show the note of the selected cell next to it, within the grid, unless the cell is hidden.
+pub fn render_popup(
+    note: &str,
+    cell_area: ratatui::layout::Rect,
+    grid_area: ratatui::layout::Rect,
+    frame: &mut ratatui::Frame,
+) {
+    use ratatui::style::Stylize;
+
+    if cell_area.is_empty() {
+        return;
+    }
+    let lines = note
+        .lines()
+        .flat_map(|line| {
+            let chars = line.chars().collect::<Vec<_>>();
+            match chars.is_empty() {
+                true => vec![String::new()],
+                false => chars
+                    .chunks(POPUP_WIDTH)
+                    .map(|chunk| chunk.iter().collect())
+                    .collect(),
+            }
+        })
+        .collect::<Vec<String>>();
+    let width = lines
+        .iter()
+        .map(|line| ratatui::text::Span::raw(line).width())
+        .max()
+        .unwrap_or_default();
+    let area = grid_area.intersection(ratatui::layout::Rect::new(
+        cell_area.right(),
+        cell_area.y,
+        crate::utils::usize_to_u16(width).saturating_add(2),
+        crate::utils::usize_to_u16(lines.len()).saturating_add(2),
+    ));
+    let block = ratatui::widgets::Block::bordered()
+        .border_type(ratatui::widgets::BorderType::Rounded)
+        .fg(INDICATOR_COLOR);
+    frame.render_widget(ratatui::widgets::Clear, area);
+    frame.render_widget(
+        ratatui::widgets::Paragraph::new(lines.join("\n"))
+            .block(block)
+            .white(),
+        area,
+    );
+}
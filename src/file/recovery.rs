@@ -0,0 +1,141 @@
+use std::collections::BTreeMap;
+
+use crate::file::change_history::{Change, Changes, Sheet};
+use crate::file::{cell, format, names, validation};
+
+/// Line starting the changes of each command in the journal.
+const JOURNAL_ENTRY: &str = "changes";
+
+/// Unsaved changes found next to a file when opening it, left by a crash.
+pub struct Recovered {
+    pub content: BTreeMap<cell::CellIndex, cell::Cell>,
+    pub formats: BTreeMap<cell::CellIndex, format::CellFormat>,
+    pub rules: Vec<format::conditional::Rule>,
+    pub validations: Vec<validation::Validation>,
+    pub notes: BTreeMap<cell::CellIndex, String>,
+    pub merges: Vec<cell::CellRect>,
+    pub names: Vec<names::Name>,
+    /// When the last change was written.
+    pub time: std::time::SystemTime,
+}
+
+/// Where the whole sheet is autosaved: a hidden swap file next to the file.
+pub fn swap_path(path: &std::path::Path) -> std::path::PathBuf {
+    super::hidden_path(path, "swp")
+}
+
+/// Where the changes made since the last autosave are appended.
+pub fn journal_path(path: &std::path::Path) -> std::path::PathBuf {
+    super::hidden_path(path, "journal")
+}
+
+/// Look for unsaved changes of the file: its swap file, or the sheet as saved when there is none,
+/// followed by the changes of the journal. Both are changes building the whole sheet from an empty
+/// one. Swap and journal older than the file are stale, it was saved again since.
+pub fn find(path: &std::path::Path, saved: &[Change]) -> Option<Recovered> {
+    let modified =
+        |path: &std::path::Path| std::fs::metadata(path).and_then(|meta| meta.modified());
+    let file_time = modified(path).ok()?;
+    let swap_time = modified(&swap_path(path)).ok();
+    let journal_time = modified(&journal_path(path)).ok();
+    if swap_time.is_some_and(|time| time < file_time)
+        || journal_time.is_some_and(|time| time < file_time)
+    {
+        return None;
+    }
+
+    let sheet = match swap_time {
+        Some(_) => read_swap(&std::fs::read_to_string(swap_path(path)).ok()?)?,
+        None => saved.to_vec(),
+    };
+    let mut recovered = Recovered {
+        content: BTreeMap::new(),
+        formats: BTreeMap::new(),
+        rules: Vec::new(),
+        validations: Vec::new(),
+        notes: BTreeMap::new(),
+        merges: Vec::new(),
+        names: Vec::new(),
+        time: swap_time.max(journal_time)?,
+    };
+    let journal = std::fs::read_to_string(journal_path(path)).unwrap_or_default();
+    let journal = read_journal(&journal);
+    let journal_changes = journal.iter().flat_map(|changes| changes.as_slice());
+    for change in sheet.iter().chain(journal_changes) {
+        change.apply(&mut Sheet {
+            content: &mut recovered.content,
+            formats: &mut recovered.formats,
+            rules: &mut recovered.rules,
+            validations: &mut recovered.validations,
+            notes: &mut recovered.notes,
+            merges: &mut recovered.merges,
+            names: &mut recovered.names,
+        });
+    }
+    Some(recovered)
+}
+
+/// Append the changes of a command to the journal.
+pub fn append_journal(path: &std::path::Path, steps: &[Changes]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut text = String::new();
+    for changes in steps {
+        text.push_str(JOURNAL_ENTRY);
+        text.push('\n');
+        for change in changes.as_slice() {
+            text.push_str(&change.to_line());
+            text.push('\n');
+        }
+    }
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(journal_path(path))?
+        .write_all(text.as_bytes())
+}
+
+/// Write the changes building the whole sheet to the swap file, starting a new empty journal
+/// after it.
+pub fn write_swap(path: &std::path::Path, sheet: &[Change]) -> std::io::Result<()> {
+    let mut text = String::new();
+    for change in sheet {
+        text.push_str(&change.to_line());
+        text.push('\n');
+    }
+    std::fs::write(swap_path(path), text)?;
+    std::fs::write(journal_path(path), "")
+}
+
+/// Read the changes of the swap file, None when it is damaged.
+fn read_swap(text: &str) -> Option<Vec<Change>> {
+    text.lines()
+        .map(|line| Change::from_fields(&line.split('\t').collect::<Vec<_>>()).ok())
+        .collect()
+}
+
+/// Remove the swap file and the journal, once their changes are saved or discarded.
+pub fn remove(path: &std::path::Path) {
+    // they may not have been written yet
+    let _ = std::fs::remove_file(swap_path(path));
+    let _ = std::fs::remove_file(journal_path(path));
+}
+
+/// Read the journal, skipping the last entry when the crash cut it.
+fn read_journal(text: &str) -> Vec<Changes> {
+    let mut entries = Vec::new();
+    let mut entry: Option<Vec<Change>> = None;
+    for line in text.lines() {
+        if line == JOURNAL_ENTRY {
+            entries.extend(entry.replace(Vec::new()).map(Changes::from_vec));
+            continue;
+        }
+        let fields = line.split('\t').collect::<Vec<_>>();
+        match (&mut entry, Change::from_fields(&fields)) {
+            (Some(changes), Ok(change)) => changes.push(change),
+            _ => return entries,
+        }
+    }
+    entries.extend(entry.map(Changes::from_vec));
+    entries
+}
@@ -0,0 +1,105 @@
+use crate::file::cell::{CellIndex, CellRect};
+
+/// Selected cells: the range from the active cell, where the selection started, to the cursor
+/// moved when extending it, and the ranges selected before it with Ctrl+click.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    /// Active cell, staying in place while the range is extended.
+    pub anchor: CellIndex,
+    /// Corner of the range opposite the active cell.
+    pub cursor: CellIndex,
+    /// Ranges added to the selection before the current one, shown and cleared along with it.
+    pub others: Vec<CellRect>,
+}
+
+impl Selection {
+    pub fn cell(index: CellIndex) -> Selection {
+        Selection::between(index, index)
+    }
+
+    pub fn between(anchor: CellIndex, cursor: CellIndex) -> Selection {
+        Selection {
+            anchor,
+            cursor,
+            others: Vec::new(),
+        }
+    }
+
+    /// Selection of the whole range, active from its top left cell.
+    pub fn range(cells: CellRect) -> Selection {
+        Selection::between(
+            CellIndex::new(cells.x, cells.y),
+            CellIndex::new(
+                cells.x.saturating_add(cells.width.saturating_sub(1)),
+                cells.y.saturating_add(cells.height.saturating_sub(1)),
+            ),
+        )
+    }
+
+    /// Current range, the one commands act on.
+    pub fn cells(&self) -> CellRect {
+        CellRect::new(
+            self.anchor.x.min(self.cursor.x),
+            self.anchor.y.min(self.cursor.y),
+            self.anchor.x.abs_diff(self.cursor.x) + 1,
+            self.anchor.y.abs_diff(self.cursor.y) + 1,
+        )
+    }
+
+    /// All the ranges, the current one last.
+    pub fn ranges(&self) -> impl Iterator<Item = CellRect> + '_ {
+        self.others.iter().copied().chain([self.cells()])
+    }
+
+    pub fn contains(&self, index: CellIndex) -> bool {
+        self.ranges().any(|cells| cells.contains(index))
+    }
+
+    /// Whether a range spans the given column.
+    pub fn contains_x(&self, x: u64) -> bool {
+        self.ranges()
+            .any(|cells| cells.x <= x && x < cells.x.saturating_add(cells.width))
+    }
+
+    /// Whether a range spans the given row.
+    pub fn contains_y(&self, y: u64) -> bool {
+        self.ranges()
+            .any(|cells| cells.y <= y && y < cells.y.saturating_add(cells.height))
+    }
+
+    /// Number of selected cells, over all the ranges.
+    pub fn count(&self) -> u64 {
+        self.ranges()
+            .map(|cells| cells.count())
+            .fold(0, u64::saturating_add)
+    }
+
+    /// Start a new range at the cell, keeping the current one selected.
+    pub fn add(&mut self, anchor: CellIndex, cursor: CellIndex) {
+        self.others.push(self.cells());
+        self.anchor = anchor;
+        self.cursor = cursor;
+    }
+
+    /// Move the cursor by an offset, growing or shrinking the range on either side of the active
+    /// cell. It stops at the edges of the sheet.
+    pub fn extend_by(&mut self, dx: i64, dy: i64) {
+        let offset = |position: u64, distance: i64| match distance < 0 {
+            true => position.saturating_sub(distance.unsigned_abs()),
+            false => position.saturating_add(distance.unsigned_abs()),
+        };
+        self.cursor = CellIndex::new(offset(self.cursor.x, dx), offset(self.cursor.y, dy));
+    }
+}
+
+impl std::fmt::Display for Selection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, cells) in self.ranges().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{cells}")?;
+        }
+        Ok(())
+    }
+}
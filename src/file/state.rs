@@ -1,7 +1,60 @@
 mod editing;
+mod history;
+mod prompt;
+mod rules;
 pub use editing::*;
+pub use history::*;
+pub use prompt::*;
+pub use rules::*;
 
 pub enum State {
     Idle,
     Editing(editing::EditingState),
+    Prompt(prompt::PromptState),
+    Rules(rules::RulesState<crate::file::format::conditional::Rule>),
+    Validations(rules::RulesState<crate::file::validation::Validation>),
+    Names(rules::RulesState<crate::file::names::Name>),
+    History(history::HistoryState),
+}
+
+/// What a state wants to do after handling an event.
+pub struct StateEventResponse {
+    pub command: crate::file::command::Command,
+    pub exit: bool,
+}
+
+/// Compute the areas of a popup editor drawn over the given cells: the inner text area,
+/// and the contour area in which the editor frame is drawn.
+fn popup_areas(
+    viewport: &crate::file::viewport::FileViewport,
+    cells: crate::file::cell::CellRect,
+    editor_frame: &crate::widgets::Editor,
+    content_width: usize,
+    content_height: usize,
+) -> (ratatui::layout::Rect, ratatui::layout::Rect) {
+    use crate::utils::usize_to_u16;
+
+    let cells_rect = viewport.cells_pos_to_screen_pos(cells);
+    let text_width = cells_rect
+        .width
+        .max(usize_to_u16(content_width))
+        .max(usize_to_u16(editor_frame.text_widths()));
+    let text_height = cells_rect.height.max(usize_to_u16(content_height));
+    let text_rect = viewport
+        .grid_area()
+        .intersection(ratatui::layout::Rect::new(
+            cells_rect.x,
+            cells_rect.y,
+            text_width,
+            text_height,
+        ));
+    let contour_rect = viewport
+        .grid_area()
+        .intersection(ratatui::layout::Rect::new(
+            text_rect.x.saturating_sub(1),
+            text_rect.y.saturating_sub(1),
+            text_rect.width.saturating_add(2),
+            text_rect.height.saturating_add(editor_frame.frame_height()),
+        ));
+    (text_rect, contour_rect)
 }
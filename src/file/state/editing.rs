@@ -1,178 +1,406 @@
+use crate::file::cell::{CellIndex, CellRect};
+use crate::file::formula;
+
+/// Colors of the references of an edited formula, in the editor and over their cells.
+const REFERENCE_COLORS: [ratatui::style::Color; 6] = [
+    ratatui::style::Color::Indexed(32),
+    ratatui::style::Color::Indexed(166),
+    ratatui::style::Color::Indexed(97),
+    ratatui::style::Color::Indexed(34),
+    ratatui::style::Color::Indexed(162),
+    ratatui::style::Color::Indexed(37),
+];
+const UNMATCHED_PAREN: ratatui::style::Color = ratatui::style::Color::Red;
+
 pub struct EditingState {
-    cells: crate::file::cell::CellRect,
+    cells: CellRect,
     buffer: crate::file::input_buffer::InputBuffer,
+    locale: crate::locale::Locale,
+    keymap: std::rc::Rc<crate::keymap::Keymap>,
+    /// Reference written by pointing at cells, rewritten while they change.
+    picked: Option<PickedReference>,
+    /// Values allowed by a list validation of the cells, picked with Up and Down.
+    choices: Vec<String>,
+}
+
+struct PickedReference {
+    /// Where it is written in the buffer.
+    span: std::ops::Range<usize>,
+    anchor: CellIndex,
+    cursor: CellIndex,
 }
 
 impl EditingState {
-    pub fn new(cells: crate::file::cell::CellRect, opening_chars: &str) -> EditingState {
+    pub fn new(
+        cells: CellRect,
+        opening_chars: &str,
+        locale: crate::locale::Locale,
+        keymap: std::rc::Rc<crate::keymap::Keymap>,
+        choices: Vec<String>,
+    ) -> EditingState {
         EditingState {
             cells,
-            buffer: crate::file::input_buffer::InputBuffer::new(opening_chars),
+            buffer: crate::file::input_buffer::InputBuffer::multiline(opening_chars),
+            locale,
+            keymap,
+            picked: None,
+            choices,
         }
     }
 
-    pub fn render(
+    /// Source of the edited formula, without its `=`, and the cursor in it.
+    fn formula_source(&self) -> Option<(&[char], usize)> {
+        match self.buffer.chars() {
+            ['=', source @ ..] => Some((source, self.buffer.cursor().saturating_sub(1))),
+            _ => None,
+        }
+    }
+
+    /// References of the edited formula, with their colors, to be highlighted over their cells.
+    pub fn references(&self) -> Vec<(CellRect, ratatui::style::Color)> {
+        let Some((source, _)) = self.formula_source() else {
+            return Vec::new();
+        };
+        formula::references(source)
+            .into_iter()
+            .zip(REFERENCE_COLORS.iter().cycle())
+            .map(|((_, cells), color)| (cells, *color))
+            .collect()
+    }
+
+    /// The reference being pointed at, when nothing was typed since.
+    fn picking(&self) -> Option<&PickedReference> {
+        self.picked.as_ref().filter(|picked| {
+            self.buffer.cursor() == picked.span.end
+                && self
+                    .buffer
+                    .chars()
+                    .get(picked.span.clone())
+                    .is_some_and(|written| {
+                        written.iter().collect::<String>()
+                            == reference_text(picked.anchor, picked.cursor)
+                    })
+        })
+    }
+
+    /// Whether a reference can be written at the cursor: in a formula, after an operator or an
+    /// opening parenthesis, and before the end of an argument.
+    fn can_insert_reference(&self) -> bool {
+        let Some((source, cursor)) = self.formula_source() else {
+            return false;
+        };
+        let before = source[..cursor].iter().rev().find(|ch| !ch.is_whitespace());
+        let after = source[cursor..].iter().find(|ch| !ch.is_whitespace());
+        !formula::in_text(&source[..cursor])
+            && matches!(
+                before,
+                None | Some(
+                    '(' | ',' | ';' | ':' | '+' | '-' | '*' | '/' | '^' | '&' | '=' | '<' | '>'
+                )
+            )
+            && matches!(after, None | Some(')' | ',' | ';'))
+    }
+
+    /// Write the reference to the cells between the anchor and the cursor, in place of the one
+    /// being pointed at. Returns whether it could be written.
+    fn pick(&mut self, anchor: CellIndex, cursor: CellIndex) -> bool {
+        let span = match self.picking() {
+            Some(picked) => picked.span.clone(),
+            None if self.can_insert_reference() => self.buffer.cursor()..self.buffer.cursor(),
+            None => return false,
+        };
+        let text = reference_text(anchor, cursor);
+        let start = span.start;
+        self.buffer.replace(span, &text);
+        self.picked = Some(PickedReference {
+            span: start..self.buffer.cursor(),
+            anchor,
+            cursor,
+        });
+        true
+    }
+
+    /// Point at the cell next to the pointed one, or to the edited cell for the first reference.
+    /// Extending keeps the anchor of the range in place.
+    fn pick_towards(&mut self, direction: crate::keymap::Direction, extend: bool) -> bool {
+        use crate::keymap::Direction;
+
+        let (anchor, cursor) = match self.picking() {
+            Some(picked) => (picked.anchor, picked.cursor),
+            None => {
+                let edited = CellIndex::new(self.cells.x, self.cells.y);
+                (edited, edited)
+            }
+        };
+        let (dx, dy) = match direction {
+            Direction::Up => (0, -1),
+            Direction::Down => (0, 1),
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+        };
+        let cursor = cursor.offset(dx, dy).unwrap_or(cursor);
+        self.pick(if extend { anchor } else { cursor }, cursor)
+    }
+
+    /// Point at cells with the mouse while typing a formula: pressing writes the reference of a
+    /// cell, or replaces the one pointed at, and dragging stretches it to a range. Returns whether
+    /// the event was used.
+    pub fn handle_mouse(
+        &mut self,
+        mouse: crossterm::event::MouseEvent,
+        viewport: &crate::file::viewport::FileViewport,
+    ) -> bool {
+        use crossterm::event::MouseEventKind;
+
+        let position = ratatui::layout::Position::new(mouse.column, mouse.row);
+        let Some(cell) = viewport.screen_pos_to_cell_pos(position) else {
+            return false;
+        };
+        match mouse.kind {
+            MouseEventKind::Down(_) => self.pick(cell, cell),
+            MouseEventKind::Drag(_) => match self.picking() {
+                Some(picked) => self.pick(picked.anchor, cell),
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Position of the written value among the choices.
+    fn choice(&self) -> Option<usize> {
+        let text = self.buffer.string();
+        self.choices
+            .iter()
+            .position(|choice| choice.eq_ignore_ascii_case(&text))
+    }
+
+    /// Replace the written value by the next or previous choice. Returns whether there are
+    /// choices, outside formulas.
+    fn pick_choice(&mut self, direction: crate::keymap::Direction) -> bool {
+        use crate::keymap::Direction;
+
+        let last = match self.choices.len().checked_sub(1) {
+            Some(last) if self.formula_source().is_none() => last,
+            _ => return false,
+        };
+        let picked = match (direction, self.choice()) {
+            (Direction::Down, None) => 0,
+            (Direction::Down, Some(choice)) => (choice + 1).min(last),
+            (Direction::Up, None) => last,
+            (Direction::Up, Some(choice)) => choice.saturating_sub(1),
+            _ => return false,
+        };
+        let text = self.choices[picked].clone();
+        self.buffer.replace(0..self.buffer.chars().len(), &text);
+        true
+    }
+
+    /// Draw the choices in a list under the editor, the written one highlighted.
+    fn render_choices(
         &self,
         viewport: &crate::file::viewport::FileViewport,
+        below: ratatui::layout::Rect,
         frame: &mut ratatui::Frame,
     ) {
-        use crate::utils::usize_to_u16;
-
-        let editor_frame = crate::widgets::Editor::new("Save: Enter/Tab", "Cancel: Esc");
-
-        let cells_rect = viewport.cells_pos_to_screen_pos(self.cells);
-        let text_width = cells_rect
-            .width
-            .max(usize_to_u16(self.buffer.required_width()))
-            .max(usize_to_u16(editor_frame.text_widths()));
-        let text_height = cells_rect
-            .height
-            .max(usize_to_u16(self.buffer.required_height()));
-        let text_rect = viewport
-            .grid_area()
-            .intersection(ratatui::layout::Rect::new(
-                cells_rect.x,
-                cells_rect.y,
-                text_width,
-                text_height,
-            ));
-        let contour_rect = viewport
+        use ratatui::style::Stylize;
+
+        let choice = self.choice();
+        let lines = self
+            .choices
+            .iter()
+            .enumerate()
+            .map(|(index, text)| match Some(index) == choice {
+                true => ratatui::text::Line::from(text.as_str()).reversed(),
+                false => ratatui::text::Line::from(text.as_str()),
+            })
+            .collect::<Vec<_>>();
+        let width = lines
+            .iter()
+            .map(|line| line.width())
+            .max()
+            .unwrap_or_default();
+        let area = viewport
             .grid_area()
             .intersection(ratatui::layout::Rect::new(
-                text_rect.x.saturating_sub(1),
-                text_rect.y.saturating_sub(1),
-                text_rect.width.saturating_add(2),
-                text_rect.height.saturating_add(4),
+                below.x,
+                below.bottom(),
+                crate::utils::usize_to_u16(width).saturating_add(2),
+                crate::utils::usize_to_u16(lines.len()).saturating_add(2),
             ));
+        let block = ratatui::widgets::Block::bordered()
+            .border_type(ratatui::widgets::BorderType::Rounded)
+            .cyan();
+        frame.render_widget(ratatui::widgets::Clear, area);
+        frame.render_widget(
+            ratatui::widgets::Paragraph::new(lines).block(block).white(),
+            area,
+        );
+    }
+
+    /// Help for the formula being typed: the functions completing the name before the cursor, or
+    /// the arguments of the function around it.
+    fn hint(&self) -> String {
+        let Some((source, cursor)) = self.formula_source() else {
+            return String::new();
+        };
+        match formula::completions(source, cursor) {
+            Some((_, functions)) => {
+                let mut hint = format!(
+                    "{}: {}",
+                    self.keymap.keys(crate::keymap::Action::ConfirmNext),
+                    functions[0].signature
+                );
+                for function in &functions[1..] {
+                    hint.push(' ');
+                    hint.push_str(function.name);
+                }
+                hint
+            }
+            None => formula::enclosing_function(source, cursor)
+                .map(|function| function.signature.to_string())
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn render(
+        &self,
+        viewport: &crate::file::viewport::FileViewport,
+        frame: &mut ratatui::Frame,
+    ) {
+        use crate::keymap::Action;
+        use ratatui::style::Style;
+
+        let validate_text = format!(
+            "Save: {}/{}",
+            self.keymap.keys(Action::Confirm),
+            self.keymap.keys(Action::ConfirmNext)
+        );
+        let cancel_text = format!("Cancel: {}", self.keymap.keys(Action::Cancel));
+        let hint = self.hint();
+        let editor_frame =
+            crate::widgets::Editor::new(&validate_text, &cancel_text).with_hint(&hint);
+        let (text_rect, contour_rect) = super::popup_areas(
+            viewport,
+            self.cells,
+            &editor_frame,
+            self.buffer.required_width(),
+            self.buffer.required_height(),
+        );
+
+        // positions in the formula source are one after the `=` in the buffer
+        let highlights = match self.formula_source() {
+            Some((source, _)) => formula::references(source)
+                .into_iter()
+                .zip(REFERENCE_COLORS.iter().cycle())
+                .map(|((span, _), color)| (span.start + 1..span.end + 1, Style::new().fg(*color)))
+                .chain(
+                    formula::unmatched_parens(source)
+                        .into_iter()
+                        .map(|paren| (paren + 1..paren + 2, Style::new().bg(UNMATCHED_PAREN))),
+                )
+                .collect(),
+            None => Vec::new(),
+        };
 
         frame.render_widget(ratatui::widgets::Clear, contour_rect);
         frame.render_widget(editor_frame, contour_rect);
-        self.buffer.render(frame, text_rect);
+        self.buffer.render(&highlights, frame, text_rect);
+        if !self.choices.is_empty() {
+            self.render_choices(viewport, contour_rect, frame);
+        }
     }
 }
 
-pub struct EditingStateEventResponse {
-    pub command: crate::file::command::Command,
-    pub exit: bool,
+/// Reference to the cells between two corners, as written in formulas.
+fn reference_text(anchor: CellIndex, cursor: CellIndex) -> String {
+    match anchor == cursor {
+        true => anchor.to_string(),
+        false => format!("{anchor}:{cursor}"),
+    }
 }
 
 impl crate::event::EventHandler for EditingState {
-    type EventResponse = Option<EditingStateEventResponse>;
+    type EventResponse = Option<super::StateEventResponse>;
     fn handle_event(
         &mut self,
         event: crossterm::event::Event,
         _: &mut String,
     ) -> Self::EventResponse {
         use crate::file::command::{Command, SelectionDirection};
-        use crossterm::event::Event;
-        use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
-
-        match event {
-            Event::Key(KeyEvent {
-                kind: KeyEventKind::Press | KeyEventKind::Repeat,
-                code: KeyCode::Char(ch),
-                ..
-            }) => {
-                self.buffer.push(ch);
-                Some(EditingStateEventResponse {
-                    command: Command::RedrawRequest,
-                    exit: false,
-                })
-            }
-            Event::Key(KeyEvent {
-                kind: KeyEventKind::Press | KeyEventKind::Repeat,
-                code: KeyCode::Backspace,
-                ..
-            }) => {
-                self.buffer.del_front();
-                Some(EditingStateEventResponse {
-                    command: Command::RedrawRequest,
-                    exit: false,
-                })
-            }
-            Event::Key(KeyEvent {
-                kind: KeyEventKind::Press | KeyEventKind::Repeat,
-                code: KeyCode::Delete,
-                ..
-            }) => {
-                self.buffer.del_back();
-                Some(EditingStateEventResponse {
-                    command: Command::RedrawRequest,
-                    exit: false,
-                })
-            }
-            Event::Key(KeyEvent {
-                kind: KeyEventKind::Press | KeyEventKind::Repeat,
-                code: KeyCode::Left,
-                modifiers,
-                ..
-            }) => {
-                let whole_word = modifiers.contains(KeyModifiers::CONTROL);
-                self.buffer.decrement_cursor(whole_word);
-                Some(EditingStateEventResponse {
-                    command: Command::RedrawRequest,
-                    exit: false,
-                })
-            }
-            Event::Key(KeyEvent {
-                kind: KeyEventKind::Press | KeyEventKind::Repeat,
-                code: KeyCode::Right,
-                modifiers,
-                ..
-            }) => {
-                let whole_word = modifiers.contains(KeyModifiers::CONTROL);
-                self.buffer.increment_cursor(whole_word);
-                Some(EditingStateEventResponse {
-                    command: Command::RedrawRequest,
-                    exit: false,
-                })
+        use crate::keymap::{Action, Direction};
+        use crossterm::event::{Event, KeyCode, KeyEventKind, KeyModifiers};
+
+        let redraw = Some(super::StateEventResponse {
+            command: Command::RedrawRequest,
+            exit: false,
+        });
+
+        // bound keys first, they may be typed characters
+        let action = match &event {
+            Event::Key(key) => self.keymap.action(crate::keymap::Context::Editing, key),
+            _ => None,
+        };
+        // the key confirming to the right completes a function name being typed
+        if action == Some(Action::ConfirmNext)
+            && let Some((source, cursor)) = self.formula_source()
+            && let Some((start, functions)) = formula::completions(source, cursor)
+        {
+            let name = format!("{}(", functions[0].name);
+            self.buffer.replace(start + 1..cursor + 1, &name);
+            return redraw;
+        }
+        // arrows point at cells where a reference can be written in a formula
+        if let Event::Key(key) = &event
+            && matches!(key.kind, KeyEventKind::Press | KeyEventKind::Repeat)
+            && !key
+                .modifiers
+                .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+        {
+            let direction = match key.code {
+                KeyCode::Up => Some(Direction::Up),
+                KeyCode::Down => Some(Direction::Down),
+                KeyCode::Left => Some(Direction::Left),
+                KeyCode::Right => Some(Direction::Right),
+                _ => None,
+            };
+            if let Some(direction) = direction
+                && (self.pick_towards(direction, key.modifiers.contains(KeyModifiers::SHIFT))
+                    || (key.modifiers.is_empty() && self.pick_choice(direction)))
+            {
+                return redraw;
             }
-            Event::Paste(pasted_value) => {
-                pasted_value.chars().for_each(|ch| self.buffer.push(ch));
-                Some(EditingStateEventResponse {
+        }
+
+        let next_selection = match action {
+            Some(Action::Cancel) => {
+                return Some(super::StateEventResponse {
                     command: Command::RedrawRequest,
-                    exit: false,
-                })
-            }
-            Event::Key(KeyEvent {
-                kind: KeyEventKind::Press,
-                code: KeyCode::Esc,
-                ..
-            }) => Some(EditingStateEventResponse {
-                command: Command::RedrawRequest,
-                exit: true,
-            }),
-            Event::Key(KeyEvent {
-                kind: KeyEventKind::Press,
-                code: KeyCode::Enter,
-                ..
-            }) => {
-                let buffer = self.buffer.string();
-                Some(EditingStateEventResponse {
-                    command: Command::WriteCells {
-                        cells: self.cells,
-                        content: crate::file::cell::Cell::parse(&buffer),
-                        next_selection: SelectionDirection::Return,
-                    },
                     exit: true,
-                })
+                });
             }
-            Event::Key(KeyEvent {
-                kind: KeyEventKind::Press,
-                code: KeyCode::Tab,
-                ..
-            }) => {
-                let buffer = self.buffer.string();
-                Some(EditingStateEventResponse {
-                    command: Command::WriteCells {
-                        cells: self.cells,
-                        content: crate::file::cell::Cell::parse(&buffer),
-                        next_selection: SelectionDirection::Next,
+            Some(Action::Newline) => {
+                self.buffer.push('\n');
+                return redraw;
+            }
+            Some(Action::Confirm) => SelectionDirection::Return,
+            Some(Action::ConfirmNext) => SelectionDirection::Next,
+            _ => {
+                return self.buffer.handle_edit_event(&event).then_some(
+                    super::StateEventResponse {
+                        command: Command::RedrawRequest,
+                        exit: false,
                     },
-                    exit: true,
-                })
+                );
             }
-            _ => None,
-        }
+        };
+        let buffer = self.buffer.string();
+        Some(super::StateEventResponse {
+            command: Command::WriteCells {
+                cells: self.cells,
+                content: crate::file::cell::Cell::parse(&buffer, &self.locale),
+                next_selection,
+            },
+            exit: true,
+        })
     }
 }
@@ -0,0 +1,167 @@
+use crate::file::change_history::HistoryEntry;
+
+/// Number of history lines shown at once, the list scrolling to keep the selected one visible.
+const VISIBLE_ENTRIES: usize = 16;
+
+/// Panel listing the states of the undo tree, to go back to any of them, even one left behind
+/// on another branch. Going to a state applies it at once, so it can be looked at.
+pub struct HistoryState {
+    /// Cells selected when opening the panel, where it is drawn.
+    cells: crate::file::cell::CellRect,
+    entries: Vec<HistoryEntry>,
+    /// Node of the current state.
+    current: usize,
+    /// Selected line.
+    selected: usize,
+}
+
+impl HistoryState {
+    pub fn new(
+        cells: crate::file::cell::CellRect,
+        entries: Vec<HistoryEntry>,
+        current: usize,
+    ) -> HistoryState {
+        let selected = entries
+            .iter()
+            .position(|entry| entry.node == current)
+            .unwrap_or_default();
+        HistoryState {
+            cells,
+            entries,
+            current,
+            selected,
+        }
+    }
+
+    pub fn render(
+        &self,
+        viewport: &crate::file::viewport::FileViewport,
+        frame: &mut ratatui::Frame,
+    ) {
+        use ratatui::style::Stylize;
+
+        let editor_frame = crate::widgets::Editor::new("Go to: Enter", "Close: Esc");
+        let now = crate::file::change_history::now();
+        let first = (self.selected + 1).saturating_sub(VISIBLE_ENTRIES);
+        let lines = self
+            .entries
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(VISIBLE_ENTRIES)
+            .map(|(index, entry)| {
+                let marker = match entry.node == self.current {
+                    true => '●',
+                    false => '○',
+                };
+                let line = format!(
+                    "{}{marker} {:>8}  {}",
+                    "  ".repeat(entry.depth),
+                    elapsed(now.saturating_sub(entry.time)),
+                    entry.description
+                );
+                (index, line)
+            })
+            .collect::<Vec<_>>();
+        let content_width = lines
+            .iter()
+            .map(|(_, line)| line.chars().count())
+            .max()
+            .unwrap_or_default();
+        let (text_rect, contour_rect) = super::popup_areas(
+            viewport,
+            self.cells,
+            &editor_frame,
+            content_width,
+            lines.len(),
+        );
+
+        frame.render_widget(ratatui::widgets::Clear, contour_rect);
+        frame.render_widget(editor_frame, contour_rect);
+
+        for (row, (index, line)) in lines.iter().enumerate() {
+            let line_rect = text_rect.intersection(ratatui::layout::Rect::new(
+                text_rect.x,
+                text_rect.y.saturating_add(crate::utils::usize_to_u16(row)),
+                text_rect.width,
+                1,
+            ));
+            let paragraph = ratatui::widgets::Paragraph::new(line.as_str());
+            match *index == self.selected {
+                true => frame.render_widget(paragraph.reversed(), line_rect),
+                false => frame.render_widget(paragraph, line_rect),
+            }
+        }
+    }
+}
+
+/// Time since a change, in the largest whole unit.
+pub fn elapsed(seconds: u64) -> String {
+    match seconds {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{} min ago", seconds / 60),
+        3600..86400 => format!("{} h ago", seconds / 3600),
+        _ => format!("{} d ago", seconds / 86400),
+    }
+}
+
+impl crate::event::EventHandler for HistoryState {
+    type EventResponse = Option<super::StateEventResponse>;
+    fn handle_event(
+        &mut self,
+        event: crossterm::event::Event,
+        info: &mut String,
+    ) -> Self::EventResponse {
+        use crate::file::command::Command;
+        use crossterm::event::Event;
+        use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+
+        let redraw = Some(super::StateEventResponse {
+            command: Command::RedrawRequest,
+            exit: false,
+        });
+
+        match event {
+            Event::Key(KeyEvent {
+                kind: KeyEventKind::Press,
+                code: KeyCode::Esc,
+                ..
+            }) => {
+                info.clear();
+                Some(super::StateEventResponse {
+                    command: Command::RedrawRequest,
+                    exit: true,
+                })
+            }
+            Event::Key(KeyEvent {
+                kind: KeyEventKind::Press,
+                code: KeyCode::Up,
+                ..
+            }) => {
+                self.selected = self.selected.saturating_sub(1);
+                redraw
+            }
+            Event::Key(KeyEvent {
+                kind: KeyEventKind::Press,
+                code: KeyCode::Down,
+                ..
+            }) => {
+                self.selected = (self.selected + 1).min(self.entries.len().saturating_sub(1));
+                redraw
+            }
+            Event::Key(KeyEvent {
+                kind: KeyEventKind::Press,
+                code: KeyCode::Enter,
+                ..
+            }) => {
+                let node = self.entries.get(self.selected)?.node;
+                self.current = node;
+                Some(super::StateEventResponse {
+                    command: Command::JumpHistory { node },
+                    exit: false,
+                })
+            }
+            _ => None,
+        }
+    }
+}
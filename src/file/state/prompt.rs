@@ -0,0 +1,339 @@
+/// What a prompt is asking for, and what to do with the answer.
+pub enum PromptKind {
+    NumberFormat {
+        cells: crate::file::cell::CellRect,
+    },
+    Foreground {
+        cells: crate::file::cell::CellRect,
+    },
+    Background {
+        cells: crate::file::cell::CellRect,
+    },
+    Borders {
+        cells: crate::file::cell::CellRect,
+    },
+    PasteSpecial {
+        cells: crate::file::cell::CellRect,
+    },
+    /// Path to save the sheet to.
+    SaveAs {
+        cells: crate::file::cell::CellRect,
+    },
+    /// Path to export the values of the cells to.
+    ExportCells {
+        cells: crate::file::cell::CellRect,
+    },
+    /// Command line of vim mode, as `:w` or `:q`.
+    VimCommand {
+        cells: crate::file::cell::CellRect,
+    },
+    /// Text to search in the cells.
+    Search {
+        cells: crate::file::cell::CellRect,
+    },
+    /// Cells or name of cells to select.
+    GoTo {
+        cells: crate::file::cell::CellRect,
+    },
+    /// Note of the top left cell, deleted when empty.
+    Note {
+        cells: crate::file::cell::CellRect,
+    },
+    /// What to do with the file changed on disk by another program, as summarized.
+    ExternalChange {
+        cells: crate::file::cell::CellRect,
+        summary: String,
+    },
+    /// Whether to run the command replacing an existing file.
+    Overwrite {
+        cells: crate::file::cell::CellRect,
+        question: String,
+        command: Box<crate::file::command::Command>,
+    },
+    /// Whether to recover the unsaved changes found when opening the file, as summarized.
+    Recover {
+        cells: crate::file::cell::CellRect,
+        summary: String,
+    },
+}
+
+impl PromptKind {
+    fn label(&self) -> &str {
+        match self {
+            PromptKind::NumberFormat { .. } => "Number format: ",
+            PromptKind::Foreground { .. } => "Text colour: ",
+            PromptKind::Background { .. } => "Fill colour: ",
+            PromptKind::Borders { .. } => "Borders: ",
+            PromptKind::PasteSpecial { .. } => "Paste special: ",
+            PromptKind::SaveAs { .. } => "Save as: ",
+            PromptKind::ExportCells { .. } => "Export to: ",
+            PromptKind::VimCommand { .. } => ":",
+            PromptKind::Search { .. } => "/",
+            PromptKind::GoTo { .. } => "Go to: ",
+            PromptKind::Note { .. } => "Note: ",
+            PromptKind::ExternalChange { summary, .. }
+            | PromptKind::Overwrite {
+                question: summary, ..
+            }
+            | PromptKind::Recover { summary, .. } => summary,
+        }
+    }
+
+    fn anchor(&self) -> crate::file::cell::CellRect {
+        match self {
+            PromptKind::NumberFormat { cells }
+            | PromptKind::Foreground { cells }
+            | PromptKind::Background { cells }
+            | PromptKind::Borders { cells }
+            | PromptKind::PasteSpecial { cells }
+            | PromptKind::SaveAs { cells }
+            | PromptKind::ExportCells { cells }
+            | PromptKind::VimCommand { cells }
+            | PromptKind::Search { cells }
+            | PromptKind::GoTo { cells }
+            | PromptKind::Note { cells }
+            | PromptKind::ExternalChange { cells, .. }
+            | PromptKind::Overwrite { cells, .. }
+            | PromptKind::Recover { cells, .. } => *cells,
+        }
+    }
+
+    /// Build the command to execute from the prompt answer.
+    fn command(&self, answer: &str) -> Result<crate::file::command::Command, String> {
+        use crate::file::command::Command;
+        use crate::file::format::FormatChange;
+
+        let (cells, change) = match self {
+            PromptKind::NumberFormat { cells } => {
+                let number_format = match answer.trim() {
+                    "" => None,
+                    code => Some(crate::file::format::NumberFormat::parse(code)?),
+                };
+                (cells, FormatChange::NumberFormat(number_format))
+            }
+            PromptKind::Foreground { cells } => (
+                cells,
+                FormatChange::Foreground(FormatChange::parse_color(answer)?),
+            ),
+            PromptKind::Background { cells } => (
+                cells,
+                FormatChange::Background(FormatChange::parse_color(answer)?),
+            ),
+            PromptKind::Borders { cells } => (cells, FormatChange::parse_borders(answer)?),
+            PromptKind::PasteSpecial { cells } => {
+                return Ok(Command::PasteClipboard {
+                    cells: *cells,
+                    options: crate::file::clipboard::PasteOptions::parse(answer)?,
+                });
+            }
+            PromptKind::SaveAs { .. } => {
+                return Ok(Command::SaveAs {
+                    path: parse_path(answer)?,
+                    overwrite: false,
+                });
+            }
+            PromptKind::ExportCells { cells } => {
+                return Ok(Command::ExportCells {
+                    cells: *cells,
+                    path: parse_path(answer)?,
+                    overwrite: false,
+                });
+            }
+            PromptKind::VimCommand { cells } => return vim_command(answer, *cells),
+            PromptKind::Search { .. } => {
+                return match answer {
+                    "" => Err("Type the text to search".to_string()),
+                    text => Ok(Command::Search {
+                        text: text.to_string(),
+                        backwards: false,
+                    }),
+                };
+            }
+            PromptKind::GoTo { .. } => {
+                return match answer.trim() {
+                    "" => Err("Type the cells or the name to go to".to_string()),
+                    target => Ok(Command::GoToCells {
+                        target: target.to_string(),
+                    }),
+                };
+            }
+            PromptKind::Note { cells } => {
+                return Ok(Command::SetNote {
+                    index: crate::file::cell::CellIndex::new(cells.x, cells.y),
+                    note: (!answer.trim().is_empty()).then(|| answer.to_string()),
+                });
+            }
+            PromptKind::ExternalChange { .. } => {
+                return Ok(Command::ResolveExternalChange {
+                    resolution: crate::file::disk::Resolution::parse(answer)?,
+                });
+            }
+            PromptKind::Overwrite { command, .. } => {
+                return match answer.trim().to_ascii_lowercase().as_str() {
+                    "y" | "yes" => Ok(command.as_ref().clone()),
+                    "n" | "no" => Ok(Command::RedrawRequest),
+                    _ => Err("Answer y to replace the file, n to keep it".into()),
+                };
+            }
+            PromptKind::Recover { .. } => {
+                let accept = match answer.trim().to_ascii_lowercase().as_str() {
+                    "y" | "yes" => true,
+                    "n" | "no" => false,
+                    _ => return Err("Answer y to recover the changes, n to discard them".into()),
+                };
+                return Ok(Command::Recover { accept });
+            }
+        };
+        Ok(Command::FormatCells {
+            cells: *cells,
+            change,
+        })
+    }
+}
+
+/// Path typed in a prompt, relative to the working directory.
+fn parse_path(answer: &str) -> Result<std::path::PathBuf, String> {
+    match answer.trim() {
+        "" => Err("Type the path of the file to write".to_string()),
+        path => Ok(std::path::PathBuf::from(path)),
+    }
+}
+
+/// Command typed after `:` in vim mode: `w` (with an optional path), `q`, `q!`, `wq`, `x`, or a
+/// row number to go to.
+fn vim_command(
+    answer: &str,
+    cells: crate::file::cell::CellRect,
+) -> Result<crate::file::command::Command, String> {
+    use crate::file::command::Command;
+
+    let answer = answer.trim();
+    if let Ok(row) = answer.parse::<u64>() {
+        return Ok(Command::Select {
+            cells: crate::file::cell::CellRect::new(cells.x, row.saturating_sub(1), 1, 1),
+        });
+    }
+    match answer.split_once(' ') {
+        Some(("w", path)) => Ok(Command::WriteCopy {
+            path: parse_path(path)?,
+            overwrite: false,
+        }),
+        _ => match answer {
+            "w" => Ok(Command::Save),
+            "q" => Ok(Command::Quit {
+                save: false,
+                force: false,
+            }),
+            "q!" => Ok(Command::Quit {
+                save: false,
+                force: true,
+            }),
+            "wq" | "x" => Ok(Command::Quit {
+                save: true,
+                force: false,
+            }),
+            other => Err(format!("Not an editor command: {other}")),
+        },
+    }
+}
+
+/// Single line text input, asking the user for a value.
+pub struct PromptState {
+    kind: PromptKind,
+    buffer: crate::file::input_buffer::InputBuffer,
+}
+
+impl PromptState {
+    pub fn new(kind: PromptKind, initial_value: &str) -> PromptState {
+        PromptState {
+            kind,
+            buffer: crate::file::input_buffer::InputBuffer::new(initial_value),
+        }
+    }
+
+    pub fn render(
+        &self,
+        viewport: &crate::file::viewport::FileViewport,
+        frame: &mut ratatui::Frame,
+    ) {
+        use crate::utils::usize_to_u16;
+        use ratatui::style::Stylize;
+
+        let editor_frame = crate::widgets::Editor::new("Apply: Enter", "Cancel: Esc");
+        let label = self.kind.label();
+        let (text_rect, contour_rect) = super::popup_areas(
+            viewport,
+            self.kind.anchor(),
+            &editor_frame,
+            label.chars().count() + self.buffer.required_width(),
+            1,
+        );
+
+        frame.render_widget(ratatui::widgets::Clear, contour_rect);
+        frame.render_widget(editor_frame, contour_rect);
+
+        let label_width = usize_to_u16(label.chars().count()).min(text_rect.width);
+        let [label_rect, input_rect] = ratatui::layout::Layout::horizontal([
+            ratatui::layout::Constraint::Length(label_width),
+            ratatui::layout::Constraint::Min(0),
+        ])
+        .areas(text_rect);
+        frame.render_widget(
+            ratatui::widgets::Paragraph::new(label).fg(ratatui::style::Color::Cyan),
+            label_rect,
+        );
+        self.buffer.render(&[], frame, input_rect);
+    }
+}
+
+impl crate::event::EventHandler for PromptState {
+    type EventResponse = Option<super::StateEventResponse>;
+    fn handle_event(
+        &mut self,
+        event: crossterm::event::Event,
+        info: &mut String,
+    ) -> Self::EventResponse {
+        use crate::file::command::Command;
+        use crossterm::event::Event;
+        use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+
+        if self.buffer.handle_edit_event(&event) {
+            return Some(super::StateEventResponse {
+                command: Command::RedrawRequest,
+                exit: false,
+            });
+        }
+
+        match event {
+            Event::Key(KeyEvent {
+                kind: KeyEventKind::Press,
+                code: KeyCode::Esc,
+                ..
+            }) => {
+                info.clear();
+                Some(super::StateEventResponse {
+                    command: Command::RedrawRequest,
+                    exit: true,
+                })
+            }
+            Event::Key(KeyEvent {
+                kind: KeyEventKind::Press,
+                code: KeyCode::Enter,
+                ..
+            }) => match self.kind.command(&self.buffer.string()) {
+                Ok(command) => Some(super::StateEventResponse {
+                    command,
+                    exit: true,
+                }),
+                Err(e) => {
+                    *info = e;
+                    Some(super::StateEventResponse {
+                        command: Command::RedrawRequest,
+                        exit: false,
+                    })
+                }
+            },
+            _ => None,
+        }
+    }
+}
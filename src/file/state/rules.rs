@@ -0,0 +1,268 @@
+use crate::file::command::Command;
+use crate::file::format::conditional::Rule;
+use crate::file::names::Name;
+use crate::file::validation::Validation;
+
+const NEW_RULE: &str = "+ new rule";
+
+/// Rule edited in the dialog, written as a line of text.
+pub trait DialogRule: Clone + std::fmt::Display {
+    /// How a rule is written, shown while writing one.
+    const SYNTAX: &'static str;
+    fn parse(source: &str) -> Result<Self, String>;
+    /// Command replacing all the rules of this kind.
+    fn command(rules: Vec<Self>) -> Command;
+    /// Text a new rule starts from, for the cells selected when opening the dialog.
+    fn template(cells: crate::file::cell::CellRect) -> String {
+        format!("{cells} ")
+    }
+    /// Whether the rules can stand together, why not otherwise.
+    fn check(_rules: &[Self]) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl DialogRule for Rule {
+    const SYNTAX: &'static str = "Rule: <cells> <condition> [fg=<colour>] [bg=<colour>] [bold]";
+    fn parse(source: &str) -> Result<Self, String> {
+        Rule::parse(source)
+    }
+    fn command(rules: Vec<Self>) -> Command {
+        Command::SetRules { rules }
+    }
+}
+
+impl DialogRule for Validation {
+    const SYNTAX: &'static str = "Validation: <cells> <number|integer|list|date|length|formula> \
+                                  <arguments> [warn]";
+    fn parse(source: &str) -> Result<Self, String> {
+        Validation::parse(source)
+    }
+    fn command(validations: Vec<Self>) -> Command {
+        Command::SetValidations { validations }
+    }
+}
+
+impl DialogRule for Name {
+    const SYNTAX: &'static str = "Name: <name> <cells|value>, texts looking like another value \
+                                  being quoted";
+    fn parse(source: &str) -> Result<Self, String> {
+        Name::parse(source)
+    }
+    fn command(names: Vec<Self>) -> Command {
+        Command::SetNames { names }
+    }
+    fn template(cells: crate::file::cell::CellRect) -> String {
+        format!("Name {cells}")
+    }
+    fn check(names: &[Self]) -> Result<(), String> {
+        for (index, name) in names.iter().enumerate() {
+            if crate::file::names::find(&names[..index], &name.name).is_some() {
+                return Err(format!("The name \"{}\" is already used", name.name));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Dialog listing the conditional formatting or data validation rules, or the names, to add,
+/// edit or remove them. Every validated edit is applied at once, so it can be seen and undone.
+pub struct RulesState<R: DialogRule> {
+    /// Cells selected when opening the dialog, used as the range of new rules.
+    cells: crate::file::cell::CellRect,
+    rules: Vec<R>,
+    /// Selected line, where the last one adds a new rule.
+    selected: usize,
+    /// Input of the rule being written, if any.
+    editing: Option<crate::file::input_buffer::InputBuffer>,
+}
+
+impl<R: DialogRule> RulesState<R> {
+    pub fn new(cells: crate::file::cell::CellRect, rules: Vec<R>) -> RulesState<R> {
+        RulesState {
+            cells,
+            rules,
+            selected: 0,
+            editing: None,
+        }
+    }
+
+    pub fn render(
+        &self,
+        viewport: &crate::file::viewport::FileViewport,
+        frame: &mut ratatui::Frame,
+    ) {
+        use ratatui::style::Stylize;
+
+        let editor_frame = match self.editing {
+            Some(_) => crate::widgets::Editor::new("Apply: Enter", "Cancel: Esc"),
+            None => crate::widgets::Editor::new("Edit: Enter", "Close: Esc"),
+        };
+        let lines = self
+            .rules
+            .iter()
+            .map(|rule| rule.to_string())
+            .chain(std::iter::once(NEW_RULE.to_string()))
+            .collect::<Vec<_>>();
+        let content_width = lines
+            .iter()
+            .map(|line| line.chars().count())
+            .chain(self.editing.iter().map(|buffer| buffer.required_width()))
+            .max()
+            .unwrap_or_default();
+        let (text_rect, contour_rect) = super::popup_areas(
+            viewport,
+            self.cells,
+            &editor_frame,
+            content_width,
+            lines.len(),
+        );
+
+        frame.render_widget(ratatui::widgets::Clear, contour_rect);
+        frame.render_widget(editor_frame, contour_rect);
+
+        for (index, line) in lines.iter().enumerate() {
+            let line_rect = text_rect.intersection(ratatui::layout::Rect::new(
+                text_rect.x,
+                text_rect
+                    .y
+                    .saturating_add(crate::utils::usize_to_u16(index)),
+                text_rect.width,
+                1,
+            ));
+            match (&self.editing, index == self.selected) {
+                (Some(buffer), true) => buffer.render(&[], frame, line_rect),
+                (None, true) => frame.render_widget(
+                    ratatui::widgets::Paragraph::new(line.as_str()).reversed(),
+                    line_rect,
+                ),
+                (_, false) if index == self.rules.len() => frame.render_widget(
+                    ratatui::widgets::Paragraph::new(line.as_str()).dark_gray(),
+                    line_rect,
+                ),
+                (_, false) => {
+                    frame.render_widget(ratatui::widgets::Paragraph::new(line.as_str()), line_rect)
+                }
+            }
+        }
+    }
+
+    fn apply(&self) -> Option<super::StateEventResponse> {
+        Some(super::StateEventResponse {
+            command: R::command(self.rules.clone()),
+            exit: false,
+        })
+    }
+}
+
+impl<R: DialogRule> crate::event::EventHandler for RulesState<R> {
+    type EventResponse = Option<super::StateEventResponse>;
+    fn handle_event(
+        &mut self,
+        event: crossterm::event::Event,
+        info: &mut String,
+    ) -> Self::EventResponse {
+        use crossterm::event::Event;
+        use crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+
+        let redraw = Some(super::StateEventResponse {
+            command: Command::RedrawRequest,
+            exit: false,
+        });
+
+        if let Some(buffer) = &mut self.editing {
+            if buffer.handle_edit_event(&event) {
+                return redraw;
+            }
+            return match event {
+                Event::Key(KeyEvent {
+                    kind: KeyEventKind::Press,
+                    code: KeyCode::Esc,
+                    ..
+                }) => {
+                    self.editing = None;
+                    info.clear();
+                    redraw
+                }
+                Event::Key(KeyEvent {
+                    kind: KeyEventKind::Press,
+                    code: KeyCode::Enter,
+                    ..
+                }) => {
+                    let mut rules = self.rules.clone();
+                    let checked = R::parse(&buffer.string()).and_then(|rule| {
+                        match rules.get_mut(self.selected) {
+                            Some(existing) => *existing = rule,
+                            None => rules.push(rule),
+                        }
+                        R::check(&rules)
+                    });
+                    match checked {
+                        Ok(()) => {
+                            self.rules = rules;
+                            self.editing = None;
+                            self.apply()
+                        }
+                        Err(e) => {
+                            *info = e;
+                            redraw
+                        }
+                    }
+                }
+                _ => None,
+            };
+        }
+
+        match event {
+            Event::Key(KeyEvent {
+                kind: KeyEventKind::Press,
+                code: KeyCode::Esc,
+                ..
+            }) => {
+                info.clear();
+                Some(super::StateEventResponse {
+                    command: Command::RedrawRequest,
+                    exit: true,
+                })
+            }
+            Event::Key(KeyEvent {
+                kind: KeyEventKind::Press,
+                code: KeyCode::Up,
+                ..
+            }) => {
+                self.selected = self.selected.saturating_sub(1);
+                redraw
+            }
+            Event::Key(KeyEvent {
+                kind: KeyEventKind::Press,
+                code: KeyCode::Down,
+                ..
+            }) => {
+                self.selected = (self.selected + 1).min(self.rules.len());
+                redraw
+            }
+            Event::Key(KeyEvent {
+                kind: KeyEventKind::Press,
+                code: KeyCode::Enter,
+                ..
+            }) => {
+                let initial_value = match self.rules.get(self.selected) {
+                    Some(rule) => rule.to_string(),
+                    None => R::template(self.cells),
+                };
+                self.editing = Some(crate::file::input_buffer::InputBuffer::new(&initial_value));
+                *info = R::SYNTAX.to_string();
+                redraw
+            }
+            Event::Key(KeyEvent {
+                kind: KeyEventKind::Press,
+                code: KeyCode::Delete | KeyCode::Backspace,
+                ..
+            }) if self.selected < self.rules.len() => {
+                self.rules.remove(self.selected);
+                self.apply()
+            }
+            _ => None,
+        }
+    }
+}
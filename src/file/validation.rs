@@ -0,0 +1,263 @@
+use crate::file::cell;
+use crate::file::formula::{Evaluator, Expr, Value};
+
+/// Restriction on the values written to the cells of a range.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Validation {
+    pub cells: cell::CellRect,
+    pub criterion: Criterion,
+    /// Whether invalid values are written anyway, with a warning, instead of being rejected.
+    pub warn: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Criterion {
+    /// Numbers within inclusive bounds.
+    Number(f64, f64),
+    /// Whole numbers within inclusive bounds.
+    Integer(f64, f64),
+    /// One of the given texts, case insensitive.
+    List(Vec<String>),
+    /// Dates within inclusive bounds, as serial days.
+    Date(i64, i64),
+    /// Texts whose length in chars is within inclusive bounds.
+    Length(usize, usize),
+    /// Formula written for the top left cell of the range, moved as if copied on the others,
+    /// true for valid values.
+    Formula(Expr),
+}
+
+impl Validation {
+    /// Parse a validation written as `<cells> <criterion> [warn]`, with one of these criteria:
+    /// `number N M`, `integer N M`, `list <text>, <text>...`, `date <date> <date>`,
+    /// `length N M` or `formula =<formula>`. Dates are written as ISO, `2024-03-01`.
+    pub fn parse(source: &str) -> Result<Validation, String> {
+        let source = source.trim();
+        let (cells, rest) = source.split_once(' ').unwrap_or((source, ""));
+        let cells = cell::CellRect::parse(cells)
+            .ok_or(format!("Invalid cells \"{cells}\" in validation"))?;
+
+        let rest = rest.trim();
+        let (rest, warn) = match rest.rsplit_once(' ') {
+            Some((start, last)) if last.eq_ignore_ascii_case("warn") => (start.trim_end(), true),
+            _ => (rest, false),
+        };
+        let (keyword, argument) = rest.split_once(' ').unwrap_or((rest, ""));
+        let argument = argument.trim();
+        let bounds = |keyword: &str| {
+            argument
+                .split_once(' ')
+                .map(|(low, high)| (low.trim(), high.trim()))
+                .ok_or(format!("Expected two bounds after \"{keyword}\""))
+        };
+        let number = |number: &str| {
+            number
+                .parse::<f64>()
+                .map_err(|_| format!("Invalid number \"{number}\" in validation"))
+        };
+        let count = |count: &str| {
+            count
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid length \"{count}\" in validation"))
+        };
+        let date = |date: &str| {
+            crate::locale::Locale::default()
+                .parse_date(date)
+                .ok_or(format!("Invalid date \"{date}\" in validation"))
+        };
+        let criterion = match keyword.to_ascii_lowercase().as_str() {
+            "number" => {
+                let (low, high) = bounds(keyword)?;
+                Criterion::Number(number(low)?, number(high)?)
+            }
+            "integer" => {
+                let (low, high) = bounds(keyword)?;
+                Criterion::Integer(number(low)?, number(high)?)
+            }
+            "list" => {
+                let choices = argument
+                    .split(',')
+                    .map(|choice| choice.trim().to_string())
+                    .filter(|choice| !choice.is_empty())
+                    .collect::<Vec<_>>();
+                if choices.is_empty() {
+                    return Err("Expected values after \"list\"".to_string());
+                }
+                Criterion::List(choices)
+            }
+            "date" => {
+                let (low, high) = bounds(keyword)?;
+                Criterion::Date(date(low)?, date(high)?)
+            }
+            "length" => {
+                let (low, high) = bounds(keyword)?;
+                Criterion::Length(count(low)?, count(high)?)
+            }
+            "formula" => {
+                let source = argument.strip_prefix('=').unwrap_or(argument);
+                match crate::file::formula::Formula::parse(source) {
+                    crate::file::formula::Formula::Expr(expr) => Criterion::Formula(expr),
+                    crate::file::formula::Formula::Invalid { error, .. } => return Err(error),
+                }
+            }
+            "" => return Err("Missing criterion in validation".to_string()),
+            other => return Err(format!("Unknown criterion \"{other}\" in validation")),
+        };
+        Ok(Validation {
+            cells,
+            criterion,
+            warn,
+        })
+    }
+
+    /// Validation following its cells when rows or columns are inserted or deleted.
+    /// None when all its cells are deleted.
+    pub fn shifted(&self, shift: &cell::LineShift) -> Option<Validation> {
+        let criterion = match &self.criterion {
+            Criterion::Formula(expr) => Criterion::Formula(
+                expr.map_references(&|reference, role| reference.shifted(shift, role)),
+            ),
+            other => other.clone(),
+        };
+        Some(Validation {
+            cells: shift.rect(self.cells)?,
+            criterion,
+            warn: self.warn,
+        })
+    }
+
+    /// Validation following a block of cells moved by an offset, when its range is inside the
+    /// block.
+    pub fn moved(&self, from: cell::CellRect, dx: i64, dy: i64) -> Validation {
+        let cells = match from.contains(cell::CellIndex::new(self.cells.x, self.cells.y))
+            && from.contains(cell::CellIndex::new(
+                self.cells.x + self.cells.width - 1,
+                self.cells.y + self.cells.height - 1,
+            )) {
+            true => self.cells.offset(dx, dy).unwrap_or(self.cells),
+            false => self.cells,
+        };
+        let criterion = match &self.criterion {
+            Criterion::Formula(expr) => Criterion::Formula(
+                expr.map_references(&|reference, role| reference.moved(from, dx, dy, role)),
+            ),
+            other => other.clone(),
+        };
+        Validation {
+            cells,
+            criterion,
+            warn: self.warn,
+        }
+    }
+
+    /// Values allowed in the cells, to pick from while editing them.
+    pub fn choices(&self) -> Option<&[String]> {
+        match &self.criterion {
+            Criterion::List(choices) => Some(choices),
+            _ => None,
+        }
+    }
+
+    /// Check the value of the cell at `index`, with the evaluator of the content it is written
+    /// in. Empty cells are always valid. Returns what a valid value should be otherwise.
+    pub fn check(
+        &self,
+        index: cell::CellIndex,
+        value: &Value,
+        evaluator: &Evaluator,
+    ) -> Result<(), String> {
+        if !self.cells.contains(index) || *value == Value::Empty {
+            return Ok(());
+        }
+        let valid = match &self.criterion {
+            Criterion::Number(low, high) => {
+                matches!(value, Value::Number(number) if (*low..=*high).contains(number))
+            }
+            Criterion::Integer(low, high) => matches!(
+                value,
+                Value::Number(number) if number.fract() == 0.0 && (*low..=*high).contains(number)
+            ),
+            Criterion::List(choices) => value.as_text().is_ok_and(|text| {
+                choices
+                    .iter()
+                    .any(|choice| choice.eq_ignore_ascii_case(&text))
+            }),
+            Criterion::Date(low, high) => match value {
+                Value::Date(serial) => (*low..=*high).contains(serial),
+                Value::DateTime(serial) => {
+                    (*low..=*high).contains(&crate::file::format::date::split_serial(*serial).0)
+                }
+                _ => false,
+            },
+            Criterion::Length(low, high) => value
+                .as_text()
+                .is_ok_and(|text| (*low..=*high).contains(&text.chars().count())),
+            Criterion::Formula(expr) => {
+                let dx = index.x as i64 - self.cells.x as i64;
+                let dy = index.y as i64 - self.cells.y as i64;
+                let expr = expr.map_references(&|reference, _| reference.offset(dx, dy));
+                evaluator
+                    .eval(&expr)
+                    .is_ok_and(|result| result.as_bool().unwrap_or(false))
+            }
+        };
+        match valid {
+            true => Ok(()),
+            false => Err(self.expected()),
+        }
+    }
+
+    /// What the valid values are, as shown when rejecting others.
+    fn expected(&self) -> String {
+        let date = |serial: i64| crate::locale::Locale::default().format_date(serial);
+        match &self.criterion {
+            Criterion::Number(low, high) => format!("a number between {low} and {high}"),
+            Criterion::Integer(low, high) => format!("a whole number between {low} and {high}"),
+            Criterion::List(choices) => format!("one of {}", choices.join(", ")),
+            Criterion::Date(low, high) => {
+                format!("a date between {} and {}", date(*low), date(*high))
+            }
+            Criterion::Length(low, high) => {
+                format!("a text of {low} to {high} characters")
+            }
+            Criterion::Formula(expr) => format!("a value for which ={expr} is true"),
+        }
+    }
+}
+
+impl std::fmt::Display for Validation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let date = |serial: i64| crate::locale::Locale::default().format_date(serial);
+        write!(f, "{} ", self.cells)?;
+        match &self.criterion {
+            Criterion::Number(low, high) => write!(f, "number {low} {high}")?,
+            Criterion::Integer(low, high) => write!(f, "integer {low} {high}")?,
+            Criterion::List(choices) => write!(f, "list {}", choices.join(", "))?,
+            Criterion::Date(low, high) => write!(f, "date {} {}", date(*low), date(*high))?,
+            Criterion::Length(low, high) => write!(f, "length {low} {high}")?,
+            Criterion::Formula(expr) => write!(f, "formula ={expr}")?,
+        }
+        if self.warn {
+            write!(f, " warn")?;
+        }
+        Ok(())
+    }
+}
+
+/// Read the validations of the file from its sidecar, with a line for each validation, as written
+/// in the validations dialog. Invalid lines are skipped.
+pub fn read(path: &std::path::Path) -> Vec<Validation> {
+    let text = std::fs::read_to_string(super::hidden_path(path, "validations")).unwrap_or_default();
+    text.lines()
+        .filter_map(|line| Validation::parse(line).ok())
+        .collect()
+}
+
+/// Write the validations of the file next to it, removing the sidecar when there are none.
+pub fn write(path: &std::path::Path, validations: &[Validation]) -> std::io::Result<()> {
+    let text = validations
+        .iter()
+        .map(|validation| format!("{validation}\n"))
+        .collect::<String>();
+    super::write_hidden(path, "validations", &text)
+}
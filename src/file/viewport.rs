@@ -1,12 +1,15 @@
-use std::u64;
-
 use crate::file::cell;
+use crate::file::selection::Selection;
 
 pub struct FileViewport {
     pub cell_size: ratatui::layout::Size,
     pub area: ratatui::layout::Rect,
     pub top_left: cell::CellIndex,
-    pub selection: Option<cell::CellRect>,
+    /// Number of frozen columns (x) and rows (y), pinned to the top left of the grid.
+    pub frozen: cell::CellIndex,
+    pub selection: Option<Selection>,
+    /// Selection dragged with the mouse to be moved: the grabbed cell and where it is now.
+    drag_move: Option<(cell::CellIndex, cell::CellIndex)>,
 }
 
 impl FileViewport {
@@ -16,23 +19,65 @@ impl FileViewport {
             cell_size: ratatui::layout::Size::new(12, 1),
             area,
             top_left: cell::CellIndex::new(0, 0),
+            frozen: cell::CellIndex::new(0, 0),
             selection: None,
+            drag_move: None,
+        }
+    }
+
+    /// Where the selection would be moved if the mouse dragging it was released now.
+    pub fn drag_target(&self) -> Option<cell::CellRect> {
+        let (grab, current) = self.drag_move?;
+        self.selected_cells()?.offset(
+            current.x as i64 - grab.x as i64,
+            current.y as i64 - grab.y as i64,
+        )
+    }
+
+    /// Current range of the selection, the cells commands act on.
+    pub fn selected_cells(&self) -> Option<cell::CellRect> {
+        self.selection.as_ref().map(Selection::cells)
+    }
+
+    /// Select the whole range, active from its top left cell.
+    pub fn select(&mut self, cells: cell::CellRect) {
+        self.selection = Some(Selection::range(cells));
+    }
+
+    /// Stretch a selection of a single merged cell over the whole merge.
+    pub fn fit_merges(&mut self, merges: &[cell::CellRect]) {
+        let Some(selection) = self
+            .selection
+            .as_ref()
+            .filter(|selection| selection.count() == 1)
+        else {
+            return;
+        };
+        if let Some(merge) = merges.iter().find(|merge| merge.contains(selection.anchor)) {
+            self.select(*merge);
         }
     }
 
     /// Whether a given cell is in the viewport selection or not.
     pub fn is_selected(&self, cell: cell::CellIndex) -> bool {
-        if let Some(selection) = self.selection {
+        if let Some(selection) = &self.selection {
             selection.contains(cell)
         } else {
             false
         }
     }
 
+    /// Whether the given cell is the active one of a selection of many cells.
+    pub fn is_active(&self, cell: cell::CellIndex) -> bool {
+        self.selection
+            .as_ref()
+            .is_some_and(|selection| selection.anchor == cell && selection.count() > 1)
+    }
+
     /// Whether the given column is in the selection or not.
     pub fn is_selected_x(&self, cell_x: u64) -> bool {
-        if let Some(selection) = self.selection {
-            selection.x <= cell_x && cell_x < selection.x + selection.width
+        if let Some(selection) = &self.selection {
+            selection.contains_x(cell_x)
         } else {
             false
         }
@@ -40,8 +85,8 @@ impl FileViewport {
 
     /// Whether the given row is in the selection or not.
     pub fn is_selected_y(&self, cell_y: u64) -> bool {
-        if let Some(selection) = self.selection {
-            selection.y <= cell_y && cell_y < selection.y + selection.height
+        if let Some(selection) = &self.selection {
+            selection.contains_y(cell_y)
         } else {
             false
         }
@@ -58,6 +103,54 @@ impl FileViewport {
         )
     }
 
+    /// Number of cells that can be drawn in the grid, horizontally and vertically,
+    /// including the last partially visible ones.
+    fn grid_slots(&self) -> (u64, u64) {
+        let grid = self.grid_area();
+        (
+            u64::from(grid.width.div_ceil(self.cell_size.width)),
+            u64::from(grid.height.div_ceil(self.cell_size.height)),
+        )
+    }
+
+    /// Number of cells that are fully visible in the grid, horizontally and vertically.
+    fn full_grid_slots(&self) -> (u64, u64) {
+        let grid = self.grid_area();
+        (
+            u64::from(grid.width / self.cell_size.width),
+            u64::from(grid.height / self.cell_size.height),
+        )
+    }
+
+    /// Columns drawn on screen, from left to right: the frozen ones, then the scrolled ones.
+    pub fn visible_columns(&self) -> impl Iterator<Item = u64> + use<> {
+        let (slots, _) = self.grid_slots();
+        visible_indices(self.frozen.x, self.top_left.x, slots)
+    }
+
+    /// Rows drawn on screen, from top to bottom: the frozen ones, then the scrolled ones.
+    pub fn visible_rows(&self) -> impl Iterator<Item = u64> + use<> {
+        let (_, slots) = self.grid_slots();
+        visible_indices(self.frozen.y, self.top_left.y, slots)
+    }
+
+    /// Freeze the rows above and the columns left of the given cell, so they stay pinned while scrolling.
+    /// The frozen area is capped so that at least one scrolling row and column remain.
+    pub fn freeze_at(&mut self, cell: cell::CellIndex) {
+        let (columns, rows) = self.full_grid_slots();
+        self.frozen = cell::CellIndex::new(
+            cell.x.min(columns.saturating_sub(1)),
+            cell.y.min(rows.saturating_sub(1)),
+        );
+        self.top_left.x = self.top_left.x.max(self.frozen.x);
+        self.top_left.y = self.top_left.y.max(self.frozen.y);
+    }
+
+    /// Whether some rows or columns are currently frozen.
+    pub fn is_frozen(&self) -> bool {
+        self.frozen.x > 0 || self.frozen.y > 0
+    }
+
     /// Get the cell index from a given screen position.
     /// If the cells or out of the current view, returns None.
     pub fn screen_pos_to_cell_pos(
@@ -68,11 +161,39 @@ impl FileViewport {
         if grid_rect.contains(screen_pos) {
             let grid_x = screen_pos.x.checked_sub(grid_rect.x)?;
             let grid_y = screen_pos.y.checked_sub(grid_rect.y)?;
-            let cell_x = grid_x / self.cell_size.width;
-            let cell_y = grid_y / self.cell_size.height;
+            let slot_x = u64::from(grid_x / self.cell_size.width);
+            let slot_y = u64::from(grid_y / self.cell_size.height);
             Some(cell::CellIndex::new(
-                u64::from(cell_x).saturating_add(self.top_left.x),
-                u64::from(cell_y).saturating_add(self.top_left.y),
+                slot_to_index(slot_x, self.frozen.x, self.top_left.x),
+                slot_to_index(slot_y, self.frozen.y, self.top_left.y),
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Get the row or column of the axis header at a given screen position.
+    /// If the position is not on a header, returns None.
+    pub fn screen_pos_to_header(
+        &self,
+        screen_pos: ratatui::layout::Position,
+    ) -> Option<(cell::Axis, u64)> {
+        let grid_rect = self.grid_area();
+        let in_columns = grid_rect.x <= screen_pos.x && screen_pos.x < grid_rect.right();
+        let in_rows = grid_rect.y <= screen_pos.y && screen_pos.y < grid_rect.bottom();
+        let on_x_axis = self.area.y <= screen_pos.y && screen_pos.y < grid_rect.y;
+        let on_y_axis = self.area.x <= screen_pos.x && screen_pos.x < grid_rect.x;
+        if on_x_axis && in_columns {
+            let slot = u64::from((screen_pos.x - grid_rect.x) / self.cell_size.width);
+            Some((
+                cell::Axis::Columns,
+                slot_to_index(slot, self.frozen.x, self.top_left.x),
+            ))
+        } else if on_y_axis && in_rows {
+            let slot = u64::from((screen_pos.y - grid_rect.y) / self.cell_size.height);
+            Some((
+                cell::Axis::Rows,
+                slot_to_index(slot, self.frozen.y, self.top_left.y),
             ))
         } else {
             None
@@ -80,61 +201,193 @@ impl FileViewport {
     }
 
     /// Get the screen position from the given cell position, constrained to the grid view.
+    /// Cells hidden behind the frozen rows and columns are not part of the resulting rect.
     pub fn cells_pos_to_screen_pos(
         &self,
         cells: crate::file::cell::CellRect,
     ) -> ratatui::layout::Rect {
         use crate::utils::u64_to_u16;
 
-        let start_x = u64_to_u16(
-            cells
-                .x
-                .saturating_add(1)
-                .saturating_sub(self.top_left.x)
-                .saturating_mul(u64::from(self.cell_size.width)),
-        ) + self.area.x;
-        let end_x =
-            start_x.saturating_add(u64_to_u16(cells.width).saturating_mul(self.cell_size.width));
-
-        let start_y = u64_to_u16(
-            cells
-                .y
-                .saturating_add(1)
-                .saturating_sub(self.top_left.y)
-                .saturating_mul(u64::from(self.cell_size.height)),
-        ) + self.area.y;
-        let end_y =
-            start_y.saturating_add(u64_to_u16(cells.height).saturating_mul(self.cell_size.height));
+        let grid_area = self.grid_area();
+        let (start_x, end_x) = span_to_slots(cells.x, cells.width, self.frozen.x, self.top_left.x);
+        let (start_y, end_y) = span_to_slots(cells.y, cells.height, self.frozen.y, self.top_left.y);
 
+        let cell_width = u64::from(self.cell_size.width);
+        let cell_height = u64::from(self.cell_size.height);
         let cells_rect = ratatui::layout::Rect::new(
-            start_x,
-            start_y,
-            end_x.saturating_sub(start_x),
-            end_y.saturating_sub(start_y),
+            grid_area
+                .x
+                .saturating_add(u64_to_u16(start_x.saturating_mul(cell_width))),
+            grid_area
+                .y
+                .saturating_add(u64_to_u16(start_y.saturating_mul(cell_height))),
+            u64_to_u16(end_x.saturating_sub(start_x).saturating_mul(cell_width)),
+            u64_to_u16(end_y.saturating_sub(start_y).saturating_mul(cell_height)),
         );
 
-        cells_rect.intersection(self.grid_area())
+        cells_rect.intersection(grid_area)
+    }
+
+    /// Number of rows crossed by a page move: the fully visible rows below the frozen ones.
+    pub fn page_rows(&self) -> u64 {
+        let (_, rows) = self.full_grid_slots();
+        rows.saturating_sub(self.frozen.y).max(1)
     }
 
-    fn keep_selection_in_view(&mut self) {
-        if let Some(selection) = self.selection {
-            self.top_left.x = self.top_left.x.min(selection.x);
-            self.top_left.x = self.top_left.x.max(
-                selection
-                    .x
-                    .saturating_add(1)
-                    .saturating_add(selection.width)
-                    .saturating_sub(u64::from(self.area.width / self.cell_size.width)),
-            );
-            self.top_left.y = self.top_left.y.min(selection.y);
-            self.top_left.y = self.top_left.y.max(
-                selection
-                    .y
-                    .saturating_add(1)
-                    .saturating_add(selection.height)
-                    .saturating_sub(u64::from(self.area.height)),
-            );
+    /// Scroll the rows by the given number, staying below the frozen ones.
+    pub fn scroll_rows(&mut self, rows: i64) {
+        self.top_left.y = match rows < 0 {
+            true => self.top_left.y.saturating_sub(rows.unsigned_abs()),
+            false => self.top_left.y.saturating_add(rows.unsigned_abs()),
+        }
+        .max(self.frozen.y);
+    }
+
+    /// Scroll to the current range of the selection, or to its cursor when it doesn't fit.
+    pub fn keep_selection_in_view(&mut self) {
+        if let Some(selection) = &self.selection {
+            let (columns, rows) = self.full_grid_slots();
+            let cursor = cell::CellRect::new(selection.cursor.x, selection.cursor.y, 1, 1);
+            for cells in [selection.cells(), cursor] {
+                keep_span_in_view(
+                    cells.x,
+                    cells.width,
+                    self.frozen.x,
+                    &mut self.top_left.x,
+                    columns,
+                );
+                keep_span_in_view(
+                    cells.y,
+                    cells.height,
+                    self.frozen.y,
+                    &mut self.top_left.y,
+                    rows,
+                );
+            }
+        }
+    }
+}
+
+/// Indices drawn on an axis: the frozen ones, followed by the scrolled ones until all slots are filled.
+fn visible_indices(frozen: u64, scroll: u64, slots: u64) -> impl Iterator<Item = u64> {
+    let frozen = frozen.min(slots);
+    (0..frozen).chain(scroll..scroll.saturating_add(slots - frozen))
+}
+
+/// Index on an axis drawn at the given slot.
+fn slot_to_index(slot: u64, frozen: u64, scroll: u64) -> u64 {
+    if slot < frozen {
+        slot
+    } else {
+        scroll.saturating_add(slot - frozen)
+    }
+}
+
+/// Slots range (start included, end excluded) covered on screen by the given span of indices on an axis.
+/// Returns an empty range if the span is entirely hidden behind the frozen area.
+fn span_to_slots(start: u64, len: u64, frozen: u64, scroll: u64) -> (u64, u64) {
+    let end = start.saturating_add(len);
+    let scrolled_start = start.max(scroll);
+    let scrolled_slots = (scrolled_start < end).then(|| {
+        (
+            frozen.saturating_add(scrolled_start - scroll),
+            frozen.saturating_add(end - scroll),
+        )
+    });
+    match (start < frozen, scrolled_slots) {
+        (true, Some((_, scrolled_end))) => (start, scrolled_end),
+        (true, None) => (start, end.min(frozen)),
+        (false, Some(slots)) => slots,
+        (false, None) => (0, 0),
+    }
+}
+
+/// Update the scroll on an axis so that the given span is visible.
+/// Spans entirely in the frozen area are always visible, and the scroll never goes into the frozen area.
+fn keep_span_in_view(start: u64, len: u64, frozen: u64, scroll: &mut u64, slots: u64) {
+    let end = start.saturating_add(len);
+    if end <= frozen {
+        return;
+    }
+    let scroll_slots = slots.saturating_sub(frozen).max(1);
+    *scroll = (*scroll).min(start.max(frozen));
+    *scroll = (*scroll).max(end.saturating_sub(scroll_slots));
+    *scroll = (*scroll).max(frozen);
+}
+
+impl FileViewport {
+    /// Move, grow or shrink the selection as the action asks, or freeze the panes at it.
+    /// Merged cells are single stops, left from their edge.
+    pub fn handle_action(
+        &mut self,
+        action: crate::keymap::Action,
+        merges: &[cell::CellRect],
+        info: &mut String,
+    ) -> Option<super::command::Command> {
+        use crate::keymap::{Action, Direction};
+
+        match action {
+            Action::Deselect => {
+                self.selection.take()?;
+                info.clear();
+                return Some(super::command::Command::RedrawRequest);
+            }
+            Action::FreezePanes => {
+                if self.is_frozen() {
+                    self.frozen = cell::CellIndex::new(0, 0);
+                    *info = "Unfroze panes".to_string();
+                } else {
+                    let selection = self.selected_cells()?;
+                    self.freeze_at(cell::CellIndex::new(selection.x, selection.y));
+                    *info = format!("Froze {} rows and {} columns", self.frozen.y, self.frozen.x);
+                    self.keep_selection_in_view();
+                }
+                return Some(super::command::Command::RedrawRequest);
+            }
+            _ => {}
+        }
+
+        let selection = self.selection.as_mut()?;
+        let cells = selection.cells();
+        let (dx, dy) = match action {
+            Action::Move(direction) | Action::Extend(direction) => match direction {
+                Direction::Up => (0, -1),
+                Direction::Down => (0, 1),
+                Direction::Left => (-1, 0),
+                Direction::Right => (1, 0),
+            },
+            // go to the cells next to a larger selection
+            Action::Next => (i64::try_from(cells.width).unwrap_or(i64::MAX), 0),
+            Action::Edit => (0, i64::try_from(cells.height).unwrap_or(i64::MAX)),
+            _ => return None,
+        };
+        match action {
+            Action::Extend(_) => selection.extend_by(dx, dy),
+            // the range moves as a whole, leaving the other ones
+            _ => {
+                let moved = match merges.contains(&cells) {
+                    true => cell::CellRect::new(cells.x, cells.y, 1, 1).offset(
+                        match dx > 0 {
+                            true => i64::try_from(cells.width).unwrap_or(i64::MAX),
+                            false => dx,
+                        },
+                        match dy > 0 {
+                            true => i64::try_from(cells.height).unwrap_or(i64::MAX),
+                            false => dy,
+                        },
+                    ),
+                    false => cells.offset(dx, dy),
+                };
+                if let Some(moved) = moved {
+                    *selection = Selection::range(moved);
+                }
+            }
         }
+        self.fit_merges(merges);
+        let selection = self.selection.as_ref()?;
+        *info = format!("Selected {selection} ({} cells)", selection.count());
+        self.keep_selection_in_view();
+        Some(super::command::Command::RedrawRequest)
     }
 }
 
@@ -146,174 +399,100 @@ impl crate::event::EventHandler for FileViewport {
         info: &mut String,
     ) -> Self::EventResponse {
         use crossterm::event::Event;
+        use crossterm::event::KeyModifiers;
         use crossterm::event::MouseEventKind;
-        use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
 
         match event {
-            Event::Key(KeyEvent {
-                code: KeyCode::Esc,
-                kind: KeyEventKind::Press,
-                ..
-            }) => match self.selection {
-                Some(_) => {
-                    self.selection = None;
-                    info.clear();
-                    Some(super::command::Command::RedrawRequest)
-                }
-                None => None,
-            },
-            Event::Key(KeyEvent {
-                code: KeyCode::Up,
-                kind: KeyEventKind::Press | KeyEventKind::Repeat,
-                modifiers,
-                ..
-            }) => {
-                if let Some(selection) = &mut self.selection {
-                    let offset = 1 + u64::from(modifiers.contains(KeyModifiers::CONTROL)) * 7;
-                    if modifiers.contains(KeyModifiers::SHIFT) {
-                        selection.height = selection.height.saturating_sub(offset).max(1);
-                    } else {
-                        selection.y = selection.y.saturating_sub(offset);
-                    }
-                    *info = format!("Selected {selection} ({} cells)", selection.count());
-                    self.keep_selection_in_view();
-                    Some(super::command::Command::RedrawRequest)
-                } else {
-                    None
-                }
-            }
-            Event::Key(KeyEvent {
-                code: KeyCode::Down,
-                kind: KeyEventKind::Press | KeyEventKind::Repeat,
-                modifiers,
-                ..
-            }) => {
-                if let Some(selection) = &mut self.selection {
-                    let offset = 1 + u64::from(modifiers.contains(KeyModifiers::CONTROL)) * 7;
-                    if modifiers.contains(KeyModifiers::SHIFT) {
-                        selection.height = selection.height.saturating_add(offset);
-                    } else {
-                        selection.y = selection.y.saturating_add(offset);
-                    }
-                    *info = format!("Selected {selection} ({} cells)", selection.count());
-                    self.keep_selection_in_view();
-                    Some(super::command::Command::RedrawRequest)
-                } else {
+            Event::Mouse(mouse) => match mouse.kind {
+                // pressing in a selection of many cells, or with Alt, grabs it to move it, unless
+                // there are several ranges
+                MouseEventKind::Down(_)
+                    if !mouse
+                        .modifiers
+                        .intersects(KeyModifiers::CONTROL | KeyModifiers::SHIFT)
+                        && self
+                            .selection
+                            .as_ref()
+                            .is_some_and(|selection| selection.others.is_empty())
+                        && self.selected_cells().is_some_and(|selection| {
+                            self.screen_pos_to_cell_pos(ratatui::layout::Position::new(
+                                mouse.column,
+                                mouse.row,
+                            ))
+                            .is_some_and(|cell| {
+                                selection.contains(cell)
+                                    && (selection.count() > 1
+                                        || mouse.modifiers.contains(KeyModifiers::ALT))
+                            })
+                        }) =>
+                {
+                    let mouse_pos = ratatui::layout::Position::new(mouse.column, mouse.row);
+                    let cell = self.screen_pos_to_cell_pos(mouse_pos)?;
+                    self.drag_move = Some((cell, cell));
                     None
                 }
-            }
-            Event::Key(KeyEvent {
-                code: KeyCode::Left,
-                kind: KeyEventKind::Press | KeyEventKind::Repeat,
-                modifiers,
-                ..
-            }) => {
-                if let Some(selection) = &mut self.selection {
-                    let offset = 1 + u64::from(modifiers.contains(KeyModifiers::CONTROL)) * 7;
-                    if modifiers.contains(KeyModifiers::SHIFT) {
-                        selection.width = selection.width.saturating_sub(offset).max(1);
-                    } else {
-                        selection.x = selection.x.saturating_sub(offset);
+                MouseEventKind::Drag(_) if self.drag_move.is_some() => {
+                    let mouse_pos = ratatui::layout::Position::new(mouse.column, mouse.row);
+                    let cell = self.screen_pos_to_cell_pos(mouse_pos)?;
+                    if let Some((_, current)) = &mut self.drag_move {
+                        *current = cell;
                     }
-                    *info = format!("Selected {selection} ({} cells)", selection.count());
-                    self.keep_selection_in_view();
-                    Some(super::command::Command::RedrawRequest)
-                } else {
-                    None
-                }
-            }
-            Event::Key(KeyEvent {
-                code: KeyCode::Right,
-                kind: KeyEventKind::Press | KeyEventKind::Repeat,
-                modifiers,
-                ..
-            }) => {
-                if let Some(selection) = &mut self.selection {
-                    let offset = 1 + u64::from(modifiers.contains(KeyModifiers::CONTROL)) * 7;
-                    if modifiers.contains(KeyModifiers::SHIFT) {
-                        selection.width = selection.width.saturating_add(offset);
-                    } else {
-                        selection.x = selection.x.saturating_add(offset);
+                    if let (Some(selection), Some(target)) =
+                        (self.selected_cells(), self.drag_target())
+                    {
+                        *info = format!("Moving {selection} to {target}");
                     }
-                    *info = format!("Selected {selection} ({} cells)", selection.count());
-                    self.keep_selection_in_view();
-                    Some(super::command::Command::RedrawRequest)
-                } else {
-                    None
-                }
-            }
-            Event::Key(KeyEvent {
-                code: KeyCode::Tab,
-                kind: KeyEventKind::Press | KeyEventKind::Repeat,
-                ..
-            }) => {
-                if let Some(selection) = &mut self.selection {
-                    selection.x = selection.x.saturating_add(selection.width);
-                    *info = format!("Selected {selection} ({} cells)", selection.count());
-                    self.keep_selection_in_view();
                     Some(super::command::Command::RedrawRequest)
-                } else {
-                    None
-                }
-            }
-            Event::Key(KeyEvent {
-                code: KeyCode::Enter,
-                kind: KeyEventKind::Press | KeyEventKind::Repeat,
-                ..
-            }) => {
-                if let Some(selection) = &mut self.selection {
-                    selection.y = selection.y.saturating_add(selection.height);
-                    *info = format!("Selected {selection} ({} cells)", selection.count());
-                    self.keep_selection_in_view();
-                    Some(super::command::Command::RedrawRequest)
-                } else {
-                    None
                 }
-            }
-            Event::Mouse(mouse) => match mouse.kind {
-                MouseEventKind::Down(_) => {
-                    let mouse_pos = ratatui::layout::Position::new(mouse.column, mouse.row);
-                    match self.screen_pos_to_cell_pos(mouse_pos) {
-                        Some(cell) => {
-                            let selection = cell::CellRect::new(cell.x, cell.y, 1, 1);
+                MouseEventKind::Up(_) if self.drag_move.is_some() => {
+                    let target = self.drag_target();
+                    let (grab, _) = self.drag_move.take()?;
+                    match (self.selected_cells(), target) {
+                        (Some(from), Some(target)) if from != target => {
+                            Some(super::command::Command::MoveCells {
+                                from,
+                                to: cell::CellIndex::new(target.x, target.y),
+                            })
+                        }
+                        // released without moving: select the grabbed cell only
+                        _ => {
+                            let selection = Selection::cell(grab);
                             *info = format!("Selected {selection} ({} cells)", selection.count());
                             self.selection = Some(selection);
                             Some(super::command::Command::RedrawRequest)
                         }
-                        None => None,
                     }
                 }
-                MouseEventKind::Drag(_) => {
+                // Shift stretches the selection to the cell, Ctrl adds a range
+                MouseEventKind::Down(_) => {
                     let mouse_pos = ratatui::layout::Position::new(mouse.column, mouse.row);
-                    match self.screen_pos_to_cell_pos(mouse_pos) {
-                        Some(cell) => {
-                            match &mut self.selection {
-                                Some(selection) => {
-                                    selection.width =
-                                        cell.x.saturating_add(1).saturating_sub(selection.x).max(1);
-                                    selection.height =
-                                        cell.y.saturating_add(1).saturating_sub(selection.y).max(1);
-                                    *info = format!(
-                                        "Selected {selection} ({} cells)",
-                                        selection.count()
-                                    );
-                                }
-                                None => {
-                                    let selection = cell::CellRect::new(cell.x, cell.y, 1, 1);
-                                    *info = format!(
-                                        "Selected {selection} ({} cells)",
-                                        selection.count()
-                                    );
-                                    self.selection = Some(selection);
-                                }
-                            }
-                            Some(super::command::Command::RedrawRequest)
+                    let cell = self.screen_pos_to_cell_pos(mouse_pos)?;
+                    let selection = match self.selection.take() {
+                        Some(mut selection) if mouse.modifiers.contains(KeyModifiers::SHIFT) => {
+                            selection.cursor = cell;
+                            selection
                         }
-                        None => None,
-                    }
+                        Some(mut selection) if mouse.modifiers.contains(KeyModifiers::CONTROL) => {
+                            selection.add(cell, cell);
+                            selection
+                        }
+                        _ => Selection::cell(cell),
+                    };
+                    *info = format!("Selected {selection} ({} cells)", selection.count());
+                    self.selection = Some(selection);
+                    Some(super::command::Command::RedrawRequest)
+                }
+                // the cursor follows the mouse, on either side of the active cell
+                MouseEventKind::Drag(_) => {
+                    let mouse_pos = ratatui::layout::Position::new(mouse.column, mouse.row);
+                    let cell = self.screen_pos_to_cell_pos(mouse_pos)?;
+                    let selection = self.selection.get_or_insert_with(|| Selection::cell(cell));
+                    selection.cursor = cell;
+                    *info = format!("Selected {selection} ({} cells)", selection.count());
+                    Some(super::command::Command::RedrawRequest)
                 }
                 MouseEventKind::ScrollUp => {
-                    self.top_left.y = self.top_left.y.saturating_sub(1);
+                    self.top_left.y = self.top_left.y.saturating_sub(1).max(self.frozen.y);
                     Some(super::command::Command::RedrawRequest)
                 }
                 MouseEventKind::ScrollDown => {
@@ -321,7 +500,7 @@ impl crate::event::EventHandler for FileViewport {
                     Some(super::command::Command::RedrawRequest)
                 }
                 MouseEventKind::ScrollLeft => {
-                    self.top_left.x = self.top_left.x.saturating_sub(1);
+                    self.top_left.x = self.top_left.x.saturating_sub(1).max(self.frozen.x);
                     Some(super::command::Command::RedrawRequest)
                 }
                 MouseEventKind::ScrollRight => {
@@ -0,0 +1,256 @@
+use crate::file::cell::{CellIndex, CellRect};
+use crate::file::command::Command;
+use crate::file::selection::Selection;
+use crate::file::state;
+
+/// Most times a command is repeated by its count, so a mistyped count can't queue billions.
+const MAX_REPEAT: u64 = 10_000;
+
+/// Vim-style modal layer over the sheet, enabled from the config file. Typed characters are
+/// commands, as `5j` or `dd`, instead of starting to edit the cells: `i` and `a` do it.
+/// The other bindings keep working.
+#[derive(Default)]
+pub struct Vim {
+    /// Keys of the command typed so far, as `5` or `d`.
+    pending: String,
+    /// Visual mode, where motions move the cursor of the selection instead of the selection.
+    visual: bool,
+    /// Last searched text, searched again by `n` and `N`.
+    pub search: Option<String>,
+    /// Last change made, repeated on the selection by `.`.
+    pub last_change: Option<Command>,
+}
+
+impl Vim {
+    /// Forget the pending keys and leave visual mode. Returns whether there was any.
+    pub fn reset(&mut self) -> bool {
+        let active = !self.pending.is_empty() || self.visual;
+        self.pending.clear();
+        self.visual = false;
+        active
+    }
+}
+
+/// Where a move goes, from the selection or from its cursor in visual mode.
+enum Motion {
+    By(i64, i64),
+    Row(u64),
+    Column(u64),
+    /// Last row holding cells.
+    LastRow,
+    /// Last cell of the row.
+    LastColumn,
+}
+
+impl super::FileApp {
+    /// Handle a character typed in vim mode, once the command it ends is complete.
+    pub(super) fn handle_vim_key(&mut self, key: char, info: &mut String) -> Option<Command> {
+        let vim = self.vim.as_mut()?;
+        vim.pending.push(key);
+        let pending = vim.pending.clone();
+        let visual = vim.visual;
+
+        let digits = pending
+            .find(|ch: char| !ch.is_ascii_digit())
+            .unwrap_or(pending.len());
+        let (count, keys) = match pending.split_at(digits) {
+            // `0` goes to the first column, it only counts after other digits
+            ("0", "") => (None, "0"),
+            (digits, keys) => (digits.parse::<u64>().ok(), keys),
+        };
+        let times = count.unwrap_or(1);
+        let repeat = times.min(MAX_REPEAT);
+        let distance = i64::try_from(times).unwrap_or(i64::MAX);
+        let top_left = self.viewport.top_left;
+        let selection = self
+            .viewport
+            .selected_cells()
+            .unwrap_or(CellRect::new(top_left.x, top_left.y, 1, 1));
+        // lines past the last written or formatted row are empty, `dd` and `yy` stop at it
+        let last_row = [
+            self.content.keys().next_back(),
+            self.formats.keys().next_back(),
+            self.notes.keys().next_back(),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|index| index.y)
+        .max()
+        .unwrap_or(0);
+        let lines = count
+            .unwrap_or(selection.height)
+            .min(last_row.saturating_sub(selection.y).saturating_add(1))
+            .max(1);
+
+        let mut commands = Vec::new();
+        match (keys, visual) {
+            // wait for the rest of the command
+            ("" | "g", _) | ("d" | "y", false) => {
+                *info = pending;
+                return Some(Command::RedrawRequest);
+            }
+            ("h", _) => self.vim_motion(Motion::By(-distance, 0), info),
+            ("j", _) => self.vim_motion(Motion::By(0, distance), info),
+            ("k", _) => self.vim_motion(Motion::By(0, -distance), info),
+            ("l", _) => self.vim_motion(Motion::By(distance, 0), info),
+            ("gg", _) => self.vim_motion(Motion::Row(times - 1), info),
+            ("G", _) => match count {
+                Some(row) => self.vim_motion(Motion::Row(row - 1), info),
+                None => self.vim_motion(Motion::LastRow, info),
+            },
+            ("0", _) => self.vim_motion(Motion::Column(0), info),
+            ("$", _) => self.vim_motion(Motion::LastColumn, info),
+            ("v", false) => {
+                let anchor = self
+                    .viewport
+                    .selection
+                    .as_ref()
+                    .map_or(top_left, |selection| selection.anchor);
+                if let Some(vim) = &mut self.vim {
+                    vim.visual = true;
+                }
+                self.viewport.selection = Some(Selection::cell(anchor));
+                *info = "-- VISUAL --".to_string();
+            }
+            ("v", true) => info.clear(),
+            ("dd", false) => {
+                // the deleted rows are yanked, to be put back by `p`
+                let width = self
+                    .content
+                    .keys()
+                    .chain(self.formats.keys())
+                    .map(|index| index.x + 1)
+                    .max()
+                    .unwrap_or(1);
+                commands.push(Command::CopyCells {
+                    cells: CellRect::new(0, selection.y, width, lines),
+                });
+                commands.push(Command::ShiftLines {
+                    shift: crate::file::cell::LineShift {
+                        axis: crate::file::cell::Axis::Rows,
+                        at: selection.y,
+                        count: lines,
+                        inserted: false,
+                    },
+                });
+            }
+            ("d" | "x", true) | ("x", false) => commands.push(Command::DeleteCells {
+                cells: selection,
+                next_selection: crate::file::command::SelectionDirection::Stay,
+            }),
+            ("yy", false) => commands.push(Command::CopyCells {
+                cells: CellRect::new(selection.x, selection.y, selection.width, lines),
+            }),
+            ("y", true) => commands.push(Command::CopyCells { cells: selection }),
+            ("p" | "P", _) => commands.push(Command::PasteClipboard {
+                cells: selection,
+                options: crate::file::clipboard::PasteOptions::default(),
+            }),
+            ("u", _) => {
+                let undos = repeat.min(self.changes.undo_count() as u64).max(1);
+                commands.extend((0..undos).map(|_| Command::Undo))
+            }
+            (".", _) => match self.vim.as_ref().and_then(|vim| vim.last_change.as_ref()) {
+                Some(change) => {
+                    let change = change.retargeted(selection);
+                    commands.extend((0..repeat).map(|_| change.clone()));
+                }
+                None => *info = "No change to repeat".to_string(),
+            },
+            ("i" | "a", _) => {
+                let cells = CellRect::new(selection.x, selection.y, 1, 1);
+                let content = self
+                    .content
+                    .get(&CellIndex::new(cells.x, cells.y))
+                    .map(|cell| cell.to_string())
+                    .unwrap_or_default();
+                commands.push(self.start_editing(cells, &content, info));
+            }
+            (":", _) => {
+                self.state = state::State::Prompt(state::PromptState::new(
+                    state::PromptKind::VimCommand { cells: selection },
+                    "",
+                ));
+                info.clear();
+            }
+            ("/", _) => {
+                self.state = state::State::Prompt(state::PromptState::new(
+                    state::PromptKind::Search { cells: selection },
+                    "",
+                ));
+                info.clear();
+            }
+            ("n" | "N", _) => match self.vim.as_ref().and_then(|vim| vim.search.clone()) {
+                Some(text) => commands.extend((0..repeat).map(|_| Command::Search {
+                    text: text.clone(),
+                    backwards: keys == "N",
+                })),
+                None => *info = "No previous search".to_string(),
+            },
+            _ => *info = format!("Unknown command {pending}"),
+        }
+
+        if let Some(vim) = &mut self.vim {
+            vim.pending.clear();
+            // a command ends visual mode, as do `v` and Esc
+            if !commands.is_empty() || (keys == "v" && visual) {
+                vim.visual = false;
+            }
+        }
+        for command in commands {
+            self.execute_command(command, info);
+        }
+        Some(Command::RedrawRequest)
+    }
+
+    /// Move the selection, or its cursor in visual mode.
+    fn vim_motion(&mut self, motion: Motion, info: &mut String) {
+        let top_left = self.viewport.top_left;
+        let mut selection = self
+            .viewport
+            .selection
+            .take()
+            .unwrap_or(Selection::cell(top_left));
+        let visual = self.vim.as_ref().is_some_and(|vim| vim.visual);
+        let cells = selection.cells();
+        let from = match visual {
+            true => selection.cursor,
+            false => CellIndex::new(cells.x, cells.y),
+        };
+        let offset = |position: u64, distance: i64| match distance < 0 {
+            true => position.saturating_sub(distance.unsigned_abs()),
+            false => position.saturating_add(distance.unsigned_abs()),
+        };
+        // a merged cell is a single stop, left from its far edge for a single cell
+        let merged = !visual && self.merges.contains(&cells);
+        let past = |distance: i64, size: u64| match merged && distance > 0 {
+            true => distance.saturating_add(i64::try_from(size - 1).unwrap_or(i64::MAX)),
+            false => distance,
+        };
+        let (width, height) = match merged {
+            true => (1, 1),
+            false => (cells.width, cells.height),
+        };
+        let to = match motion {
+            Motion::By(dx, dy) => CellIndex::new(
+                offset(from.x, past(dx, cells.width)),
+                offset(from.y, past(dy, cells.height)),
+            ),
+            Motion::Row(row) => CellIndex::new(from.x, row),
+            Motion::Column(column) => CellIndex::new(column, from.y),
+            Motion::LastRow => CellIndex::new(
+                from.x,
+                self.content.keys().map(|index| index.y).max().unwrap_or(0),
+            ),
+            Motion::LastColumn => self.target_cell(crate::keymap::Target::RowEnd, from),
+        };
+
+        match visual {
+            true => selection.cursor = to,
+            false => selection = Selection::range(CellRect::new(to.x, to.y, width, height)),
+        }
+        *info = format!("Selected {selection} ({} cells)", selection.count());
+        self.viewport.selection = Some(selection);
+        self.viewport.keep_selection_in_view();
+    }
+}
@@ -0,0 +1,533 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+
+/// Where a binding applies. Actions of different contexts may share keys, except global ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Context {
+    /// Everywhere, before any other binding.
+    Global,
+    /// Browsing the sheet, no editor being open.
+    Sheet,
+    /// Writing in a cell.
+    Editing,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// Cell reached by a jump, from the selection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Target {
+    /// End of the block of written cells in the direction, or the next written cell after a gap.
+    Edge(Direction),
+    RowStart,
+    /// Last written cell of the row.
+    RowEnd,
+    /// A1.
+    SheetStart,
+    /// Last used row and column.
+    SheetEnd,
+    /// As many rows as the screen shows, up or down.
+    PageUp,
+    PageDown,
+}
+
+/// Something a key can be bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    Save,
+    SaveAs,
+    ExportSelection,
+    Copy,
+    Cut,
+    Paste,
+    PasteSpecial,
+    FillDown,
+    FillRight,
+    FillSeriesDown,
+    FillSeriesRight,
+    Undo,
+    Redo,
+    History,
+    InsertRows,
+    DeleteRows,
+    InsertColumns,
+    DeleteColumns,
+    ClearFormat,
+    Bold,
+    Italic,
+    Underline,
+    AlignLeft,
+    AlignCenter,
+    AlignRight,
+    TextColor,
+    FillColor,
+    Borders,
+    NumberFormat,
+    ConditionalFormatting,
+    /// Restrict the values that can be written in cells.
+    DataValidation,
+    /// Add, edit or delete the note of the selected cell.
+    EditNote,
+    /// Merge the selected cells into one, or split the merged cells in the selection.
+    MergeCells,
+    /// Create, rename or delete the names of cells and constants used in formulas.
+    NameManager,
+    /// Select the cells, or the cells of a name, typed in a prompt.
+    GoToCells,
+    FreezePanes,
+    /// Edit the selected cell, or go to the cells under a larger selection.
+    Edit,
+    /// Go to the cells right of the selection.
+    Next,
+    ClearCells,
+    Deselect,
+    /// Move the selection by one cell.
+    Move(Direction),
+    /// Grow or shrink the selection by one cell.
+    Extend(Direction),
+    /// Select the target cell alone.
+    GoTo(Target),
+    /// Stretch the selection from its active cell to the target cell.
+    ExtendTo(Target),
+    /// Write the cells and go to the ones below.
+    Confirm,
+    /// Write the cells and go to the ones on the right.
+    ConfirmNext,
+    Cancel,
+    /// Start a new line in the edited cells.
+    Newline,
+}
+
+/// Every action, with its name in the config file and its default keys.
+const ACTIONS: &[(Action, &str, &[&str])] = &[
+    (Action::Quit, "quit", &["ctrl+q"]),
+    (Action::Save, "save", &["ctrl+s"]),
+    (Action::SaveAs, "save-as", &["ctrl+alt+s"]),
+    (Action::ExportSelection, "export-selection", &["ctrl+e"]),
+    (Action::Copy, "copy", &["ctrl+c"]),
+    (Action::Cut, "cut", &["ctrl+x"]),
+    (Action::Paste, "paste", &["ctrl+v"]),
+    (Action::PasteSpecial, "paste-special", &["alt+v"]),
+    (Action::FillDown, "fill-down", &["ctrl+d"]),
+    (Action::FillRight, "fill-right", &["ctrl+r"]),
+    (Action::FillSeriesDown, "fill-series-down", &["ctrl+alt+d"]),
+    (
+        Action::FillSeriesRight,
+        "fill-series-right",
+        &["ctrl+alt+r"],
+    ),
+    (Action::Undo, "undo", &["ctrl+z"]),
+    (Action::Redo, "redo", &["ctrl+y"]),
+    (Action::History, "history", &["alt+h"]),
+    (Action::InsertRows, "insert-rows", &["ctrl+insert"]),
+    (Action::DeleteRows, "delete-rows", &["ctrl+delete"]),
+    (Action::InsertColumns, "insert-columns", &["alt+insert"]),
+    (Action::DeleteColumns, "delete-columns", &["alt+delete"]),
+    (Action::ClearFormat, "clear-format", &["alt+backspace"]),
+    (Action::Bold, "bold", &["alt+b"]),
+    (Action::Italic, "italic", &["alt+i"]),
+    (Action::Underline, "underline", &["alt+u"]),
+    (Action::AlignLeft, "align-left", &["alt+l"]),
+    (Action::AlignCenter, "align-center", &["alt+e"]),
+    (Action::AlignRight, "align-right", &["alt+r"]),
+    (Action::TextColor, "text-color", &["alt+c"]),
+    (Action::FillColor, "fill-color", &["alt+g"]),
+    (Action::Borders, "borders", &["alt+o"]),
+    (Action::NumberFormat, "number-format", &["alt+n"]),
+    (
+        Action::ConditionalFormatting,
+        "conditional-formatting",
+        &["alt+k"],
+    ),
+    (Action::DataValidation, "data-validation", &["alt+d"]),
+    (Action::EditNote, "edit-note", &["shift+f2"]),
+    (Action::MergeCells, "merge-cells", &["alt+m"]),
+    (Action::NameManager, "name-manager", &["ctrl+f3"]),
+    (Action::GoToCells, "go-to-cells", &["ctrl+g"]),
+    (Action::FreezePanes, "freeze-panes", &["alt+f"]),
+    (Action::Edit, "edit", &["enter"]),
+    (Action::Next, "next", &["tab"]),
+    (Action::ClearCells, "clear-cells", &["delete", "backspace"]),
+    (Action::Deselect, "deselect", &["esc"]),
+    (Action::Move(Direction::Up), "move-up", &["up"]),
+    (Action::Move(Direction::Down), "move-down", &["down"]),
+    (Action::Move(Direction::Left), "move-left", &["left"]),
+    (Action::Move(Direction::Right), "move-right", &["right"]),
+    (Action::Extend(Direction::Up), "extend-up", &["shift+up"]),
+    (
+        Action::Extend(Direction::Down),
+        "extend-down",
+        &["shift+down"],
+    ),
+    (
+        Action::Extend(Direction::Left),
+        "extend-left",
+        &["shift+left"],
+    ),
+    (
+        Action::Extend(Direction::Right),
+        "extend-right",
+        &["shift+right"],
+    ),
+    (
+        Action::GoTo(Target::Edge(Direction::Up)),
+        "jump-up",
+        &["ctrl+up"],
+    ),
+    (
+        Action::GoTo(Target::Edge(Direction::Down)),
+        "jump-down",
+        &["ctrl+down"],
+    ),
+    (
+        Action::GoTo(Target::Edge(Direction::Left)),
+        "jump-left",
+        &["ctrl+left"],
+    ),
+    (
+        Action::GoTo(Target::Edge(Direction::Right)),
+        "jump-right",
+        &["ctrl+right"],
+    ),
+    (
+        Action::ExtendTo(Target::Edge(Direction::Up)),
+        "extend-jump-up",
+        &["ctrl+shift+up"],
+    ),
+    (
+        Action::ExtendTo(Target::Edge(Direction::Down)),
+        "extend-jump-down",
+        &["ctrl+shift+down"],
+    ),
+    (
+        Action::ExtendTo(Target::Edge(Direction::Left)),
+        "extend-jump-left",
+        &["ctrl+shift+left"],
+    ),
+    (
+        Action::ExtendTo(Target::Edge(Direction::Right)),
+        "extend-jump-right",
+        &["ctrl+shift+right"],
+    ),
+    (Action::GoTo(Target::RowStart), "row-start", &["home"]),
+    (
+        Action::ExtendTo(Target::RowStart),
+        "extend-row-start",
+        &["shift+home"],
+    ),
+    (Action::GoTo(Target::RowEnd), "row-end", &["end"]),
+    (
+        Action::ExtendTo(Target::RowEnd),
+        "extend-row-end",
+        &["shift+end"],
+    ),
+    (
+        Action::GoTo(Target::SheetStart),
+        "sheet-start",
+        &["ctrl+home"],
+    ),
+    (
+        Action::ExtendTo(Target::SheetStart),
+        "extend-sheet-start",
+        &["ctrl+shift+home"],
+    ),
+    (Action::GoTo(Target::SheetEnd), "sheet-end", &["ctrl+end"]),
+    (
+        Action::ExtendTo(Target::SheetEnd),
+        "extend-sheet-end",
+        &["ctrl+shift+end"],
+    ),
+    (Action::GoTo(Target::PageUp), "page-up", &["pageup"]),
+    (
+        Action::ExtendTo(Target::PageUp),
+        "extend-page-up",
+        &["shift+pageup"],
+    ),
+    (Action::GoTo(Target::PageDown), "page-down", &["pagedown"]),
+    (
+        Action::ExtendTo(Target::PageDown),
+        "extend-page-down",
+        &["shift+pagedown"],
+    ),
+    (Action::Confirm, "confirm", &["enter"]),
+    (Action::ConfirmNext, "confirm-next", &["tab"]),
+    (Action::Cancel, "cancel", &["esc"]),
+    (Action::Newline, "newline", &["alt+enter"]),
+];
+
+impl Action {
+    pub fn context(self) -> Context {
+        match self {
+            Action::Quit => Context::Global,
+            Action::Confirm | Action::ConfirmNext | Action::Cancel | Action::Newline => {
+                Context::Editing
+            }
+            _ => Context::Sheet,
+        }
+    }
+
+    /// Whether holding the key repeats the action.
+    fn repeats(self) -> bool {
+        matches!(
+            self,
+            Action::Move(_)
+                | Action::Extend(_)
+                | Action::GoTo(_)
+                | Action::ExtendTo(_)
+                | Action::Edit
+                | Action::Next
+        )
+    }
+
+    pub fn name(self) -> &'static str {
+        ACTIONS
+            .iter()
+            .find(|(action, _, _)| *action == self)
+            .map_or("", |(_, name, _)| name)
+    }
+
+    fn from_name(name: &str) -> Option<Action> {
+        ACTIONS
+            .iter()
+            .find(|(_, other, _)| *other == name)
+            .map(|(action, _, _)| *action)
+    }
+}
+
+/// A key with its modifiers, written as `ctrl+shift+z` in the config file.
+/// Letters typed with Shift are stored lowercase with the Shift modifier, a lone `G` being
+/// `shift+g`, and Shift is ignored for other characters, as terminals disagree on how they
+/// report them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> KeyChord {
+        let modifiers =
+            modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        match code {
+            KeyCode::Char(ch) if ch.is_uppercase() => KeyChord {
+                code: KeyCode::Char(ch.to_lowercase().next().unwrap_or(ch)),
+                modifiers: modifiers | KeyModifiers::SHIFT,
+            },
+            KeyCode::Char(ch) if !ch.is_alphabetic() => KeyChord {
+                code,
+                modifiers: modifiers - KeyModifiers::SHIFT,
+            },
+            KeyCode::BackTab => KeyChord {
+                code,
+                modifiers: modifiers - KeyModifiers::SHIFT,
+            },
+            _ => KeyChord { code, modifiers },
+        }
+    }
+
+    pub fn from_event(event: &KeyEvent) -> KeyChord {
+        KeyChord::new(event.code, event.modifiers)
+    }
+
+    pub fn parse(text: &str) -> Result<KeyChord, String> {
+        let text = text.trim();
+        // the plus key itself, as in `ctrl++`
+        let (modifiers, key) = match text.strip_suffix("++") {
+            Some(modifiers) => (modifiers, "+"),
+            None => text.rsplit_once('+').unwrap_or(("", text)),
+        };
+        let mut chord_modifiers = KeyModifiers::NONE;
+        for modifier in modifiers.split('+').filter(|modifier| !modifier.is_empty()) {
+            chord_modifiers |= match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" | "meta" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(format!("Unknown modifier \"{modifier}\" in \"{text}\"")),
+            };
+        }
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            // `Ctrl+Y` is written the same as `ctrl+y`
+            (Some(ch), None) if !modifiers.is_empty() => {
+                KeyCode::Char(ch.to_lowercase().next().unwrap_or(ch))
+            }
+            (Some(ch), None) => KeyCode::Char(ch),
+            _ => match key.to_ascii_lowercase().as_str() {
+                "enter" | "return" => KeyCode::Enter,
+                "esc" | "escape" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" | "ins" => KeyCode::Insert,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                "space" => KeyCode::Char(' '),
+                name => match name
+                    .strip_prefix('f')
+                    .and_then(|number| number.parse().ok())
+                {
+                    Some(number @ 1..=24) => KeyCode::F(number),
+                    _ => return Err(format!("Unknown key \"{key}\" in \"{text}\"")),
+                },
+            },
+        };
+        Ok(KeyChord::new(code, chord_modifiers))
+    }
+}
+
+impl std::fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (modifier, name) in [
+            (KeyModifiers::CONTROL, "ctrl+"),
+            (KeyModifiers::ALT, "alt+"),
+            (KeyModifiers::SHIFT, "shift+"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{name}")?;
+            }
+        }
+        match self.code {
+            KeyCode::Enter => write!(f, "enter"),
+            KeyCode::Esc => write!(f, "esc"),
+            KeyCode::Tab => write!(f, "tab"),
+            KeyCode::BackTab => write!(f, "backtab"),
+            KeyCode::Backspace => write!(f, "backspace"),
+            KeyCode::Delete => write!(f, "delete"),
+            KeyCode::Insert => write!(f, "insert"),
+            KeyCode::Up => write!(f, "up"),
+            KeyCode::Down => write!(f, "down"),
+            KeyCode::Left => write!(f, "left"),
+            KeyCode::Right => write!(f, "right"),
+            KeyCode::Home => write!(f, "home"),
+            KeyCode::End => write!(f, "end"),
+            KeyCode::PageUp => write!(f, "pageup"),
+            KeyCode::PageDown => write!(f, "pagedown"),
+            KeyCode::Char(' ') => write!(f, "space"),
+            KeyCode::Char(ch) => write!(f, "{ch}"),
+            KeyCode::F(number) => write!(f, "f{number}"),
+            other => write!(f, "{other:?}"),
+        }
+    }
+}
+
+/// Keys bound to every action, the defaults replaced by the ones of the config file.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: std::collections::HashMap<(Context, KeyChord), Action>,
+    /// Keys of each action, in the order they were given, to show them.
+    chords: std::collections::HashMap<Action, Vec<KeyChord>>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        // the default keys are valid and don't conflict
+        Keymap::with_bindings(&[]).unwrap()
+    }
+}
+
+impl Keymap {
+    /// Bind the actions named in the config file to their keys, the other actions keeping
+    /// their default keys. An empty list of keys unbinds an action.
+    /// Fails on unknown names and keys, and on keys bound to many actions at once.
+    pub fn with_bindings(bindings: &[(String, Vec<String>)]) -> Result<Keymap, String> {
+        let mut chords = ACTIONS
+            .iter()
+            .map(|(action, _, defaults)| {
+                let defaults = defaults
+                    .iter()
+                    .map(|chord| KeyChord::parse(chord))
+                    .collect::<Result<Vec<_>, _>>();
+                defaults.map(|defaults| (*action, defaults))
+            })
+            .collect::<Result<std::collections::HashMap<_, _>, _>>()?;
+        for (name, keys) in bindings {
+            let action =
+                Action::from_name(name).ok_or_else(|| format!("Unknown action \"{name}\""))?;
+            let keys = keys
+                .iter()
+                .map(|key| KeyChord::parse(key))
+                .collect::<Result<Vec<_>, _>>()?;
+            chords.insert(action, keys);
+        }
+
+        let mut keymap = Keymap {
+            bindings: std::collections::HashMap::new(),
+            chords,
+        };
+        let mut conflicts = Vec::new();
+        for (action, _, _) in ACTIONS {
+            for chord in &keymap.chords[action] {
+                let context = action.context();
+                let other = match context {
+                    // global keys are caught before the ones of any other context
+                    Context::Global => keymap
+                        .bindings
+                        .iter()
+                        .find(|((_, other_chord), _)| other_chord == chord)
+                        .map(|(_, other)| *other),
+                    _ => keymap
+                        .bindings
+                        .get(&(context, *chord))
+                        .or_else(|| keymap.bindings.get(&(Context::Global, *chord)))
+                        .copied(),
+                };
+                match other {
+                    Some(other) if other != *action => conflicts.push(format!(
+                        "{chord} is bound to both {} and {}",
+                        other.name(),
+                        action.name()
+                    )),
+                    _ => {
+                        keymap.bindings.insert((context, *chord), *action);
+                    }
+                }
+            }
+        }
+        match conflicts.is_empty() {
+            true => Ok(keymap),
+            false => Err(conflicts.join(", ")),
+        }
+    }
+
+    /// Action bound to the pressed key in the context, or in the global one.
+    pub fn action(&self, context: Context, event: &KeyEvent) -> Option<Action> {
+        let chord = KeyChord::from_event(event);
+        let action = self
+            .bindings
+            .get(&(Context::Global, chord))
+            .or_else(|| self.bindings.get(&(context, chord)))
+            .copied()?;
+        match event.kind {
+            KeyEventKind::Press => Some(action),
+            KeyEventKind::Repeat if action.repeats() => Some(action),
+            _ => None,
+        }
+    }
+
+    /// Keys of the action, as shown to the user, as `enter/tab`.
+    pub fn keys(&self, action: Action) -> String {
+        self.chords
+            .get(&action)
+            .map(|chords| {
+                chords
+                    .iter()
+                    .map(|chord| chord.to_string())
+                    .collect::<Vec<_>>()
+                    .join("/")
+            })
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,320 @@
+use crate::file::format::date;
+
+/// In which order the parts of a date are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateOrder {
+    DayMonthYear,
+    MonthDayYear,
+    YearMonthDay,
+}
+
+/// Regional settings, used to detect typed values in the cells input and to display them.
+/// ISO dates (`2024-03-01`) and english booleans (`TRUE`, `FALSE`) are accepted with any locale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Locale {
+    pub date_order: DateOrder,
+    pub true_word: &'static str,
+    pub false_word: &'static str,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Locale {
+            date_order: DateOrder::YearMonthDay,
+            true_word: "TRUE",
+            false_word: "FALSE",
+        }
+    }
+}
+
+impl Locale {
+    /// Find a locale from its name, such as `en-US`, `fr_FR.UTF-8`, `de` or `iso`.
+    pub fn from_name(name: &str) -> Option<Locale> {
+        let name = name.split('.').next().unwrap_or(name).replace('_', "-");
+        let (language, region) = name.split_once('-').unwrap_or((&name, ""));
+        let locale = match (
+            language.to_ascii_lowercase().as_str(),
+            region.to_ascii_uppercase().as_str(),
+        ) {
+            ("iso" | "c" | "posix", _) => Locale::default(),
+            ("en", "US" | "") => Locale {
+                date_order: DateOrder::MonthDayYear,
+                ..Locale::default()
+            },
+            ("en", _) => Locale {
+                date_order: DateOrder::DayMonthYear,
+                ..Locale::default()
+            },
+            ("fr", _) => Locale {
+                date_order: DateOrder::DayMonthYear,
+                true_word: "VRAI",
+                false_word: "FAUX",
+            },
+            ("de", _) => Locale {
+                date_order: DateOrder::DayMonthYear,
+                true_word: "WAHR",
+                false_word: "FALSCH",
+            },
+            ("es", _) => Locale {
+                date_order: DateOrder::DayMonthYear,
+                true_word: "VERDADERO",
+                false_word: "FALSO",
+            },
+            ("ja" | "zh" | "ko" | "sv", _) => Locale::default(),
+            _ => return None,
+        };
+        Some(locale)
+    }
+
+    /// Locale from the environment variables, defaulting to ISO settings.
+    pub fn from_env() -> Locale {
+        ["LC_ALL", "LC_TIME", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| Locale::from_name(&value))
+            .unwrap_or_default()
+    }
+
+    pub fn parse_bool(&self, content: &str) -> Option<bool> {
+        let content = content.trim();
+        if content.eq_ignore_ascii_case("TRUE") || content.eq_ignore_ascii_case(self.true_word) {
+            Some(true)
+        } else if content.eq_ignore_ascii_case("FALSE")
+            || content.eq_ignore_ascii_case(self.false_word)
+        {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// Parse a date into a serial day number.
+    /// Dates starting with a 4 digits year are read as ISO, the others follow the locale order.
+    pub fn parse_date(&self, content: &str) -> Option<i64> {
+        let separator = content.chars().find(|ch| matches!(ch, '-' | '/' | '.'))?;
+        let parts = content.split(separator).collect::<Vec<_>>();
+        let [first, second, third] = parts.as_slice() else {
+            return None;
+        };
+        if !parts
+            .iter()
+            .all(|part| !part.is_empty() && part.chars().all(|ch| ch.is_ascii_digit()))
+        {
+            return None;
+        }
+
+        let (year, month, day) = match (first.len(), self.date_order) {
+            (4, _) => (*first, *second, *third),
+            (_, DateOrder::DayMonthYear) => (*third, *second, *first),
+            (_, DateOrder::MonthDayYear) => (*third, *first, *second),
+            (_, DateOrder::YearMonthDay) => return None,
+        };
+        let year = match (year.len(), year.parse::<i64>().ok()?) {
+            (4, year) => year,
+            (2, year) if year < 30 => 2000 + year,
+            (2, year) => 1900 + year,
+            _ => return None,
+        };
+        let month = month.parse::<u32>().ok()?;
+        let day = day.parse::<u32>().ok()?;
+        if !(1..=12).contains(&month) || day == 0 || day > date::days_in_month(year, month) {
+            return None;
+        }
+        date::civil_to_serial(year, month, day)
+    }
+
+    /// Parse a time such as `14:30` or `14:30:05` into a fraction of days.
+    /// Hours are not limited to a day, so this also reads durations such as `36:00`.
+    pub fn parse_time(&self, content: &str) -> Option<f64> {
+        let parts = content.split(':').collect::<Vec<_>>();
+        let (hours, minutes, seconds) = match parts.as_slice() {
+            [hours, minutes] => (*hours, *minutes, "00"),
+            [hours, minutes, seconds] => (*hours, *minutes, *seconds),
+            _ => return None,
+        };
+        // seconds may have a fraction, as `05.25`
+        let (whole_seconds, fraction) = seconds.split_once('.').unwrap_or((seconds, "0"));
+        if [hours, minutes, whole_seconds, fraction]
+            .iter()
+            .any(|part| part.is_empty() || !part.chars().all(|ch| ch.is_ascii_digit()))
+            || minutes.len() != 2
+            || whole_seconds.len() != 2
+        {
+            return None;
+        }
+        let hours = hours.parse::<u64>().ok()?;
+        let minutes = minutes.parse::<u64>().ok()?;
+        let seconds = seconds.parse::<f64>().ok()?;
+        if minutes >= 60 || !(0.0..60.0).contains(&seconds) {
+            return None;
+        }
+        let minutes = hours.checked_mul(60)?.checked_add(minutes)?;
+        Some((minutes as f64 * 60.0 + seconds) / 86400.0)
+    }
+
+    /// Parse a date followed by a time of day, separated by a space or a `T`.
+    pub fn parse_datetime(&self, content: &str) -> Option<f64> {
+        let (date, time) = content.split_once([' ', 'T'])?;
+        let date = self.parse_date(date)?;
+        let time = self.parse_time(time.trim())?;
+        (time < 1.0).then_some(date as f64 + time)
+    }
+
+    pub fn format_bool(&self, value: bool) -> &'static str {
+        match value {
+            true => self.true_word,
+            false => self.false_word,
+        }
+    }
+
+    /// Date in the locale order. Serials out of the handled range of dates are kept as numbers.
+    pub fn format_date(&self, serial: i64) -> String {
+        let civil = date::serial_to_civil(serial).filter(|_| date::SERIAL_RANGE.contains(&serial));
+        let Some((year, month, day)) = civil else {
+            return serial.to_string();
+        };
+        match self.date_order {
+            DateOrder::DayMonthYear => format!("{day:02}/{month:02}/{year:04}"),
+            DateOrder::MonthDayYear => format!("{month:02}/{day:02}/{year:04}"),
+            DateOrder::YearMonthDay => format!("{year:04}-{month:02}-{day:02}"),
+        }
+    }
+
+    pub fn format_datetime(&self, serial: f64) -> String {
+        let (days, time) = date::split_serial(serial);
+        format!("{} {}", self.format_date(days), format_time(time))
+    }
+}
+
+/// Format a duration in days as hours, minutes and seconds when there are some.
+pub fn format_time(days: f64) -> String {
+    let sign = if days < 0.0 { "-" } else { "" };
+    let seconds = (days.abs() * 86400.0).round() as u64;
+    let (hours, minutes, seconds) = (seconds / 3600, (seconds / 60) % 60, seconds % 60);
+    match seconds {
+        0 => format!("{sign}{hours}:{minutes:02}"),
+        _ => format!("{sign}{hours}:{minutes:02}:{seconds:02}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn french() -> Locale {
+        Locale::from_name("fr_FR.UTF-8").unwrap()
+    }
+
+    #[test]
+    fn finds_locales_from_their_name() {
+        assert_eq!(
+            Locale::from_name("en_US.UTF-8").unwrap().date_order,
+            DateOrder::MonthDayYear
+        );
+        assert_eq!(
+            Locale::from_name("en-GB").unwrap().date_order,
+            DateOrder::DayMonthYear
+        );
+        assert_eq!(french().true_word, "VRAI");
+        assert_eq!(Locale::from_name("C"), Some(Locale::default()));
+        assert_eq!(Locale::from_name("xx_XX"), None);
+    }
+
+    #[test]
+    fn parses_booleans_in_english_and_the_locale_language() {
+        assert_eq!(french().parse_bool("vrai"), Some(true));
+        assert_eq!(french().parse_bool(" Faux "), Some(false));
+        assert_eq!(french().parse_bool("true"), Some(true));
+        assert_eq!(Locale::default().parse_bool("vrai"), None);
+    }
+
+    #[test]
+    fn parses_dates_in_the_locale_order() {
+        let first_of_march = Locale::default().parse_date("2024-03-01").unwrap();
+        assert_eq!(french().parse_date("01/03/2024"), Some(first_of_march));
+        assert_eq!(french().parse_date("2024-03-01"), Some(first_of_march));
+        assert_eq!(french().parse_date("1.3.24"), Some(first_of_march));
+        let us = Locale::from_name("en_US").unwrap();
+        assert_eq!(us.parse_date("03/01/2024"), Some(first_of_march));
+        assert_eq!(Locale::default().parse_date("01/03/2024"), None);
+    }
+
+    #[test]
+    fn rejects_invalid_dates() {
+        for date in [
+            "2024-02-30",
+            "2023-02-29",
+            "2024-13-01",
+            "2024-00-10",
+            "2024-03",
+            "2024-03-01-02",
+            "2024-03-+1",
+            "2024/03-01",
+            "1.3.124",
+        ] {
+            assert_eq!(french().parse_date(date), None, "{date}");
+        }
+        assert!(french().parse_date("2024-02-29").is_some());
+    }
+
+    #[test]
+    fn parses_times_and_durations() {
+        let locale = Locale::default();
+        assert_eq!(locale.parse_time("12:00"), Some(0.5));
+        assert_eq!(locale.parse_time("36:00"), Some(1.5));
+        assert_eq!(locale.parse_time("0:00:30"), Some(30.0 / 86400.0));
+        assert_eq!(locale.parse_time("0:00:30.5"), Some(30.5 / 86400.0));
+    }
+
+    #[test]
+    fn rejects_invalid_times() {
+        for time in [
+            "1:60",
+            "1:5",
+            "1:00:60",
+            "1:00:+5",
+            "1:00:1e1",
+            "1:00:5",
+            "1:00:",
+            "1:00:05.",
+            ":30",
+            "-1:00",
+            "1:00:00:00",
+            "1:30 ",
+        ] {
+            assert_eq!(Locale::default().parse_time(time), None, "{time}");
+        }
+    }
+
+    #[test]
+    fn parses_date_times() {
+        let locale = Locale::default();
+        let date = locale.parse_date("2024-03-01").unwrap() as f64;
+        assert_eq!(locale.parse_datetime("2024-03-01 12:00"), Some(date + 0.5));
+        assert_eq!(locale.parse_datetime("2024-03-01T12:00"), Some(date + 0.5));
+        assert_eq!(locale.parse_datetime("2024-03-01 24:00"), None);
+        assert_eq!(locale.parse_datetime("2024-03-01"), None);
+    }
+
+    #[test]
+    fn formats_values_back_as_they_are_parsed() {
+        for locale in [
+            Locale::default(),
+            french(),
+            Locale::from_name("en_US").unwrap(),
+        ] {
+            let serial = locale.parse_date("2024-03-01").unwrap();
+            assert_eq!(locale.parse_date(&locale.format_date(serial)), Some(serial));
+            let datetime = serial as f64 + 0.75;
+            assert_eq!(
+                locale.parse_datetime(&locale.format_datetime(datetime)),
+                Some(datetime)
+            );
+            assert_eq!(locale.parse_bool(locale.format_bool(false)), Some(false));
+        }
+        assert_eq!(french().format_date(45352), "01/03/2024");
+        assert_eq!(format_time(1.5), "36:00");
+        assert_eq!(format_time(-(90.0 / 86400.0)), "-0:01:30");
+    }
+}
@@ -1,7 +1,10 @@
 mod app;
 mod args;
+mod config;
 mod event;
 mod file;
+mod keymap;
+mod locale;
 mod utils;
 mod widgets;
 
@@ -51,13 +54,24 @@ fn restore_terminal(
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = args::parse_args();
     let mut file = None;
+    let mut locale = locale::Locale::from_env();
+    let mut keep_history = false;
 
     for arg in args.into_iter() {
         match arg {
             args::Args::File(path) => file = Some(path),
+            args::Args::Locale(name) => match locale::Locale::from_name(&name) {
+                Some(from_name) => locale = from_name,
+                None => return Err(format!("Unknown locale \"{name}\"").into()),
+            },
+            args::Args::KeepHistory => keep_history = true,
         }
     }
 
+    let config = config::Config::load()?;
+    let keymap = std::rc::Rc::new(config.keymap);
+    let vim = config.vim;
+
     let mut stdout = std::io::stdout();
     let support_enhancement = matches!(
         crossterm::terminal::supports_keyboard_enhancement(),
@@ -69,8 +83,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     setup_terminal(support_enhancement, &mut stdout)?;
 
     let mut application = match file {
-        Some(file) => app::App::with_file(term_size, &file)?,
-        None => app::App::empty(term_size),
+        Some(file) => app::App::with_file(term_size, &file, locale, keep_history, keymap, vim)?,
+        None => app::App::empty(term_size, keymap),
     };
 
     let result = application.run(terminal);
@@ -1,4 +1,4 @@
-use ratatui::{buffer::Buffer, symbols::line::TOP_LEFT};
+use ratatui::buffer::Buffer;
 
 const HORIZONTAL: char = '─';
 const VERTICAL: char = '│';
@@ -9,6 +9,8 @@ const BOTTOMS: (char, char) = ('╰', '╯');
 pub struct Editor<'a> {
     validate_text: &'a str,
     cancel_text: &'a str,
+    /// Help shown above the key texts, as the arguments of a formula function.
+    hint: &'a str,
 }
 
 impl<'a> Editor<'a> {
@@ -16,15 +18,30 @@ impl<'a> Editor<'a> {
         Editor {
             validate_text,
             cancel_text,
+            hint: "",
         }
     }
 
+    pub fn with_hint(self, hint: &'a str) -> Editor<'a> {
+        Editor { hint, ..self }
+    }
+
     pub fn text_widths(&self) -> usize {
         self.validate_text
             .len()
             .max(self.cancel_text.len())
             .saturating_mul(2)
             .saturating_add(1)
+            .max(self.hint.chars().count())
+    }
+
+    /// Number of lines drawn around the edited text: the borders, the key texts and the hint,
+    /// each after a separator.
+    pub fn frame_height(&self) -> u16 {
+        match self.hint.is_empty() {
+            true => 4,
+            false => 6,
+        }
     }
 
     fn draw_line(
@@ -81,7 +98,7 @@ impl<'a> Editor<'a> {
         self.draw_line(area.as_position(), line_height, area.width, TOPS, buf);
         line_height = line_height.saturating_add(1);
 
-        let sides_height = area.height.saturating_sub(4);
+        let sides_height = area.height.saturating_sub(self.frame_height());
         self.draw_sides(
             area.as_position(),
             line_height,
@@ -91,6 +108,32 @@ impl<'a> Editor<'a> {
         );
         line_height = line_height.saturating_add(sides_height);
 
+        if !self.hint.is_empty() {
+            self.draw_line(area.as_position(), line_height, area.width, CROSSES, buf);
+            line_height = line_height.saturating_add(1);
+            self.draw_sides(
+                area.as_position(),
+                line_height,
+                line_height.saturating_add(1),
+                area.width,
+                buf,
+            );
+            let hint_area = ratatui::layout::Rect::new(
+                area.x.saturating_add(1),
+                area.y.saturating_add(line_height),
+                area.width.saturating_sub(2),
+                1,
+            );
+            buf.set_stringn(
+                hint_area.x,
+                hint_area.y,
+                self.hint,
+                usize::from(hint_area.width),
+                ratatui::style::Color::White,
+            );
+            line_height = line_height.saturating_add(1);
+        }
+
         self.draw_line(area.as_position(), line_height, area.width, CROSSES, buf);
         line_height = line_height.saturating_add(1);
 
@@ -171,9 +214,9 @@ impl<'a> Editor<'a> {
 
 impl<'a> ratatui::widgets::Widget for Editor<'a> {
     fn render(self, area: ratatui::prelude::Rect, buf: &mut ratatui::prelude::Buffer) {
-        match area.height {
-            0..5 => self.draw_only_exterior(area, buf),
-            _ => self.draw_full(area, buf),
+        match area.height > self.frame_height() {
+            false => self.draw_only_exterior(area, buf),
+            true => self.draw_full(area, buf),
         }
     }
 }